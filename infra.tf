@@ -74,7 +74,10 @@ module "beancount" {
     OPENAI_API_KEY                = var.openai_api_key
     STRIPE_API_KEY                = var.stripe_api_key
     STRIPE_WEBHOOK_SIGNING_SECRET = var.stripe_webhook_signing_secret
+    EMAIL_FROM                    = "beancount.bot <jdevries3133@gmail.com>"
+    SMTP_RELAY                    = "smtp.gmail.com"
     SMTP_EMAIL_USERNAME           = "jdevries3133@gmail.com"
     SMTP_EMAIL_PASSWORD           = var.smtp_email_password
+    TRUST_CF_CONNECTING_IP        = "true"
   }
 }
@@ -0,0 +1,12 @@
+create table meal(
+    id serial primary key not null,
+    user_id int not null references users(id) on delete cascade,
+    name text not null,
+    created_at timestamp with time zone not null default now()
+);
+
+create table meal_food(
+    meal_id int not null references meal(id) on delete cascade,
+    food_id int not null references food(id) on delete cascade,
+    primary key (meal_id, food_id)
+);
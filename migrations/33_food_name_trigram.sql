@@ -0,0 +1,14 @@
+-- Fuzzy food search uses `pg_trgm`, which ships with PostgreSQL but needs
+-- superuser rights to create, unless it's a trusted extension (PostgreSQL 13
+-- and later, or a managed database which allow-lists it). If it can't be
+-- created, this migration still succeeds and food search falls back to a
+-- plain `ilike` match; see `src/count_chat/food_search.rs`.
+do $$
+begin
+    create extension if not exists pg_trgm;
+    create index food_name_trgm_idx on food using gin (name gin_trgm_ops);
+exception
+    when insufficient_privilege or undefined_file then
+        raise notice 'pg_trgm is unavailable, food search will use ilike';
+end
+$$;
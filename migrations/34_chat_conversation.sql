@@ -0,0 +1,8 @@
+-- Each user has at most one pending conversation with the LLM, which is
+-- replaced whenever they describe a new food.
+create table chat_conversation(
+    user_id int primary key not null references users(id) on delete cascade,
+    food_name text not null,
+    messages jsonb not null,
+    updated_at timestamp with time zone not null default now()
+);
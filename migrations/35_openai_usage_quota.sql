@@ -0,0 +1,8 @@
+-- Per-user overrides for the default token quotas in `config.rs`.
+create table openai_usage_quota(
+    user_id int primary key not null references users(id) on delete cascade,
+    daily_token_limit int not null,
+    monthly_token_limit int not null
+);
+
+create index openai_usage_created_at on openai_usage (created_at);
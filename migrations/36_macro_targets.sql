@@ -0,0 +1,9 @@
+alter table user_preference add column
+macro_target_unit text not null default 'grams'
+check (macro_target_unit in ('grams', 'percent'));
+
+alter table user_preference add column protein_target int;
+
+alter table user_preference add column carbohydrates_target int;
+
+alter table user_preference add column fat_target int;
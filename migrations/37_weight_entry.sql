@@ -0,0 +1,10 @@
+-- Weights are stored in whichever unit the user chooses to enter them in.
+create table weight_entry(
+    id serial primary key not null,
+    user_id int not null references users(id) on delete cascade,
+    weight double precision not null check (weight > 0),
+    created_at timestamp with time zone not null default now()
+);
+
+create index weight_entry_user_id_created_at
+on weight_entry (user_id, created_at);
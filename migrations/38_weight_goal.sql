@@ -0,0 +1,14 @@
+-- Used with weight history to estimate maintenance calories, and to suggest
+-- a calorie goal.
+create table weight_goal(
+    user_id int primary key not null references users(id) on delete cascade,
+    weight_unit text not null default 'lbs'
+        check (weight_unit in ('lbs', 'kg')),
+    weekly_change double precision not null default 0
+);
+
+-- Each weigh-in remembers its unit, so that it can be converted to the unit
+-- of the user's weight goal. Until now, weights were unitless, so we assume
+-- they were entered in pounds.
+alter table weight_entry add column unit text not null default 'lbs'
+    check (unit in ('lbs', 'kg'));
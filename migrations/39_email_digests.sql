@@ -0,0 +1,15 @@
+alter table user_preference add column
+daily_digest_enabled boolean not null default false;
+
+alter table user_preference add column
+weekly_digest_enabled boolean not null default false;
+
+-- Each digest is claimed here before it's sent, so that it's sent at most
+-- once, even if the scheduler runs in more than one process.
+create table email_digest_sent(
+    user_id int not null references users(id) on delete cascade,
+    kind text not null check (kind in ('daily', 'weekly')),
+    period_start date not null,
+    sent_at timestamp with time zone not null default now(),
+    primary key (user_id, kind, period_start)
+);
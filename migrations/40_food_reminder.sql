@@ -0,0 +1,8 @@
+alter table user_preference add column reminder_time time;
+
+create table food_reminder_sent(
+    user_id int not null references users(id) on delete cascade,
+    reminder_date date not null,
+    sent_at timestamp with time zone not null default now(),
+    primary key (user_id, reminder_date)
+);
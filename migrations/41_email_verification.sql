@@ -0,0 +1,12 @@
+alter table users add column email_verified boolean not null default false;
+
+-- Accounts which registered before verification existed keep working with
+-- password reset. Anonymous accounts have placeholder addresses, so they are
+-- never verified.
+update users set email_verified = true where username not like 'anon-%';
+
+create table email_verification_link(
+    user_id int not null primary key references users(id) on delete cascade,
+    created_at timestamp with time zone not null default now(),
+    slug text not null
+);
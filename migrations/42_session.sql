@@ -0,0 +1,10 @@
+create table session(
+    id text primary key,
+    user_id int not null references users(id) on delete cascade,
+    created_at timestamp with time zone not null default now(),
+    expires_at timestamp with time zone not null,
+    last_seen_at timestamp with time zone not null default now(),
+    user_agent text
+);
+
+create index session_user_id on session(user_id);
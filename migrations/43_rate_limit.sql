@@ -0,0 +1,6 @@
+create table rate_limit(
+    key text primary key,
+    failures int not null default 0,
+    last_failure_at timestamp with time zone not null default now(),
+    locked_until timestamp with time zone
+);
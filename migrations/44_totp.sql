@@ -0,0 +1,19 @@
+-- `enabled` is false while enrollment is pending, until the user confirms
+-- that their authenticator app is producing codes.
+create table totp(
+    user_id int primary key references users(id) on delete cascade,
+    secret bytea not null,
+    enabled boolean not null default false,
+    -- The time step of the last accepted code, so that codes can't be
+    -- replayed.
+    last_used_step bigint,
+    created_at timestamp with time zone not null default now()
+);
+
+create table totp_recovery_code(
+    id serial primary key,
+    user_id int not null references users(id) on delete cascade,
+    digest text not null
+);
+
+create index totp_recovery_code_user_id on totp_recovery_code(user_id);
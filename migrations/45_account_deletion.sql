@@ -0,0 +1,28 @@
+-- Deleting a user should delete all of their data. Migration 13 did this for
+-- `user_preference`, and newer tables cascade already; these are the rest.
+
+alter table balancing_checkpoint drop constraint balancing_checkpoint_user_id_fkey;
+alter table balancing_checkpoint add constraint balancing_checkpoint_user_id_fkey
+foreign key (user_id) references users(id) on delete cascade;
+
+alter table comment drop constraint comment_user_id_fkey;
+alter table comment add constraint comment_user_id_fkey
+foreign key (user_id) references users(id) on delete cascade;
+
+alter table food_eaten_event drop constraint food_eaten_event_user_id_fkey;
+alter table food_eaten_event add constraint food_eaten_event_user_id_fkey
+foreign key (user_id) references users(id) on delete cascade;
+
+alter table openai_usage_user drop constraint openai_usage_user_user_id_fkey;
+alter table openai_usage_user add constraint openai_usage_user_user_id_fkey
+foreign key (user_id) references users(id) on delete cascade;
+
+-- `openai_usage` rows are deleted along with the user, which then clears
+-- them out of this table.
+alter table openai_usage_user drop constraint openai_usage_user_usage_id_fkey;
+alter table openai_usage_user add constraint openai_usage_user_usage_id_fkey
+foreign key (usage_id) references openai_usage(id) on delete cascade;
+
+alter table password_reset_link drop constraint password_reset_link_user_id_fkey;
+alter table password_reset_link add constraint password_reset_link_user_id_fkey
+foreign key (user_id) references users(id) on delete cascade;
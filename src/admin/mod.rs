@@ -0,0 +1,5 @@
+//! Pages which are only available to [crate::config::ADMINISTRATOR_USER_IDS].
+
+mod usage;
+
+pub use usage::{set_usage_quota, usage_dashboard};
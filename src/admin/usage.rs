@@ -0,0 +1,332 @@
+//! OpenAI token usage and estimated cost, per user and per day. Also the
+//! place where administrators can override the default token quotas for
+//! individual users.
+
+use crate::{
+    config::{
+        COMPLETION_TOKEN_COST_PER_MILLION, DAILY_TOKEN_QUOTA,
+        MONTHLY_TOKEN_QUOTA, PROMPT_TOKEN_COST_PER_MILLION,
+    },
+    prelude::*,
+};
+use axum::response::Redirect;
+use chrono::{Days, NaiveDate};
+
+/// The dashboard shows usage over this many days.
+const USAGE_WINDOW_DAYS: u64 = 30;
+
+fn estimate_cost(prompt_tokens: i64, completion_tokens: i64) -> f64 {
+    (prompt_tokens as f64 * PROMPT_TOKEN_COST_PER_MILLION
+        + completion_tokens as f64 * COMPLETION_TOKEN_COST_PER_MILLION)
+        / 1_000_000.0
+}
+
+struct UserUsage {
+    user_id: i32,
+    username: String,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    total_tokens: i64,
+    daily_token_limit: Option<i32>,
+    monthly_token_limit: Option<i32>,
+}
+
+struct DailyUsage {
+    day: NaiveDate,
+    user_count: i64,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    total_tokens: i64,
+}
+
+struct QuotaForm<'a> {
+    usage: &'a UserUsage,
+}
+impl Component for QuotaForm<'_> {
+    fn render(&self) -> String {
+        let route = Route::AdminUsageQuota;
+        let user_id = self.usage.user_id;
+        let daily = self.usage.daily_token_limit.unwrap_or(DAILY_TOKEN_QUOTA);
+        let monthly = self
+            .usage
+            .monthly_token_limit
+            .unwrap_or(MONTHLY_TOKEN_QUOTA);
+        let is_default = if self.usage.daily_token_limit.is_none() {
+            r#"<span class="text-xs">(default)</span>"#
+        } else {
+            ""
+        };
+        format!(
+            r#"
+            <form method="POST" action="{route}" class="flex gap-1 items-center">
+                <input type="hidden" name="user_id" value="{user_id}" />
+                <input
+                    class="w-24 rounded text-sm dark:text-black"
+                    type="number"
+                    min="0"
+                    name="daily_token_limit"
+                    value="{daily}"
+                    aria-label="Daily token limit"
+                />
+                <input
+                    class="w-24 rounded text-sm dark:text-black"
+                    type="number"
+                    min="0"
+                    name="monthly_token_limit"
+                    value="{monthly}"
+                    aria-label="Monthly token limit"
+                />
+                <button
+                    class="dark:bg-emerald-700 dark:hover:bg-emerald-800
+                    bg-emerald-100 hover:bg-emerald-200 p-1 rounded text-sm"
+                >
+                    Save
+                </button>
+                {is_default}
+            </form>
+            "#
+        )
+    }
+}
+
+struct UsageDashboard<'a> {
+    users: &'a [UserUsage],
+    days: &'a [DailyUsage],
+}
+impl Component for UsageDashboard<'_> {
+    fn render(&self) -> String {
+        let user_rows =
+            self.users.iter().fold(String::new(), |mut acc, usage| {
+                let username = clean(&usage.username);
+                let prompt = usage.prompt_tokens;
+                let completion = usage.completion_tokens;
+                let total = usage.total_tokens;
+                let cost = estimate_cost(prompt, completion);
+                let quota_form = QuotaForm { usage }.render();
+                acc.push_str(&format!(
+                    r#"
+                    <tr>
+                        <td>{username}</td>
+                        <td>{prompt}</td>
+                        <td>{completion}</td>
+                        <td>{total}</td>
+                        <td>${cost:.2}</td>
+                        <td>{quota_form}</td>
+                    </tr>
+                    "#
+                ));
+                acc
+            });
+        let day_rows =
+            self.days.iter().fold(String::new(), |mut acc, usage| {
+                let day = usage.day;
+                let user_count = usage.user_count;
+                let prompt = usage.prompt_tokens;
+                let completion = usage.completion_tokens;
+                let total = usage.total_tokens;
+                let cost = estimate_cost(prompt, completion);
+                acc.push_str(&format!(
+                    r#"
+                    <tr>
+                        <td>{day}</td>
+                        <td>{user_count}</td>
+                        <td>{prompt}</td>
+                        <td>{completion}</td>
+                        <td>{total}</td>
+                        <td>${cost:.2}</td>
+                    </tr>
+                    "#
+                ));
+                acc
+            });
+        let prompt_total = self.users.iter().map(|u| u.prompt_tokens).sum();
+        let completion_total =
+            self.users.iter().map(|u| u.completion_tokens).sum();
+        let cost_total = estimate_cost(prompt_total, completion_total);
+        format!(
+            r#"
+            <div class="flex flex-col gap-4 dark:text-slate-200">
+                <h1 class="text-2xl font-extrabold">OpenAI Usage</h1>
+                <p>
+                    Estimated cost over the last {USAGE_WINDOW_DAYS} days:
+                    ${cost_total:.2}
+                </p>
+                <h2 class="text-xl font-bold">By User</h2>
+                <div class="overflow-x-auto">
+                    <table class="text-left">
+                        <thead>
+                            <tr>
+                                <th>User</th>
+                                <th>Prompt Tokens</th>
+                                <th>Completion Tokens</th>
+                                <th>Total Tokens</th>
+                                <th>Cost</th>
+                                <th>Daily / Monthly Limit</th>
+                            </tr>
+                        </thead>
+                        <tbody>{user_rows}</tbody>
+                    </table>
+                </div>
+                <h2 class="text-xl font-bold">By Day (UTC)</h2>
+                <div class="overflow-x-auto">
+                    <table class="text-left">
+                        <thead>
+                            <tr>
+                                <th>Day</th>
+                                <th>Users</th>
+                                <th>Prompt Tokens</th>
+                                <th>Completion Tokens</th>
+                                <th>Total Tokens</th>
+                                <th>Cost</th>
+                            </tr>
+                        </thead>
+                        <tbody>{day_rows}</tbody>
+                    </table>
+                </div>
+            </div>
+            "#
+        )
+    }
+}
+
+async fn get_user_usage(
+    db: impl PgExecutor<'_>,
+    since: DateTime<Utc>,
+) -> Aresult<Vec<UserUsage>> {
+    Ok(query_as!(
+        UserUsage,
+        r#"select
+            u.id user_id,
+            u.username,
+            sum(ou.prompt_tokens) "prompt_tokens!",
+            sum(ou.completion_tokens) "completion_tokens!",
+            sum(ou.total_tokens) "total_tokens!",
+            q.daily_token_limit "daily_token_limit?",
+            q.monthly_token_limit "monthly_token_limit?"
+        from openai_usage ou
+        join openai_usage_user ouu on ouu.usage_id = ou.id
+        join users u on u.id = ouu.user_id
+        left join openai_usage_quota q on q.user_id = u.id
+        where ou.created_at >= $1
+        group by
+            u.id,
+            u.username,
+            q.daily_token_limit,
+            q.monthly_token_limit
+        order by sum(ou.total_tokens) desc"#,
+        since
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+async fn get_daily_usage(
+    db: impl PgExecutor<'_>,
+    since: DateTime<Utc>,
+) -> Aresult<Vec<DailyUsage>> {
+    Ok(query_as!(
+        DailyUsage,
+        r#"select
+            date(ou.created_at at time zone 'UTC') "day!",
+            count(distinct ouu.user_id) "user_count!",
+            sum(ou.prompt_tokens) "prompt_tokens!",
+            sum(ou.completion_tokens) "completion_tokens!",
+            sum(ou.total_tokens) "total_tokens!"
+        from openai_usage ou
+        join openai_usage_user ouu on ouu.usage_id = ou.id
+        where ou.created_at >= $1
+        group by 1
+        order by 1 desc"#,
+        since
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+pub async fn usage_dashboard(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "usage dashboard")?;
+    if !session.is_administrator() {
+        return Err(ServerError::forbidden(
+            "non-admin tried to view usage dashboard",
+        ));
+    }
+    let since = utc_now()
+        .checked_sub_days(Days::new(USAGE_WINDOW_DAYS))
+        .ok_or(Error::msg("usage window is out of range"))?;
+    let users = get_user_usage(&db, since).await?;
+    let days = get_daily_usage(&db, since).await?;
+    Ok(Page {
+        title: "OpenAI Usage",
+        children: &PageContainer {
+            children: &UsageDashboard {
+                users: &users,
+                days: &days,
+            },
+        },
+    }
+    .render())
+}
+
+#[derive(Deserialize)]
+pub struct QuotaPayload {
+    user_id: i32,
+    daily_token_limit: i32,
+    monthly_token_limit: i32,
+}
+
+pub async fn set_usage_quota(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<QuotaPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "set usage quota")?;
+    if !session.is_administrator() {
+        return Err(ServerError::forbidden(
+            "non-admin tried to set usage quota",
+        ));
+    }
+    if form.daily_token_limit < 0 || form.monthly_token_limit < 0 {
+        return Err(ServerError::bad_request(
+            "negative token limit",
+            Some("Token limits cannot be negative".into()),
+        ));
+    }
+    query!(
+        "insert into openai_usage_quota
+            (user_id, daily_token_limit, monthly_token_limit)
+        values ($1, $2, $3)
+        on conflict (user_id)
+        do update set
+            daily_token_limit = excluded.daily_token_limit,
+            monthly_token_limit = excluded.monthly_token_limit",
+        form.user_id,
+        form.daily_token_limit,
+        form.monthly_token_limit
+    )
+    .execute(&db)
+    .await?;
+    Ok(Redirect::to(&Route::AdminUsage.as_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_estimate_cost() {
+        assert_eq!(estimate_cost(0, 0), 0.0);
+        assert_eq!(estimate_cost(1_000_000, 0), PROMPT_TOKEN_COST_PER_MILLION);
+        assert_eq!(
+            estimate_cost(0, 1_000_000),
+            COMPLETION_TOKEN_COST_PER_MILLION
+        );
+        assert_eq!(
+            estimate_cost(2_000_000, 2_000_000),
+            2.0 * (PROMPT_TOKEN_COST_PER_MILLION
+                + COMPLETION_TOKEN_COST_PER_MILLION)
+        );
+    }
+}
@@ -1,7 +1,6 @@
 use super::{pw::hash_new, register::create_user};
 use crate::{config, htmx, preferences::save_user_preference, prelude::*};
 use axum::extract::Query;
-use chrono::Days;
 use regex::Regex;
 use serde_urlencoded::to_string;
 use uuid::Uuid;
@@ -48,7 +47,7 @@ pub struct AnonParams {
 }
 
 pub async fn init_anon(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Query(AnonParams { next }): Query<AnonParams>,
     Form(AnonForm { timezone }): Form<AnonForm>,
@@ -59,7 +58,7 @@ pub async fn init_anon(
             let uuid = Uuid::new_v4().to_string();
             let username = format!("anon-{uuid}");
             let email = format!("anon-{uuid}@example.com");
-            let password = hash_new(&Uuid::new_v4().to_string());
+            let password = hash_new(&Uuid::new_v4().to_string()).await?;
             let user = create_user(
                 &db,
                 username,
@@ -74,17 +73,13 @@ pub async fn init_anon(
                 ..Default::default()
             };
             save_user_preference(&db, user.id, &preferences).await?;
-            Session {
-                user_id: user.id,
-                username: user.username,
-                // I'm just going to fake a distant future created date, which
-                // creates a long-lived token. This user won't know their
-                // password until they convert into a non-anon registered
-                // user, so we don't want to surprise log them out.
-                created_at: utc_now().checked_add_days(Days::new(365)).expect(
-                    "can add 1 year to the current date w/o overflowing",
-                ),
-            }
+            Session::create(
+                &db,
+                user.id,
+                user.username,
+                config::ANON_SESSION_EXPIRY_TIME_DAYS,
+            )
+            .await?
         }
     };
     let response_headers = HeaderMap::new();
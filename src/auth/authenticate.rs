@@ -3,9 +3,9 @@
 //! authenticating a user with the provided credentials.
 
 use super::{pw, Session};
-use crate::{chrono_utils::utc_now, db_ops, db_ops::GetModel, models};
+use crate::{config, db_ops, db_ops::GetModel, models};
 use anyhow::{bail, Result};
-use sqlx::{postgres::PgPool, query_as};
+use sqlx::{postgres::PgPool, query, query_as};
 
 /// We are a bit losey goosey on the identifier for a better user experience.
 /// I'm fairly convinced this is not a security issue. If we consider a
@@ -21,13 +21,37 @@ pub async fn authenticate(
     username_or_email: &str,
     password: &str,
 ) -> Result<Session> {
-    let user = models::User::get(
+    let user = check_password(db, username_or_email, password).await?;
+    Session::create(
+        db,
+        user.id,
+        user.username,
+        config::SESSION_EXPIRY_TIME_DAYS,
+    )
+    .await
+}
+
+/// The first half of [authenticate], for callers which need to do more (like
+/// ask for a two-factor code) before starting a session.
+pub async fn check_password(
+    db: &PgPool,
+    username_or_email: &str,
+    password: &str,
+) -> Result<models::User> {
+    let user = match models::User::get(
         db,
         &db_ops::GetUserQuery {
             identifier: db_ops::UserIdentifer::Identifier(username_or_email),
         },
     )
-    .await?;
+    .await
+    {
+        Ok(user) => user,
+        Err(e) => {
+            pw::check_dummy(password).await;
+            return Err(e);
+        }
+    };
     // This is kept out of the `User` model because I don't want to leak
     // password digests in autnentication tokens. The entire User object is
     // serialized into the user's session token, which is signed but not
@@ -40,12 +64,21 @@ pub async fn authenticate(
     .fetch_one(db)
     .await?;
 
-    if pw::check(password, &truth).is_ok() {
-        Ok(Session {
-            user_id: user.id,
-            username: user.username.clone(),
-            created_at: utc_now(),
-        })
+    if pw::check(password, &truth).await.is_ok() {
+        // Legacy SHA-256 digests are upgraded in place, now that we have the
+        // plaintext password.
+        if pw::needs_rehash(&truth) {
+            let new = pw::hash_new(password).await?;
+            query!(
+                "update users set salt = $1, digest = $2 where id = $3",
+                new.salt,
+                new.digest,
+                user.id
+            )
+            .execute(db)
+            .await?;
+        }
+        Ok(user)
     } else {
         bail!("wrong password")
     }
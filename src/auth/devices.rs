@@ -0,0 +1,151 @@
+//! Lists the user's active sessions, and lets them log out of any of them.
+
+use super::session_store::{revoke, revoke_all};
+use crate::{htmx, prelude::*};
+
+struct ActiveSession {
+    id: String,
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    user_agent: Option<String>,
+}
+
+struct DevicesPage<'a> {
+    sessions: &'a [ActiveSession],
+    current_session_id: &'a str,
+    timezone: Tz,
+}
+impl Component for DevicesPage<'_> {
+    fn render(&self) -> String {
+        let home = Route::UserHome;
+        let revoke_all = Route::RevokeAllSessions;
+        let rows = self.sessions.iter().fold(String::new(), |mut acc, s| {
+            let format = |dt: DateTime<Utc>| {
+                dt.with_timezone(&self.timezone)
+                    .format("%b %e, %Y %l:%M %p")
+                    .to_string()
+            };
+            let created_at = format(s.created_at);
+            let last_seen_at = format(s.last_seen_at);
+            let user_agent = s
+                .user_agent
+                .as_deref()
+                .map_or("Unknown device".into(), clean);
+            let current = if s.id == self.current_session_id {
+                r#"<span class="text-xs bg-green-100 text-black rounded p-1">This device</span>"#
+            } else {
+                ""
+            };
+            let revoke = Route::RevokeSession(Some(s.id.clone()));
+            acc.push_str(&format!(
+                r#"
+                <tr>
+                    <td class="pr-4 py-2">
+                        <p class="text-sm break-all">{user_agent}</p>
+                        {current}
+                    </td>
+                    <td class="pr-4 py-2 text-sm">{created_at}</td>
+                    <td class="pr-4 py-2 text-sm">{last_seen_at}</td>
+                    <td class="py-2">
+                        <button
+                            hx-delete="{revoke}"
+                            hx-swap="outerHTML"
+                            hx-target="closest tr"
+                            class="text-sm bg-red-100 hover:bg-red-200 rounded
+                            p-1 text-black"
+                        >
+                            Log out
+                        </button>
+                    </td>
+                </tr>
+                "#
+            ));
+            acc
+        });
+        format!(
+            r#"
+            <div class="flex flex-col gap-2">
+                <a class="link" href="{home}">Back to dashboard</a>
+                <h1 class="text-2xl font-extrabold">Devices</h1>
+                <p class="max-w-prose">
+                    These are the browsers which are logged in to your
+                    account. Changing your password logs out everywhere.
+                </p>
+                <table class="text-left">
+                    <thead>
+                        <tr>
+                            <th class="pr-4">Device</th>
+                            <th class="pr-4">Logged in</th>
+                            <th class="pr-4">Last active</th>
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody>{rows}</tbody>
+                </table>
+                <button
+                    hx-post="{revoke_all}"
+                    hx-confirm="Log out of every device, including this one?"
+                    class="self-start bg-red-100 hover:bg-red-200 rounded p-1
+                    text-black"
+                >
+                    Log out everywhere
+                </button>
+            </div>
+            "#
+        )
+    }
+}
+
+pub async fn devices(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "devices")?;
+    let preferences = session.get_preferences(&db).await?;
+    let sessions = query_as!(
+        ActiveSession,
+        "select id, created_at, last_seen_at, user_agent
+        from session
+        where user_id = $1 and expires_at > now()
+        order by last_seen_at desc",
+        session.user_id
+    )
+    .fetch_all(&db)
+    .await?;
+    Ok(Page {
+        title: "Devices",
+        children: &PageContainer {
+            children: &DevicesPage {
+                sessions: &sessions,
+                current_session_id: &session.id,
+                timezone: preferences.timezone,
+            },
+        },
+    }
+    .render())
+}
+
+/// Logging out of the current session sends the user to the login page;
+/// otherwise, the session's row is removed from the table.
+pub async fn revoke_session(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "revoke session")?;
+    revoke(&db, session.user_id, &id).await?;
+    if id == session.id {
+        Ok(htmx::redirect(HeaderMap::new(), &Route::Login.as_string()))
+    } else {
+        Ok(HeaderMap::new())
+    }
+}
+
+pub async fn revoke_all_sessions(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "revoke all sessions")?;
+    revoke_all(&db, session.user_id).await?;
+    Ok(htmx::redirect(HeaderMap::new(), &Route::Login.as_string()))
+}
@@ -1,6 +1,14 @@
-use super::authenticate::authenticate;
+use super::{
+    authenticate::check_password,
+    rate_limit::PasswordAccepted,
+    session_store, totp,
+    two_factor::{login_token, TwoFactorLoginForm},
+};
 use crate::{auth::InitAnonNextRoute, config, htmx, prelude::*};
-use axum::http::{HeaderValue, StatusCode};
+use axum::{
+    http::{HeaderValue, StatusCode},
+    response::Response,
+};
 
 pub struct LoginForm;
 impl Component for LoginForm {
@@ -124,34 +132,32 @@ pub async fn get_login_form(
         .render()
     };
     Ok(match session {
-        Some(session) => {
-            if utc_now()
-                .signed_duration_since(session.created_at)
-                .num_days()
-                < config::SESSION_EXPIRY_TIME_DAYS
-            {
-                // The user is already authenticated, let's redirect them to the
-                // user homepage.
-                let mut headers = HeaderMap::new();
-                headers.insert(
-                    "Location",
-                    HeaderValue::from_str(&Route::UserHome.as_string())?,
-                );
-                headers.insert(
-                    "Hx-Redirect",
-                    HeaderValue::from_str(&Route::UserHome.as_string())?,
-                );
+        Some(_) => {
+            // The user is already authenticated, let's redirect them to the
+            // user homepage.
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "Location",
+                HeaderValue::from_str(&Route::UserHome.as_string())?,
+            );
+            headers.insert(
+                "Hx-Redirect",
+                HeaderValue::from_str(&Route::UserHome.as_string())?,
+            );
 
-                (StatusCode::SEE_OTHER, headers).into_response()
-            } else {
-                form.into_response()
-            }
+            (StatusCode::SEE_OTHER, headers).into_response()
         }
         None => form.into_response(),
     })
 }
 
-pub async fn logout() -> Result<impl IntoResponse, ServerError> {
+pub async fn logout(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    if let Some(session) = Session::from_headers(&headers) {
+        session_store::revoke(&db, session.user_id, &session.id).await?;
+    }
     let login = Route::Login;
     let mut headers = HeaderMap::new();
     headers.insert(
@@ -170,17 +176,38 @@ pub struct LoginFormPayload {
     password: String,
 }
 
+/// Users with two-factor authentication get [TwoFactorLoginForm] after
+/// entering the right password, and only get a session once they submit it.
 pub async fn handle_login(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     Form(form): Form<LoginFormPayload>,
-) -> Result<impl IntoResponse, ServerError> {
-    let session = authenticate(&db, &form.identifier, &form.password).await;
+) -> Result<Response, ServerError> {
+    let user = check_password(&db, &form.identifier, &form.password).await;
     let headers = HeaderMap::new();
-    if let Ok(session) = session {
+    if let Ok(user) = user {
+        if totp::is_enabled(&db, user.id).await? {
+            let token = login_token(user.id);
+            let mut response = TwoFactorLoginForm {
+                token: &token,
+                identifier: &form.identifier,
+                error: None,
+            }
+            .render()
+            .into_response();
+            response.extensions_mut().insert(PasswordAccepted);
+            return Ok(response);
+        }
+        let session = Session::create(
+            &db,
+            user.id,
+            user.username,
+            config::SESSION_EXPIRY_TIME_DAYS,
+        )
+        .await?;
         let homepage = Route::UserHome.as_string();
         let headers = session.update_headers(headers);
         let headers = htmx::redirect(headers, &homepage);
-        Ok((headers, "OK".to_string()))
+        Ok((headers, "OK".to_string()).into_response())
     } else {
         let login_route = Route::Login;
         Ok((
@@ -188,6 +215,7 @@ pub async fn handle_login(
             format!(
                 r#"<p hx-trigger="load delay:1s" hx-get="{login_route}">Invalid login credentials.</p>"#
             ),
-        ))
+        )
+            .into_response())
     }
 }
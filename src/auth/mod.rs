@@ -1,14 +1,21 @@
 mod anon;
 mod authenticate;
-mod crypto;
+pub mod crypto;
+mod devices;
 mod login;
 mod pw;
+pub mod rate_limit;
 mod register;
 mod reset;
 mod session;
+pub mod session_store;
+pub mod totp;
+mod two_factor;
+mod verify;
 
 pub use anon::{init_anon, is_anon, InitAnonNextRoute};
-pub use authenticate::authenticate;
+pub use authenticate::{authenticate, check_password};
+pub use devices::{devices, revoke_all_sessions, revoke_session};
 pub use login::{get_login_form, handle_login, logout};
 pub use register::{get_registration_form, handle_registration, RegisterForm};
 pub use reset::{
@@ -16,3 +23,11 @@ pub use reset::{
     handle_pw_reset_request,
 };
 pub use session::Session;
+pub use two_factor::{
+    confirm_two_factor, disable_two_factor, handle_login_two_factor,
+    start_two_factor_enrollment, two_factor_settings,
+};
+pub use verify::{
+    resend_verification_email, send_verification_email, verify_email,
+    VerifyEmailBanner,
+};
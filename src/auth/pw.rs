@@ -1,23 +1,37 @@
-//! Methods for handling passwords. Passwords are combined with random salt and
-//! hashed using [sha2::Sha256]. Only the salt and resultant digest is then
-//! persisted to the `users` table in the database. Salt comes from UUIDv4,
-//! which
-//! [is not a secure source of salt](https://stackoverflow.com/a/3596660/13262536),
-//! so please use long and secure passwords, and PRs to fix this are very
-//! welcome!
+//! Methods for handling passwords. Passwords are hashed with Argon2id, and the
+//! resulting [PHC string](https://github.com/P-H-C/phc-string-format) (which
+//! includes the salt and hashing parameters) is persisted to `users.digest`.
+//!
+//! Passwords used to be combined with a random salt and hashed once using
+//! [sha2::Sha256], with the salt stored in `users.salt`. Those legacy digests
+//! are still accepted by [check], and [needs_rehash] identifies them so that
+//! they can be replaced with an Argon2id hash after the user's next
+//! successful login.
+//!
+//! Argon2id is deliberately slow, so hashing and verification run on
+//! tokio's blocking thread pool instead of stalling the async workers.
 
 use anyhow::{bail, Result};
+use argon2::{
+    password_hash::{
+        rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier,
+        SaltString,
+    },
+    Argon2,
+};
 use base64::{engine::general_purpose, Engine};
 use sha2::{Digest, Sha256};
-use uuid::Uuid;
+use tokio::task::spawn_blocking;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct HashedPw {
+    /// Only used by legacy SHA-256 digests. Argon2id hashes keep their salt
+    /// inside the PHC string, so this is empty for them.
     pub salt: String,
     pub digest: String,
 }
 
-fn hash(pw: &str, salt: &str) -> HashedPw {
+fn legacy_hash(pw: &str, salt: &str) -> String {
     let mut pw_digest = salt.to_string();
     pw_digest.push_str(pw);
 
@@ -25,24 +39,32 @@ fn hash(pw: &str, salt: &str) -> HashedPw {
     hash.update(&pw_digest);
 
     let digest = hash.finalize().to_vec();
-    let b64_digest = general_purpose::STANDARD.encode(digest);
+    general_purpose::STANDARD.encode(digest)
+}
 
-    if b64_digest.len() > 255 {
-        panic!("password is too long");
-    }
+/// Hash a new password; random salt is generated.
+pub async fn hash_new(pw: &str) -> Result<HashedPw> {
+    let pw = pw.to_string();
+    Ok(spawn_blocking(move || hash_new_blocking(&pw)).await?)
+}
+
+fn hash_new_blocking(pw: &str) -> HashedPw {
+    let salt = SaltString::generate(&mut OsRng);
+    let digest = Argon2::default()
+        .hash_password(pw.as_bytes(), &salt)
+        .expect("can hash password with default argon2 params")
+        .to_string();
 
     HashedPw {
-        salt: salt.to_string(),
-        digest: b64_digest,
+        salt: String::new(),
+        digest,
     }
 }
 
-/// Hash a new password; random salt is generated.
-pub fn hash_new(pw: &str) -> HashedPw {
-    let salt = Uuid::new_v4();
-    let salt_str = salt.clone().to_string();
-
-    hash(pw, &salt_str)
+/// Legacy SHA-256 digests are base64, which never includes `$`, and PHC
+/// strings always begin with `$`.
+fn is_legacy(truth: &HashedPw) -> bool {
+    !truth.digest.starts_with('$')
 }
 
 /// Check if a user's password `pw` matches a HashedPw from the database
@@ -50,36 +72,93 @@ pub fn hash_new(pw: &str) -> HashedPw {
 ///
 /// # Bugs
 ///
-/// This does not use a cryptographically secure string comparison, and may
-/// therefore be vulnerable to timing attack.
-pub fn check(pw: &str, truth: &HashedPw) -> Result<()> {
-    let user_input_digest = hash(pw, &truth.salt).digest;
-
-    if user_input_digest == truth.digest {
-        Ok(())
-    } else {
+/// Legacy digests are not compared in constant time, and may therefore be
+/// vulnerable to timing attack. They'll go away as users log in.
+pub async fn check(pw: &str, truth: &HashedPw) -> Result<()> {
+    let pw = pw.to_string();
+    let truth = truth.clone();
+    spawn_blocking(move || check_blocking(&pw, &truth)).await?
+}
+
+fn check_blocking(pw: &str, truth: &HashedPw) -> Result<()> {
+    if is_legacy(truth) {
+        if legacy_hash(pw, &truth.salt) == truth.digest {
+            return Ok(());
+        }
         bail!("passwords do not match")
     }
+    let hash = PasswordHash::new(&truth.digest)
+        .map_err(|e| anyhow::Error::msg(format!("invalid digest: {e}")))?;
+    match Argon2::default().verify_password(pw.as_bytes(), &hash) {
+        Ok(()) => Ok(()),
+        Err(_) => bail!("passwords do not match"),
+    }
+}
+
+/// An Argon2id hash of a password that nobody has, with the same parameters
+/// as [hash_new].
+const DUMMY_DIGEST: &str = "$argon2id$v=19$m=19456,t=2,p=1$aLA5xa1KwpjNNg1OIY68Gw$w1cfGpnbXKr0ZnWDkxBU9r/D3QZP15ynzPqNURElxkA";
+
+/// Does the same work as [check], for a login to an account which doesn't
+/// exist. Otherwise, the response time would tell whether an account
+/// exists, since only real accounts pay for Argon2id.
+pub async fn check_dummy(pw: &str) {
+    let truth = HashedPw {
+        salt: String::new(),
+        digest: DUMMY_DIGEST.into(),
+    };
+    let _ = check(pw, &truth).await;
+}
+
+/// True if the digest should be replaced with one from [hash_new], after the
+/// password has passed [check].
+pub fn needs_rehash(truth: &HashedPw) -> bool {
+    is_legacy(truth)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
-    #[test]
-    fn test_can_hash_new_pw() {
-        hash_new("heyoooo");
+    #[tokio::test]
+    async fn test_new_hash_is_argon2id() {
+        let hash = hash_new("heyoooo").await.unwrap();
+        assert!(hash.digest.starts_with("$argon2id$"));
+        assert!(!needs_rehash(&hash));
     }
 
-    #[test]
-    fn test_can_check_old_pw() {
-        let hash = hash_new("heyoooo");
-        check("heyoooo", &hash).unwrap();
+    #[tokio::test]
+    async fn test_can_check_old_pw() {
+        let hash = hash_new("heyoooo").await.unwrap();
+        check("heyoooo", &hash).await.unwrap();
+        assert!(check("nopeeee", &hash).await.is_err());
     }
 
     #[test]
-    fn test_utf8_support() {
-        let hash = hash_new("heyoooo 🥹");
-        check("heyoooo 🥹", &hash).unwrap();
+    fn test_dummy_digest_matches_new_hashes() {
+        let dummy = PasswordHash::new(DUMMY_DIGEST).unwrap();
+        let digest = hash_new_blocking("heyoooo").digest;
+        let new = PasswordHash::new(&digest).unwrap();
+        assert_eq!(dummy.algorithm, new.algorithm);
+        assert_eq!(dummy.version, new.version);
+        assert_eq!(dummy.params, new.params);
+    }
+
+    #[tokio::test]
+    async fn test_utf8_support() {
+        let hash = hash_new("heyoooo 🥹").await.unwrap();
+        check("heyoooo 🥹", &hash).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_legacy_digest() {
+        let salt = "4b9f3e2e-1f6a-4f0e-9a4e-2f9b8d6c1a7e";
+        let truth = HashedPw {
+            salt: salt.into(),
+            digest: legacy_hash("heyoooo", salt),
+        };
+        assert!(needs_rehash(&truth));
+        check("heyoooo", &truth).await.unwrap();
+        assert!(check("nopeeee", &truth).await.is_err());
     }
 }
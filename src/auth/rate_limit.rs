@@ -0,0 +1,292 @@
+//! Brute-force protection for the login and password reset forms. Failures
+//! are counted in the `rate_limit` table for both the client's IP address
+//! and the username or email that was submitted, so that guessing can't be
+//! spread across many accounts or many addresses. After
+//! [config::RATE_LIMIT_FREE_ATTEMPTS], each failure doubles the lockout.
+//!
+//! Each attempt is [reserve]d before the form is handled, and counts as a
+//! failure unless it's [refund]ed afterwards. Otherwise, a burst of
+//! concurrent requests could all get in before the first one failed.
+//!
+//! See [crate::middleware::rate_limit], which applies this to routes.
+
+use crate::{config, prelude::*};
+use axum::{extract::ConnectInfo, http::request::Parts};
+use std::{collections::HashMap, env, net::SocketAddr, sync::LazyLock};
+
+/// Added to the response when the password was right, but the user still
+/// needs to enter a two-factor code. That's neither a success nor a failure.
+#[derive(Clone, Copy)]
+pub struct PasswordAccepted;
+
+/// Added to the response when a form which doesn't start a session, like
+/// turning off two-factor authentication, succeeds.
+#[derive(Clone, Copy)]
+pub struct AttemptSucceeded;
+
+/// How long to lock out a key after its `failures`th failure, if at all.
+fn lockout_minutes(failures: i32) -> Option<i64> {
+    let extra = failures - config::RATE_LIMIT_FREE_ATTEMPTS;
+    (extra >= 0).then(|| {
+        (1_i64 << extra.min(32)).min(config::RATE_LIMIT_MAX_LOCKOUT_MINUTES)
+    })
+}
+
+/// Lockout for the `n`th failure is at index `n - 1`, and `0` means no
+/// lockout. Failures past the end of the schedule get the last entry.
+fn lockout_schedule() -> Vec<i32> {
+    let mut schedule = vec![];
+    for failures in 1.. {
+        let minutes = lockout_minutes(failures);
+        schedule.push(minutes.unwrap_or(0) as i32);
+        if minutes == Some(config::RATE_LIMIT_MAX_LOCKOUT_MINUTES) {
+            return schedule;
+        }
+    }
+    unreachable!("lockout reaches the maximum")
+}
+
+/// Anyone can send a `CF-Connecting-IP` header, so it's only trusted when
+/// `TRUST_CF_CONNECTING_IP=true`, which should only be set when the server
+/// can't be reached except through Cloudflare.
+static TRUST_CF_CONNECTING_IP: LazyLock<bool> = LazyLock::new(|| {
+    env::var("TRUST_CF_CONNECTING_IP").is_ok_and(|v| v == "true")
+});
+
+/// The client's address from Cloudflare, if we [TRUST_CF_CONNECTING_IP].
+/// Otherwise, the peer address of the connection.
+pub fn client_ip(parts: &Parts) -> Option<String> {
+    client_ip_with(parts, *TRUST_CF_CONNECTING_IP)
+}
+
+fn client_ip_with(parts: &Parts, trust_cloudflare: bool) -> Option<String> {
+    let cloudflare = || {
+        parts
+            .headers
+            .get("CF-Connecting-IP")
+            .and_then(|ip| ip.to_str().ok())
+            .map(|ip| ip.to_owned())
+    };
+    let peer = || {
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+    };
+    if trust_cloudflare {
+        cloudflare().or_else(peer)
+    } else {
+        peer()
+    }
+}
+
+/// Pulls the username or email out of a login or password reset form.
+pub fn form_identifier(body: &[u8]) -> Option<String> {
+    let form: HashMap<String, String> =
+        serde_urlencoded::from_bytes(body).ok()?;
+    form.get("identifier")
+        .or_else(|| form.get("email"))
+        .cloned()
+}
+
+pub fn identifier_key(identifier: &str) -> String {
+    format!("identifier:{}", identifier.trim().to_lowercase())
+}
+
+pub fn keys(ip: Option<&str>, identifier: Option<&str>) -> Vec<String> {
+    ip.map(|ip| format!("ip:{ip}"))
+        .into_iter()
+        .chain(identifier.map(identifier_key))
+        .collect()
+}
+
+/// When the longest outstanding lockout for any of the `keys` ends.
+pub async fn locked_until(
+    db: &PgPool,
+    keys: &[String],
+) -> Aresult<Option<DateTime<Utc>>> {
+    Ok(query!(
+        "select max(locked_until) locked_until from rate_limit
+        where key = any($1) and locked_until > now()",
+        keys
+    )
+    .fetch_one(db)
+    .await?
+    .locked_until)
+}
+
+/// An attempt which was counted by [reserve].
+pub struct Reservation {
+    key: String,
+    /// The lockout which this attempt started, if any.
+    locked_until: Option<DateTime<Utc>>,
+}
+
+/// Count an attempt against each of the `keys`, unless any of them is locked
+/// out, in which case [None] is returned and nothing is counted. Keys which
+/// are locked out are skipped by the `where` clause, so they're missing
+/// from the result; checking and counting in one statement is what keeps
+/// concurrent attempts from getting past the limit.
+pub async fn reserve(
+    db: &PgPool,
+    keys: &[String],
+) -> Aresult<Option<Vec<Reservation>>> {
+    let reservations = query_as!(
+        Reservation,
+        "insert into rate_limit (key, failures, locked_until)
+        select
+            key,
+            1,
+            now() + make_interval(mins => nullif(($3::int[])[1], 0))
+        from unnest($1::text[]) key
+        on conflict (key) do update set
+            failures = case
+                when rate_limit.last_failure_at
+                    < now() - make_interval(hours => $2)
+                then 1
+                else rate_limit.failures + 1
+            end,
+            last_failure_at = now(),
+            locked_until = now() + make_interval(mins => nullif(
+                ($3::int[])[least(
+                    case
+                        when rate_limit.last_failure_at
+                            < now() - make_interval(hours => $2)
+                        then 1
+                        else rate_limit.failures + 1
+                    end,
+                    cardinality($3::int[])
+                )],
+                0
+            ))
+        where
+            rate_limit.locked_until is null
+            or rate_limit.locked_until <= now()
+        returning key, locked_until",
+        keys,
+        config::RATE_LIMIT_RESET_HOURS as i32,
+        &lockout_schedule()
+    )
+    .fetch_all(db)
+    .await?;
+    if reservations.len() < keys.len() {
+        refund(db, &reservations).await?;
+        return Ok(None);
+    }
+    Ok(Some(reservations))
+}
+
+/// Take back attempts which turned out not to be failures. If an attempt
+/// started a lockout, that's lifted too, unless another failure has since
+/// replaced it.
+pub async fn refund(db: &PgPool, reservations: &[Reservation]) -> Aresult<()> {
+    for reservation in reservations {
+        query!(
+            "update rate_limit set
+                failures = greatest(failures - 1, 0),
+                locked_until = case
+                    when locked_until = $2 then null
+                    else locked_until
+                end
+            where key = $1",
+            reservation.key,
+            reservation.locked_until
+        )
+        .execute(db)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Forget the failures for a key. We do this for a username after a
+/// successful login, but not for the IP address; otherwise, someone with one
+/// account could reset their own counter between guesses at another.
+pub async fn clear(db: &PgPool, key: &str) -> Aresult<()> {
+    query!("delete from rate_limit where key = $1", key)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+pub struct LockoutNotice {
+    pub locked_until: DateTime<Utc>,
+}
+impl Component for LockoutNotice {
+    fn render(&self) -> String {
+        let seconds = self
+            .locked_until
+            .signed_duration_since(utc_now())
+            .num_seconds();
+        let minutes = ((seconds + 59) / 60).max(1);
+        let unit = if minutes == 1 { "minute" } else { "minutes" };
+        format!(
+            r#"
+            <div class="flex flex-col gap-2 max-w-prose rounded p-2 bg-red-100 text-black">
+                <p class="font-bold">Too many attempts</p>
+                <p>
+                    For your security, please wait {minutes} {unit} before
+                    trying again.
+                </p>
+            </div>
+            "#
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lockout_minutes() {
+        assert_eq!(lockout_minutes(1), None);
+        assert_eq!(lockout_minutes(4), None);
+        assert_eq!(lockout_minutes(5), Some(1));
+        assert_eq!(lockout_minutes(6), Some(2));
+        assert_eq!(lockout_minutes(8), Some(8));
+        assert_eq!(lockout_minutes(11), Some(60));
+        assert_eq!(lockout_minutes(1000), Some(60));
+    }
+
+    #[test]
+    fn test_lockout_schedule() {
+        assert_eq!(
+            lockout_schedule(),
+            vec![0, 0, 0, 0, 1, 2, 4, 8, 16, 32, 60]
+        );
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let request = axum::http::Request::builder()
+            .header("CF-Connecting-IP", "1.2.3.4")
+            .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 8000))))
+            .body(())
+            .expect("valid request");
+        let (parts, _) = request.into_parts();
+        assert_eq!(client_ip_with(&parts, true), Some("1.2.3.4".into()));
+        assert_eq!(client_ip_with(&parts, false), Some("10.0.0.1".into()));
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(
+            keys(Some("1.2.3.4"), Some(" Jack@Example.com ")),
+            vec!["ip:1.2.3.4", "identifier:jack@example.com"]
+        );
+        assert_eq!(keys(None, Some("jack")), vec!["identifier:jack"]);
+    }
+
+    #[test]
+    fn test_form_identifier() {
+        assert_eq!(
+            form_identifier(b"identifier=jack&password=hunter2"),
+            Some("jack".into())
+        );
+        assert_eq!(
+            form_identifier(b"email=jack%40example.com"),
+            Some("jack@example.com".into())
+        );
+        assert_eq!(form_identifier(b"password=hunter2"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use super::pw;
+use super::{pw, session_store};
 use crate::{
     components::Span,
     config,
@@ -176,13 +176,13 @@ pub struct RegisterFormPayload {
 
 #[axum_macros::debug_handler]
 pub async fn handle_registration(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Form(form): Form<RegisterFormPayload>,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers(&headers);
     let headers = HeaderMap::new();
-    let hashed_pw = pw::hash_new(&form.password);
+    let hashed_pw = pw::hash_new(&form.password).await?;
 
     let stripe_id =
         stripe::create_customer(&form.username, &form.email).await?;
@@ -239,51 +239,35 @@ pub async fn handle_registration(
     }
 
     let user = match session {
-        Some(mut ses) => {
-            if super::is_anon(&ses.username) {
-                // Anon users have a long-lived session. We want to change
-                // the session creation date back to "now"
-                ses.created_at = utc_now();
-                let mut anon_user = User::get(
-                    &db,
-                    &GetUserQuery {
-                        identifier: crate::db_ops::UserIdentifer::Id(
-                            ses.user_id,
-                        ),
-                    },
-                )
-                .await?;
-                query!(
-                    "update users set salt = $1, digest = $2
-                    where id = $3",
-                    hashed_pw.salt,
-                    hashed_pw.digest,
-                    ses.user_id
-                )
-                .execute(&db)
-                .await?;
+        Some(ses) if super::is_anon(&ses.username) => {
+            // Anon users have a long-lived session, which is replaced below
+            // with a regular one, now that they have a password.
+            session_store::revoke_all(&db, ses.user_id).await?;
+            let mut anon_user = User::get(
+                &db,
+                &GetUserQuery {
+                    identifier: crate::db_ops::UserIdentifer::Id(ses.user_id),
+                },
+            )
+            .await?;
+            query!(
+                "update users set salt = $1, digest = $2, email_verified = false
+                where id = $3",
+                hashed_pw.salt,
+                hashed_pw.digest,
+                ses.user_id
+            )
+            .execute(&db)
+            .await?;
 
-                anon_user.username = form.username;
-                anon_user.email = form.email;
-                anon_user.stripe_customer_id = stripe_id;
-                anon_user.save(&db).await?;
+            anon_user.username = form.username;
+            anon_user.email = form.email;
+            anon_user.stripe_customer_id = stripe_id;
+            anon_user.save(&db).await?;
 
-                anon_user
-            } else {
-                create_user(
-                    &db,
-                    form.username,
-                    form.email,
-                    &hashed_pw,
-                    stripe_id,
-                    stripe::SubscriptionTypes::FreeTrial(
-                        config::FREE_TRIAL_DURATION,
-                    ),
-                )
-                .await?
-            }
+            anon_user
         }
-        None => {
+        _ => {
             create_user(
                 &db,
                 form.username,
@@ -302,11 +286,20 @@ pub async fn handle_registration(
         ..Default::default()
     };
     save_user_preference(&db, user.id, &preferences).await?;
-    let session = Session {
-        user_id: user.id,
-        username: user.username,
-        created_at: utc_now(),
-    };
+    // The account is usable either way; the user can resend the email from
+    // the banner on their dashboard.
+    if let Err(e) =
+        super::send_verification_email(&db, user.id, &user.email).await
+    {
+        eprintln!("Error: failed to send verification email :: {e}");
+    }
+    let session = Session::create(
+        &db,
+        user.id,
+        user.username,
+        config::SESSION_EXPIRY_TIME_DAYS,
+    )
+    .await?;
     let headers = session.update_headers(headers);
     let headers = htmx::redirect(headers, &Route::UserHome.as_string());
     Ok((StatusCode::OK, headers, "OK".to_string()).into_response())
@@ -348,6 +341,7 @@ pub async fn create_user(
         created_at: query_return.created_at,
         username,
         email,
+        email_verified: false,
         stripe_customer_id,
         stripe_subscription_type: subscription_type,
     })
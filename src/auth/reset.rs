@@ -1,12 +1,45 @@
-use super::pw;
+use super::{pw, session_store, totp};
 use crate::{
     auth,
     config::{BASE_URL, RESET_TOKEN_TIMEOUT_MINUTES},
+    email::{Email, EmailButton, EmailTemplate},
     htmx,
     prelude::*,
     smtp::send_email,
 };
 
+struct ResetEmail {
+    link: Route,
+}
+impl Component for ResetEmail {
+    fn render(&self) -> String {
+        let button = EmailButton {
+            href: &self.link,
+            text: "Reset your password",
+        }
+        .render();
+        format!(
+            r#"
+            <p>
+                Someone requested a password reset for your beancount.bot
+                account. If it wasn't you, you can ignore this email.
+            </p>
+            {button}
+            <p>This link will expire in {RESET_TOKEN_TIMEOUT_MINUTES} minutes.</p>
+            "#
+        )
+    }
+}
+impl EmailTemplate for ResetEmail {
+    fn subject(&self) -> String {
+        "Password Reset for beancount.bot".into()
+    }
+    fn plaintext(&self) -> String {
+        let link = &self.link;
+        format!("Visit {BASE_URL}{link} to reset your password. This link will expire in {RESET_TOKEN_TIMEOUT_MINUTES} minutes.")
+    }
+}
+
 struct ResetRequestForm;
 impl Component for ResetRequestForm {
     fn render(&self) -> String {
@@ -40,7 +73,7 @@ impl Component for ConfirmReset<'_> {
             r#"
             <div>
                 <p>An password reset email was sent to {email} if an associated
-                user exists.</p>
+                user exists, and has verified their email address.</p>
                 <a class="link" href="{home}">Return to Home Page</a>
             </div>
             "#
@@ -64,15 +97,19 @@ pub struct ResetPayload {
 }
 
 pub async fn handle_pw_reset_request(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     Form(ResetPayload { email }): Form<ResetPayload>,
 ) -> Result<impl IntoResponse, ServerError> {
     struct Qres {
         id: i32,
     }
-    let uid = query_as!(Qres, "select id from users where email = $1", email)
-        .fetch_optional(&db)
-        .await?;
+    let uid = query_as!(
+        Qres,
+        "select id from users where email = $1 and email_verified",
+        email
+    )
+    .fetch_optional(&db)
+    .await?;
     if let Some(Qres { id }) = uid {
         // Invalidating old reset links before creating a new one feels like
         // the right move, which is also why it's enforced by our schema.
@@ -82,12 +119,7 @@ pub async fn handle_pw_reset_request(
 
         let slug = uuid::Uuid::new_v4().to_string();
         let link = Route::PasswordResetSecret(Some(slug.clone()));
-        send_email(
-            &email,
-            "Password Reset for beancount.bot",
-            &format!("Visit {BASE_URL}{link} to reset your password. This link will expire in 15 minutes."),
-        )
-        .await?;
+        send_email(&Email::new(&email, &ResetEmail { link })).await?;
         query!(
             "insert into password_reset_link (user_id, slug) values ($1, $2)",
             id,
@@ -165,7 +197,7 @@ pub struct NewPassword {
 /// Handles POST to the secret URL, performing the password reset if the slug
 /// is valid.
 pub async fn handle_password_reset(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     Path(slug): Path<String>,
     Form(NewPassword { password }): Form<NewPassword>,
 ) -> Result<impl IntoResponse, ServerError> {
@@ -206,7 +238,7 @@ pub async fn handle_password_reset(
             {
                 Ok((headers, ResetFailed {}.render()))
             } else {
-                let pw = pw::hash_new(&password);
+                let pw = pw::hash_new(&password).await?;
                 query!(
                     "update users set salt = $1, digest = $2
                     where id = $3",
@@ -216,6 +248,15 @@ pub async fn handle_password_reset(
                 )
                 .execute(&db)
                 .await?;
+                // Stolen session cookies stop working once the password
+                // changes.
+                session_store::revoke_all(&db, tok.user_id).await?;
+                // A reset link only proves access to the user's email, so
+                // users with two-factor authentication still need to log in.
+                if totp::is_enabled(&db, tok.user_id).await? {
+                    let login = Route::Login.as_string();
+                    return Ok((htmx::redirect(headers, &login), "OK".into()));
+                }
                 let session =
                     auth::authenticate(&db, &tok.username, &password).await?;
                 let homepage = Route::UserHome.as_string();
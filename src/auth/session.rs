@@ -1,6 +1,9 @@
-//! Cookie-based session, secured by a HMAC signature.
+//! Cookie-based session, secured by a HMAC signature. Each session also has a
+//! row in the `session` table, so that it can be revoked; see
+//! [super::session_store].
 use super::crypto;
 use crate::{
+    chrono_utils::utc_now,
     config,
     db_ops::{GetModel, GetUserQuery, UserIdentifer},
     errors::ServerError,
@@ -11,9 +14,10 @@ use axum::http::{HeaderMap, HeaderValue};
 use base64::{engine::general_purpose, Engine as _};
 use chrono::{DateTime, Days, Utc};
 use chrono_tz::Tz;
+use rand::RngCore;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{query, PgPool};
 
 /// `Session` is signed and serialized into the `Cookie` header when a
 /// [HeaderMap] is passed into the [Session::update_headers()] method. Thus,
@@ -31,26 +35,91 @@ use sqlx::PgPool;
 /// also to convert to/from base64 encoding.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Session {
+    /// Primary key of the `session` table.
+    pub id: String,
     pub user_id: i32,
     pub username: String,
     pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
 }
+
+/// Session cookies which were issued before sessions were stored in the
+/// database. There's no way to tell whether these were meant to be revoked,
+/// so [super::session_store] clears them, and the user has to log in again.
+/// The fields are only parsed to recognize these cookies, and never read.
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LegacySession {
+    pub user_id: i32,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+}
+
 impl Session {
+    /// Start a new session, which lasts for `lifetime_days`.
+    pub async fn create(
+        db: &PgPool,
+        user_id: i32,
+        username: String,
+        lifetime_days: u64,
+    ) -> Result<Self> {
+        let created_at = utc_now();
+        let expires_at = created_at
+            .checked_add_days(Days::new(lifetime_days))
+            .ok_or_else(|| anyhow::Error::msg("session expires too late"))?;
+        Self::insert(db, user_id, username, created_at, expires_at).await
+    }
+    async fn insert(
+        db: &PgPool,
+        user_id: i32,
+        username: String,
+        created_at: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Self> {
+        let mut id = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut id);
+        let id = hex::encode(id);
+        query!(
+            "insert into session (id, user_id, created_at, expires_at)
+            values ($1, $2, $3, $4)",
+            id,
+            user_id,
+            created_at,
+            expires_at
+        )
+        .execute(db)
+        .await?;
+        Ok(Self {
+            id,
+            user_id,
+            username,
+            created_at,
+            expires_at,
+        })
+    }
     pub fn is_administrator(&self) -> bool {
         config::ADMINISTRATOR_USER_IDS.contains(&self.user_id)
     }
     /// Parse the session from request headers, validating the cookie
     /// signature along the way. Returns the [None] variant if the session
-    /// header is missing or invalid.
+    /// header is missing, invalid, or expired.
+    ///
+    /// Sessions which have been revoked never make it this far, because
+    /// [crate::middleware::sessions] checks every session against the
+    /// `session` table, and strips revoked sessions out of the request before
+    /// any handler runs.
     pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
-        let cookie = headers.get("Cookie")?;
-        let cookie = cookie.to_str().unwrap_or("");
-        let re = Regex::new(r"session=(.*)").unwrap();
-        let captures = re.captures(cookie)?;
-        let token = &captures[1];
-        let deserialize_result = Self::deserialize(token);
-
-        deserialize_result.ok()
+        let session: Self = Self::deserialize(cookie_token(headers)?).ok()?;
+        if session.expires_at <= utc_now() {
+            return None;
+        }
+        Some(session)
+    }
+    pub(super) fn legacy_from_headers(
+        headers: &HeaderMap,
+    ) -> Option<LegacySession> {
+        Self::deserialize(cookie_token(headers)?).ok()
     }
     /// `err_msg` should identify which handler the error is coming from. Simply
     /// the name of the handler function is typically the best thing to put
@@ -66,13 +135,7 @@ impl Session {
     pub fn update_headers(&self, mut headers: HeaderMap) -> HeaderMap {
         let session_string = self.serialize();
         let expiry_date = self
-            .created_at
-            .checked_add_days(Days::new(
-                config::SESSION_EXPIRY_TIME_DAYS
-                    .try_into()
-                    .expect("7 can be a u64 too"),
-            ))
-            .expect("heat death of the universe has not happened yet")
+            .expires_at
             .with_timezone(&Tz::GMT)
             .format("%a, %d %b %Y %H:%M:%S %Z");
 
@@ -88,7 +151,7 @@ impl Session {
 
         headers
     }
-    fn serialize(&self) -> String {
+    pub fn serialize(&self) -> String {
         let json_bytes = serde_json::to_string(&self)
             .expect("session can be JSON serialized");
         let b64 = general_purpose::STANDARD_NO_PAD.encode(json_bytes);
@@ -98,7 +161,9 @@ impl Session {
 
         session
     }
-    fn deserialize(cookie: &str) -> Result<Self, &'static str> {
+    fn deserialize<T: DeserializeOwned>(
+        cookie: &str,
+    ) -> Result<T, &'static str> {
         let parts: Vec<&str> = cookie.split(':').collect();
         if parts.len() != 2 {
             Err("Invalid session")
@@ -161,6 +226,15 @@ impl Session {
     }
 }
 
+/// The raw `session` cookie value, if the request has one.
+fn cookie_token(headers: &HeaderMap) -> Option<&str> {
+    let cookie = headers.get("Cookie")?;
+    let cookie = cookie.to_str().unwrap_or("");
+    let re = Regex::new(r"session=(.*)").unwrap();
+    let captures = re.captures(cookie)?;
+    Some(captures.get(1)?.as_str())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,14 +242,29 @@ mod tests {
 
     fn get_session() -> Session {
         Session {
+            id: "abc".to_string(),
             user_id: 1,
             username: "tim".to_string(),
             created_at: DateTime::<Utc>::from_timestamp(0, 0)
                 .expect("that is a valid timestamp"),
+            expires_at: DateTime::<Utc>::from_timestamp(60 * 60 * 24 * 30, 0)
+                .expect("that is a valid timestamp"),
         }
     }
 
-    const SERIALIZED_SESSION: &str = "eyJ1c2VyX2lkIjoxLCJ1c2VybmFtZSI6InRpbSIsImNyZWF0ZWRfYXQiOiIxOTcwLTAxLTAxVDAwOjAwOjAwWiJ9:KzxVEO3TZPXQbtqbomX42mrk1KxPctywwaNaoDVG4Tg";
+    const SERIALIZED_SESSION: &str = "eyJpZCI6ImFiYyIsInVzZXJfaWQiOjEsInVzZXJuYW1lIjoidGltIiwiY3JlYXRlZF9hdCI6IjE5NzAtMDEtMDFUMDA6MDA6MDBaIiwiZXhwaXJlc19hdCI6IjE5NzAtMDEtMzFUMDA6MDA6MDBaIn0:whxc5ASP/ANXe24FbW9JVaY3W/21EAj0MQr5l/JOooA";
+
+    /// Issued before sessions had an ID.
+    const LEGACY_SESSION: &str = "eyJ1c2VyX2lkIjoxLCJ1c2VybmFtZSI6InRpbSIsImNyZWF0ZWRfYXQiOiIxOTcwLTAxLTAxVDAwOjAwOjAwWiJ9:KzxVEO3TZPXQbtqbomX42mrk1KxPctywwaNaoDVG4Tg";
+
+    fn headers(cookie: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Cookie",
+            HeaderValue::from_str(&format!("session={cookie}")).unwrap(),
+        );
+        headers
+    }
 
     #[test]
     fn test_serialize_session() {
@@ -190,9 +279,38 @@ mod tests {
     fn test_deserialize_session() {
         env::set_var("SESSION_SECRET", "foo");
 
-        let result = Session::deserialize(&String::from(SERIALIZED_SESSION))
-            .expect("result");
+        let result: Session =
+            Session::deserialize(SERIALIZED_SESSION).expect("result");
         // little snapshot test
         assert_eq!(result.user_id, get_session().user_id);
+        assert_eq!(result.id, get_session().id);
+    }
+
+    #[test]
+    fn test_legacy_session() {
+        env::set_var("SESSION_SECRET", "foo");
+
+        let headers = headers(LEGACY_SESSION);
+        assert!(Session::from_headers(&headers).is_none());
+        let legacy = Session::legacy_from_headers(&headers).expect("legacy");
+        assert_eq!(legacy.username, "tim");
+    }
+
+    #[test]
+    fn test_current_session_is_not_legacy() {
+        env::set_var("SESSION_SECRET", "foo");
+
+        let mut session = get_session();
+        session.expires_at = utc_now() + Days::new(1);
+        let headers = headers(&session.serialize());
+        assert!(Session::from_headers(&headers).is_some());
+        assert!(Session::legacy_from_headers(&headers).is_none());
+    }
+
+    #[test]
+    fn test_expired_session() {
+        env::set_var("SESSION_SECRET", "foo");
+
+        assert!(Session::from_headers(&headers(SERIALIZED_SESSION)).is_none());
     }
 }
@@ -0,0 +1,140 @@
+//! Server-side session state. Every [Session] cookie has a row in the
+//! `session` table, and the cookie is only honored while that row exists.
+//! Deleting the row revokes the session.
+//!
+//! Checking the table on every request would add a database round trip to
+//! everything, so sessions which were recently found in the table are
+//! remembered for [config::SESSION_CACHE_TTL].
+
+use super::session::Session;
+use crate::{config, prelude::*};
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::Instant,
+};
+
+/// Keeps the cache small; when it's full, stale entries are dropped, and if
+/// that's not enough, the whole cache is cleared.
+const CACHE_CAPACITY: usize = 10_000;
+
+struct CacheEntry {
+    user_id: i32,
+    checked_at: Instant,
+}
+
+static CACHE: LazyLock<Mutex<HashMap<String, CacheEntry>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn cache() -> std::sync::MutexGuard<'static, HashMap<String, CacheEntry>> {
+    CACHE.lock().expect("session cache is not poisoned")
+}
+
+fn is_cached(id: &str) -> bool {
+    cache()
+        .get(id)
+        .is_some_and(|e| e.checked_at.elapsed() < config::SESSION_CACHE_TTL)
+}
+
+fn remember(id: &str, user_id: i32) {
+    let mut cache = cache();
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, e| e.checked_at.elapsed() < config::SESSION_CACHE_TTL);
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+    }
+    cache.insert(
+        id.to_string(),
+        CacheEntry {
+            user_id,
+            checked_at: Instant::now(),
+        },
+    );
+}
+
+pub enum SessionStatus {
+    /// The request doesn't have a session cookie.
+    Missing,
+    Active,
+    /// The session was revoked, or has expired. Cookies from before
+    /// sessions were stored in the database are also treated as revoked;
+    /// see [super::session::LegacySession].
+    Revoked,
+}
+
+/// Check the request's session cookie against the `session` table. This also
+/// keeps track of when, and from which browser, each session was last used.
+pub async fn check(
+    db: &PgPool,
+    headers: &HeaderMap,
+    user_agent: Option<&str>,
+) -> Aresult<SessionStatus> {
+    if let Some(session) = Session::from_headers(headers) {
+        if is_cached(&session.id) {
+            return Ok(SessionStatus::Active);
+        }
+        let found = query!(
+            "update session set last_seen_at = now(), user_agent = $3
+            where id = $1 and user_id = $2 and expires_at > now()
+            returning id",
+            session.id,
+            session.user_id,
+            user_agent
+        )
+        .fetch_optional(db)
+        .await?;
+        return Ok(if found.is_some() {
+            remember(&session.id, session.user_id);
+            SessionStatus::Active
+        } else {
+            forget(&session.id);
+            SessionStatus::Revoked
+        });
+    }
+    if Session::legacy_from_headers(headers).is_some() {
+        return Ok(SessionStatus::Revoked);
+    }
+    Ok(SessionStatus::Missing)
+}
+
+fn forget(id: &str) {
+    cache().remove(id);
+}
+
+/// Log out a single session.
+pub async fn revoke(db: &PgPool, user_id: i32, id: &str) -> Aresult<()> {
+    query!(
+        "delete from session where id = $1 and user_id = $2",
+        id,
+        user_id
+    )
+    .execute(db)
+    .await?;
+    forget(id);
+    Ok(())
+}
+
+/// Log out everywhere. This happens whenever the user's password changes.
+pub async fn revoke_all(db: &PgPool, user_id: i32) -> Aresult<()> {
+    query!("delete from session where user_id = $1", user_id)
+        .execute(db)
+        .await?;
+    cache().retain(|_, e| e.user_id != user_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let id = "session-store-test";
+        assert!(!is_cached(id));
+        remember(id, 1);
+        assert!(is_cached(id));
+        forget(id);
+        assert!(!is_cached(id));
+    }
+}
@@ -0,0 +1,372 @@
+//! Time-based one-time passwords ([RFC 6238](https://www.rfc-editor.org/rfc/rfc6238)),
+//! which are the six-digit codes from authenticator apps. We use the
+//! parameters which every app supports: HMAC-SHA1, six digits, and 30 second
+//! time steps.
+//!
+//! Each user with two-factor authentication also gets a set of single-use
+//! recovery codes, in case they lose their phone. Those are high-entropy
+//! random strings, so they're stored as a HMAC digest, like
+//! [super::crypto] does for sessions.
+
+use super::crypto;
+use crate::prelude::*;
+use hmac::{Hmac, Mac};
+use qrcode::{render::svg, QrCode};
+use rand::{Rng, RngCore};
+use sha1::Sha1;
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// Codes from one step before or after the current one are accepted, to
+/// allow for clock drift.
+const SKEW_STEPS: i64 = 1;
+/// 160 bits, as recommended by [RFC 4226](https://www.rfc-editor.org/rfc/rfc4226).
+const SECRET_LEN: usize = 20;
+const ISSUER: &str = "beancount.bot";
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn new_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Authenticator apps expect secrets to be base32 encoded, without padding.
+pub fn base32(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(
+            BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char,
+        );
+    }
+    out
+}
+
+fn step(time: DateTime<Utc>) -> i64 {
+    time.timestamp().div_euclid(STEP_SECONDS)
+}
+
+/// The HOTP value for a time step.
+fn code_at(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret)
+        .expect("hmac accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes(
+        hash[offset..offset + 4]
+            .try_into()
+            .expect("offset leaves four bytes"),
+    ) & 0x7fff_ffff;
+    binary % 10_u32.pow(DIGITS)
+}
+
+/// The time step which `code` belongs to, if it's valid at `now`.
+fn matching_step(secret: &[u8], code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code: u32 = code.parse().ok()?;
+    let current = step(now);
+    (current - SKEW_STEPS..=current + SKEW_STEPS)
+        .find(|&step| code_at(secret, step) == code)
+}
+
+/// Codes are typed in by hand, so we're forgiving about spaces, dashes, and
+/// case.
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn is_totp_code(code: &str) -> bool {
+    code.len() == DIGITS as usize && code.chars().all(|c| c.is_ascii_digit())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect()
+}
+
+/// The `otpauth://` URI which authenticator apps scan from the QR code.
+pub fn provisioning_uri(secret: &[u8], username: &str) -> String {
+    let secret = base32(secret);
+    let username = percent_encode(username);
+    format!(
+        "otpauth://totp/{ISSUER}:{username}?secret={secret}&issuer={ISSUER}"
+    )
+}
+
+/// The QR code for [provisioning_uri], as an inline `<svg>` element.
+pub fn qr_code_svg(uri: &str) -> Aresult<String> {
+    let svg = QrCode::new(uri.as_bytes())?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .dark_color(svg::Color("#000000"))
+        .light_color(svg::Color("#ffffff"))
+        .build();
+    // Drop the XML declaration, since this is embedded in HTML.
+    Ok(svg
+        .find("<svg")
+        .map_or(svg.clone(), |start| svg[start..].to_string()))
+}
+
+fn new_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: String = (0..10)
+        .map(|_| BASE32_ALPHABET[rng.gen_range(0..32)] as char)
+        .collect::<String>()
+        .to_lowercase();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn recovery_code_digest(code: &str) -> String {
+    hex::encode(crypto::get_digest(
+        format!("totp-recovery:{}", normalize(code)).as_bytes(),
+    ))
+}
+
+pub async fn is_enabled(db: &PgPool, user_id: i32) -> Aresult<bool> {
+    Ok(query!(
+        r#"select exists(
+            select 1 from totp where user_id = $1 and enabled
+        ) "exists!""#,
+        user_id
+    )
+    .fetch_one(db)
+    .await?
+    .exists)
+}
+
+/// Replaces any pending enrollment with a new secret. This must not be
+/// called once two-factor authentication is enabled.
+pub async fn start_enrollment(db: &PgPool, user_id: i32) -> Aresult<Vec<u8>> {
+    let secret = new_secret();
+    query!(
+        "insert into totp (user_id, secret) values ($1, $2)
+        on conflict (user_id) do update set secret = $2, created_at = now()
+        where not totp.enabled",
+        user_id,
+        secret
+    )
+    .execute(db)
+    .await?;
+    Ok(secret)
+}
+
+pub async fn pending_secret(
+    db: &PgPool,
+    user_id: i32,
+) -> Aresult<Option<Vec<u8>>> {
+    Ok(query!(
+        "select secret from totp where user_id = $1 and not enabled",
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|row| row.secret))
+}
+
+/// Enables two-factor authentication if `code` is correct for the pending
+/// secret, returning a new set of recovery codes. This is the only time that
+/// the plaintext recovery codes exist.
+pub async fn confirm_enrollment(
+    db: &PgPool,
+    user_id: i32,
+    code: &str,
+) -> Aresult<Option<Vec<String>>> {
+    let Some(secret) = pending_secret(db, user_id).await? else {
+        return Ok(None);
+    };
+    let Some(step) = matching_step(&secret, &normalize(code), utc_now()) else {
+        return Ok(None);
+    };
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| new_recovery_code())
+        .collect();
+    let digests: Vec<String> =
+        codes.iter().map(|c| recovery_code_digest(c)).collect();
+    let mut tx = db.begin().await?;
+    query!(
+        "update totp set enabled = true, last_used_step = $2
+        where user_id = $1",
+        user_id,
+        step
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("delete from totp_recovery_code where user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    query!(
+        "insert into totp_recovery_code (user_id, digest)
+        select $1, unnest($2::text[])",
+        user_id,
+        &digests
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(codes))
+}
+
+/// Checks a code from the user's authenticator app, or one of their recovery
+/// codes. Either kind of code only works once.
+pub async fn verify(db: &PgPool, user_id: i32, code: &str) -> Aresult<bool> {
+    let code = normalize(code);
+    if !is_totp_code(&code) {
+        let used = query!(
+            "delete from totp_recovery_code
+            where id = (
+                select id from totp_recovery_code
+                where user_id = $1 and digest = $2
+                limit 1
+            )",
+            user_id,
+            recovery_code_digest(&code)
+        )
+        .execute(db)
+        .await?;
+        return Ok(used.rows_affected() == 1);
+    }
+    let Some(totp) = query!(
+        "select secret, last_used_step from totp
+        where user_id = $1 and enabled",
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    else {
+        return Ok(false);
+    };
+    let Some(step) = matching_step(&totp.secret, &code, utc_now()) else {
+        return Ok(false);
+    };
+    if totp.last_used_step.is_some_and(|last| step <= last) {
+        return Ok(false);
+    }
+    let claimed = query!(
+        "update totp set last_used_step = $2
+        where user_id = $1
+        and (last_used_step is null or last_used_step < $2)",
+        user_id,
+        step
+    )
+    .execute(db)
+    .await?;
+    Ok(claimed.rows_affected() == 1)
+}
+
+pub async fn recovery_codes_remaining(
+    db: &PgPool,
+    user_id: i32,
+) -> Aresult<i64> {
+    Ok(query!(
+        r#"select count(*) "count!" from totp_recovery_code
+        where user_id = $1"#,
+        user_id
+    )
+    .fetch_one(db)
+    .await?
+    .count)
+}
+
+pub async fn disable(db: &PgPool, user_id: i32) -> Aresult<()> {
+    let mut tx = db.begin().await?;
+    query!("delete from totp where user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    query!("delete from totp_recovery_code where user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The SHA1 seed from the RFC 6238 test vectors.
+    const SEED: &[u8] = b"12345678901234567890";
+
+    fn at(timestamp: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(timestamp, 0).unwrap()
+    }
+
+    #[test]
+    fn test_rfc_6238_vectors() {
+        // The RFC uses eight digits; these are the last six.
+        assert_eq!(code_at(SEED, step(at(59))), 287082);
+        assert_eq!(code_at(SEED, step(at(1111111109))), 81804);
+        assert_eq!(code_at(SEED, step(at(1234567890))), 5924);
+        assert_eq!(code_at(SEED, step(at(2000000000))), 279037);
+    }
+
+    #[test]
+    fn test_matching_step_allows_drift() {
+        let now = at(1111111109);
+        let current = step(now);
+        assert_eq!(matching_step(SEED, "081804", now), Some(current));
+        let previous = format!("{:06}", code_at(SEED, current - 1));
+        assert_eq!(matching_step(SEED, &previous, now), Some(current - 1));
+        let stale = format!("{:06}", code_at(SEED, current - 2));
+        assert_eq!(matching_step(SEED, &stale, now), None);
+        assert_eq!(matching_step(SEED, "not a code", now), None);
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32(SEED), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32(b"f"), "MY");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        assert_eq!(
+            provisioning_uri(SEED, "jack smith"),
+            "otpauth://totp/beancount.bot:jack%20smith?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=beancount.bot"
+        );
+    }
+
+    #[test]
+    fn test_qr_code_svg() {
+        let svg = qr_code_svg(&provisioning_uri(SEED, "jack")).unwrap();
+        assert!(svg.starts_with("<svg"));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        std::env::set_var("SESSION_SECRET", "foo");
+        let code = new_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert!(!is_totp_code(&normalize(&code)));
+        assert_eq!(
+            recovery_code_digest(&code),
+            recovery_code_digest(&code.to_uppercase().replace('-', " "))
+        );
+        assert_ne!(
+            recovery_code_digest(&code),
+            recovery_code_digest(&new_recovery_code())
+        );
+    }
+}
@@ -0,0 +1,489 @@
+//! Optional two-factor authentication with an authenticator app; see
+//! [super::totp]. This has the settings page for turning it on or off, and the
+//! second step of logging in.
+//!
+//! Between the two steps of logging in, the user holds a short-lived signed
+//! token which proves that they already entered the right password.
+
+use super::{crypto, is_anon, rate_limit::AttemptSucceeded, totp};
+use crate::{
+    config::{SESSION_EXPIRY_TIME_DAYS, TWO_FACTOR_LOGIN_TIMEOUT_MINUTES},
+    html_sanitize::encode_quotes,
+    htmx,
+    prelude::*,
+};
+
+fn sign_login_token(user_id: i32, expires_at: i64) -> String {
+    let digest = crypto::get_digest(
+        format!("two-factor-login:{user_id}:{expires_at}").as_bytes(),
+    );
+    format!("{user_id}.{expires_at}.{}", hex::encode(digest))
+}
+
+pub fn login_token(user_id: i32) -> String {
+    let expires_at = (utc_now()
+        + chrono::Duration::minutes(TWO_FACTOR_LOGIN_TIMEOUT_MINUTES))
+    .timestamp();
+    sign_login_token(user_id, expires_at)
+}
+
+/// The user ID in a [login_token], if it's authentic and hasn't expired.
+fn verify_login_token(token: &str, now: DateTime<Utc>) -> Option<i32> {
+    let mut parts = token.split('.');
+    let user_id: i32 = parts.next()?.parse().ok()?;
+    let expires_at: i64 = parts.next()?.parse().ok()?;
+    let digest = hex::decode(parts.next()?).ok()?;
+    if parts.next().is_some() || expires_at < now.timestamp() {
+        return None;
+    }
+    crypto::is_valid(
+        format!("two-factor-login:{user_id}:{expires_at}").as_bytes(),
+        &digest,
+    )
+    .then_some(user_id)
+}
+
+const BUTTON_STYLE: &str = "
+    self-start
+    bg-emerald-200
+    hover:bg-emerald-300
+    dark:bg-emerald-700
+    dark:hover:bg-emerald-600
+    dark:text-white
+    hover:shadow-none
+    p-1
+    rounded
+    shadow
+    transition
+";
+
+fn notice(message: Option<&str>) -> String {
+    message.map_or("".into(), |msg| {
+        format!(r#"<p class="text-sm italic">{msg}</p>"#)
+    })
+}
+
+/// The second step of the login form.
+pub struct TwoFactorLoginForm<'a> {
+    pub token: &'a str,
+    /// Whatever the user typed into the login form, so that the two steps
+    /// share a rate limit; see [super::rate_limit].
+    pub identifier: &'a str,
+    pub error: Option<&'a str>,
+}
+impl Component for TwoFactorLoginForm<'_> {
+    fn render(&self) -> String {
+        let route = Route::LoginTwoFactor;
+        let token = encode_quotes(&clean(self.token));
+        let identifier = encode_quotes(&clean(self.identifier));
+        let error = notice(self.error);
+        format!(
+            r##"
+            <div id="form-container">
+                <form
+                    class="flex flex-col gap-2 max-w-md"
+                    hx-post="{route}"
+                    hx-target="#form-container"
+                >
+                    <h1 class="text-xl">Two-Factor Authentication</h1>
+                    <p>
+                        Enter the code from your authenticator app, or one of
+                        your recovery codes.
+                    </p>
+                    <input type="hidden" name="token" value="{token}" />
+                    <input type="hidden" name="identifier" value="{identifier}" />
+                    <label for="code">Code</label>
+                    <input
+                        type="text"
+                        id="code"
+                        name="code"
+                        autocomplete="one-time-code"
+                        autofocus
+                        required
+                    />
+                    {error}
+                    <button class="{BUTTON_STYLE} w-36 h-10">Verify</button>
+                </form>
+            </div>
+            "##
+        )
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorLoginPayload {
+    token: String,
+    identifier: String,
+    code: String,
+}
+
+pub async fn handle_login_two_factor(
+    State(AppState { db, .. }): State<AppState>,
+    Form(form): Form<TwoFactorLoginPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let headers = HeaderMap::new();
+    let Some(user_id) = verify_login_token(&form.token, utc_now()) else {
+        let login = Route::Login;
+        return Ok((
+            headers,
+            format!(
+                r#"<p>This login has expired. <a class="link" href="{login}">Please log in again.</a></p>"#
+            ),
+        ));
+    };
+    let user =
+        query!("select username, email from users where id = $1", user_id)
+            .fetch_one(&db)
+            .await?;
+    if form.identifier != user.username && form.identifier != user.email {
+        return Err(ServerError::forbidden(
+            "identifier does not match two-factor login token",
+        ));
+    }
+    if totp::verify(&db, user_id, &form.code).await? {
+        let session = Session::create(
+            &db,
+            user_id,
+            user.username,
+            SESSION_EXPIRY_TIME_DAYS,
+        )
+        .await?;
+        let headers = session.update_headers(headers);
+        let headers = htmx::redirect(headers, &Route::UserHome.as_string());
+        Ok((headers, "OK".into()))
+    } else {
+        Ok((
+            headers,
+            TwoFactorLoginForm {
+                token: &form.token,
+                identifier: &form.identifier,
+                error: Some("That code didn't work. Please try again."),
+            }
+            .render(),
+        ))
+    }
+}
+
+enum TwoFactorStatus {
+    /// Anonymous users don't have a password, so there's nothing for a
+    /// second factor to protect.
+    Anon,
+    Off,
+    On {
+        recovery_codes_remaining: i64,
+    },
+}
+
+async fn get_status(
+    db: &PgPool,
+    session: &Session,
+) -> Aresult<TwoFactorStatus> {
+    Ok(if is_anon(&session.username) {
+        TwoFactorStatus::Anon
+    } else if totp::is_enabled(db, session.user_id).await? {
+        TwoFactorStatus::On {
+            recovery_codes_remaining: totp::recovery_codes_remaining(
+                db,
+                session.user_id,
+            )
+            .await?,
+        }
+    } else {
+        TwoFactorStatus::Off
+    })
+}
+
+struct TwoFactorSettings<'a> {
+    status: TwoFactorStatus,
+    message: Option<&'a str>,
+}
+impl Component for TwoFactorSettings<'_> {
+    fn render(&self) -> String {
+        let home = Route::UserHome;
+        let message = notice(self.message);
+        let body = match self.status {
+            TwoFactorStatus::Anon => {
+                let register = Route::Register;
+                format!(
+                    r#"
+                    <p>
+                        <a class="link" href="{register}">Register an account</a>
+                        to turn on two-factor authentication.
+                    </p>
+                    "#
+                )
+            }
+            TwoFactorStatus::Off => {
+                let enroll = Route::TwoFactor;
+                format!(
+                    r##"
+                    <p>
+                        Two-factor authentication is off. When it's on, you'll
+                        need a code from an authenticator app on your phone to
+                        log in, in addition to your password.
+                    </p>
+                    {message}
+                    <button
+                        hx-post="{enroll}"
+                        hx-target="#two-factor"
+                        class="{BUTTON_STYLE}"
+                    >
+                        Set up two-factor authentication
+                    </button>
+                    "##
+                )
+            }
+            TwoFactorStatus::On {
+                recovery_codes_remaining,
+            } => {
+                let disable = Route::TwoFactorDisable;
+                format!(
+                    r##"
+                    <p>
+                        Two-factor authentication is on. You have
+                        {recovery_codes_remaining} unused recovery codes.
+                    </p>
+                    <form
+                        hx-post="{disable}"
+                        hx-target="#two-factor"
+                        class="flex flex-col gap-2"
+                    >
+                        <label for="code">
+                            To turn it off, enter a code from your
+                            authenticator app, or a recovery code.
+                        </label>
+                        <input
+                            type="text"
+                            id="code"
+                            name="code"
+                            autocomplete="one-time-code"
+                            required
+                        />
+                        {message}
+                        <button
+                            class="self-start bg-red-100 hover:bg-red-200
+                            rounded p-1 text-black"
+                        >
+                            Turn off two-factor authentication
+                        </button>
+                    </form>
+                    "##
+                )
+            }
+        };
+        format!(
+            r#"
+            <div id="two-factor" class="flex flex-col gap-2 max-w-prose">
+                <a class="link" href="{home}">Back to dashboard</a>
+                <h1 class="text-2xl font-extrabold">Two-Factor Authentication</h1>
+                {body}
+            </div>
+            "#
+        )
+    }
+}
+
+struct EnrollmentForm<'a> {
+    secret: &'a [u8],
+    username: &'a str,
+    error: Option<&'a str>,
+}
+impl Component for EnrollmentForm<'_> {
+    fn render(&self) -> String {
+        let confirm = Route::TwoFactorConfirm;
+        let qr_code = totp::qr_code_svg(&totp::provisioning_uri(
+            self.secret,
+            self.username,
+        ))
+        .unwrap_or_default();
+        let key = totp::base32(self.secret)
+            .as_bytes()
+            .chunks(4)
+            .map(|c| String::from_utf8_lossy(c).into_owned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let error = notice(self.error);
+        format!(
+            r##"
+            <div id="two-factor" class="flex flex-col gap-2 max-w-prose">
+                <h1 class="text-2xl font-extrabold">Set up Two-Factor Authentication</h1>
+                <p>
+                    Scan this QR code with an authenticator app, like Google
+                    Authenticator or 1Password. Then, enter the six-digit code
+                    that it shows.
+                </p>
+                <div class="self-start bg-white p-2 rounded">{qr_code}</div>
+                <p class="text-sm">
+                    Can't scan it? Enter this key instead:
+                    <code class="break-all">{key}</code>
+                </p>
+                <form
+                    hx-post="{confirm}"
+                    hx-target="#two-factor"
+                    class="flex flex-col gap-2"
+                >
+                    <label for="code">Code</label>
+                    <input
+                        type="text"
+                        id="code"
+                        name="code"
+                        inputmode="numeric"
+                        autocomplete="one-time-code"
+                        required
+                    />
+                    {error}
+                    <button class="{BUTTON_STYLE}">
+                        Turn on two-factor authentication
+                    </button>
+                </form>
+            </div>
+            "##
+        )
+    }
+}
+
+struct RecoveryCodes<'a> {
+    codes: &'a [String],
+}
+impl Component for RecoveryCodes<'_> {
+    fn render(&self) -> String {
+        let home = Route::UserHome;
+        let codes = self.codes.iter().fold(String::new(), |mut acc, code| {
+            acc.push_str(&format!("<li>{code}</li>"));
+            acc
+        });
+        format!(
+            r#"
+            <div id="two-factor" class="flex flex-col gap-2 max-w-prose">
+                <h1 class="text-2xl font-extrabold">Two-Factor Authentication is On</h1>
+                <p>
+                    Save these recovery codes somewhere safe. If you lose
+                    access to your authenticator app, each one can be used
+                    once instead of a code. We won't show them again.
+                </p>
+                <ul class="font-mono">{codes}</ul>
+                <a class="link" href="{home}">Back to dashboard</a>
+            </div>
+            "#
+        )
+    }
+}
+
+pub async fn two_factor_settings(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "two-factor settings")?;
+    let status = get_status(&db, &session).await?;
+    Ok(Page {
+        title: "Two-Factor Authentication",
+        children: &PageContainer {
+            children: &TwoFactorSettings {
+                status,
+                message: None,
+            },
+        },
+    }
+    .render())
+}
+
+pub async fn start_two_factor_enrollment(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "start two-factor")?;
+    let status = get_status(&db, &session).await?;
+    if !matches!(status, TwoFactorStatus::Off) {
+        return Ok(TwoFactorSettings {
+            status,
+            message: None,
+        }
+        .render());
+    }
+    let secret = totp::start_enrollment(&db, session.user_id).await?;
+    Ok(EnrollmentForm {
+        secret: &secret,
+        username: &session.username,
+        error: None,
+    }
+    .render())
+}
+
+#[derive(Deserialize)]
+pub struct CodePayload {
+    code: String,
+}
+
+pub async fn confirm_two_factor(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(CodePayload { code }): Form<CodePayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "confirm two-factor")?;
+    if is_anon(&session.username) {
+        return Err(ServerError::forbidden("anon user cannot enable 2fa"));
+    }
+    if let Some(codes) =
+        totp::confirm_enrollment(&db, session.user_id, &code).await?
+    {
+        return Ok(RecoveryCodes { codes: &codes }.render());
+    }
+    Ok(match totp::pending_secret(&db, session.user_id).await? {
+        Some(secret) => EnrollmentForm {
+            secret: &secret,
+            username: &session.username,
+            error: Some("That code didn't work. Please try again."),
+        }
+        .render(),
+        None => TwoFactorSettings {
+            status: get_status(&db, &session).await?,
+            message: None,
+        }
+        .render(),
+    })
+}
+
+pub async fn disable_two_factor(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(CodePayload { code }): Form<CodePayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "disable two-factor")?;
+    let is_verified = totp::verify(&db, session.user_id, &code).await?;
+    let message = if is_verified {
+        totp::disable(&db, session.user_id).await?;
+        "Two-factor authentication is now off."
+    } else {
+        "That code didn't work. Please try again."
+    };
+    let mut response = TwoFactorSettings {
+        status: get_status(&db, &session).await?,
+        message: Some(message),
+    }
+    .render()
+    .into_response();
+    if is_verified {
+        response.extensions_mut().insert(AttemptSucceeded);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_login_token() {
+        std::env::set_var("SESSION_SECRET", "foo");
+        let now = utc_now();
+        let token = sign_login_token(42, now.timestamp() + 60);
+        assert_eq!(verify_login_token(&token, now), Some(42));
+        assert_eq!(
+            verify_login_token(&token, now + chrono::Duration::minutes(2)),
+            None
+        );
+        let forged = token.replacen("42.", "43.", 1);
+        assert_eq!(verify_login_token(&forged, now), None);
+        assert_eq!(verify_login_token("42", now), None);
+    }
+}
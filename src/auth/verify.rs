@@ -0,0 +1,222 @@
+//! Email verification. New users are sent a link, similar to a password reset
+//! link, which marks their email address as verified. Password resets are only
+//! sent to verified addresses, so that a typo at registration can't send a
+//! reset link to a stranger.
+
+use crate::{
+    config::{
+        BASE_URL, VERIFICATION_RESEND_COOLDOWN_MINUTES,
+        VERIFICATION_TOKEN_TIMEOUT_HOURS,
+    },
+    email::{Email, EmailButton, EmailTemplate},
+    prelude::*,
+    smtp::send_email,
+};
+
+struct VerificationEmail {
+    link: Route,
+}
+impl Component for VerificationEmail {
+    fn render(&self) -> String {
+        let button = EmailButton {
+            href: &self.link,
+            text: "Verify your email",
+        }
+        .render();
+        format!(
+            r#"
+            <p>
+                Welcome to beancount.bot! Please confirm that this is your
+                email address, so that we can help you if you ever forget
+                your password.
+            </p>
+            {button}
+            <p>
+                This link will expire in {VERIFICATION_TOKEN_TIMEOUT_HOURS}
+                hours. If you didn't create an account, you can ignore this
+                email.
+            </p>
+            "#
+        )
+    }
+}
+impl EmailTemplate for VerificationEmail {
+    fn subject(&self) -> String {
+        "Verify your email for beancount.bot".into()
+    }
+    fn plaintext(&self) -> String {
+        let link = &self.link;
+        format!("Welcome to beancount.bot! Visit {BASE_URL}{link} to verify your email address. This link will expire in {VERIFICATION_TOKEN_TIMEOUT_HOURS} hours. If you didn't create an account, you can ignore this email.")
+    }
+}
+
+/// Replaces any outstanding verification link for the user with a new one,
+/// and emails it to them.
+pub async fn send_verification_email(
+    db: &PgPool,
+    user_id: i32,
+    email: &str,
+) -> Aresult<()> {
+    let slug = uuid::Uuid::new_v4().to_string();
+    query!(
+        "insert into email_verification_link (user_id, slug)
+        values ($1, $2)
+        on conflict (user_id)
+        do update set slug = $2, created_at = now()",
+        user_id,
+        slug
+    )
+    .execute(db)
+    .await?;
+    let link = Route::VerifyEmail(Some(slug));
+    send_email(&Email::new(email, &VerificationEmail { link })).await
+}
+
+/// `last_sent` is when the outstanding verification link was created.
+fn can_resend(last_sent: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    last_sent.is_none_or(|sent| {
+        now.signed_duration_since(sent).num_minutes()
+            >= VERIFICATION_RESEND_COOLDOWN_MINUTES
+    })
+}
+
+pub struct VerifyEmailBanner<'a> {
+    pub email: &'a str,
+    /// Feedback after the user asks for another email.
+    pub message: Option<&'a str>,
+}
+impl Component for VerifyEmailBanner<'_> {
+    fn render(&self) -> String {
+        let email = clean(self.email);
+        let resend = Route::ResendVerificationEmail;
+        let message = self.message.map_or("".into(), |msg| {
+            format!(r#"<p class="text-sm italic">{msg}</p>"#)
+        });
+        format!(
+            r#"
+            <div
+                hx-target="this"
+                hx-swap="outerHTML"
+                class="flex flex-col gap-1 rounded p-2 bg-yellow-100 text-black"
+            >
+                <p>
+                    Please verify your email address, {email}. Until you do,
+                    we can't send you a password reset link.
+                </p>
+                <button
+                    hx-post="{resend}"
+                    class="self-start bg-yellow-200 hover:bg-yellow-300 rounded p-1 text-sm"
+                >
+                    Resend verification email
+                </button>
+                {message}
+            </div>
+            "#
+        )
+    }
+}
+
+pub async fn resend_verification_email(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session =
+        Session::from_headers_err(&headers, "resend verification email")?;
+    let user = session.get_user(&db).await?;
+    if user.email_verified {
+        return Ok("".to_string());
+    }
+    let last_sent = query!(
+        "select created_at from email_verification_link where user_id = $1",
+        user.id
+    )
+    .fetch_optional(&db)
+    .await?
+    .map(|row| row.created_at);
+    let message = if can_resend(last_sent, utc_now()) {
+        send_verification_email(&db, user.id, &user.email).await?;
+        "We sent you another verification email."
+    } else {
+        "We just sent you a verification email. Check your inbox, or try again in a few minutes."
+    };
+    Ok(VerifyEmailBanner {
+        email: &user.email,
+        message: Some(message),
+    }
+    .render())
+}
+
+struct VerificationResult {
+    verified: bool,
+}
+impl Component for VerificationResult {
+    fn render(&self) -> String {
+        let home = Route::UserHome;
+        let message = if self.verified {
+            "Thanks! Your email address is verified."
+        } else {
+            "This verification link is invalid or has expired. Log in to send yourself a new one."
+        };
+        format!(
+            r#"
+            <div class="flex flex-col gap-2 max-w-prose">
+                <h1 class="text-xl font-extrabold">Email Verification</h1>
+                <p>{message}</p>
+                <a class="link" href="{home}">Continue to your dashboard</a>
+            </div>
+            "#
+        )
+    }
+}
+
+pub async fn verify_email(
+    State(AppState { db, .. }): State<AppState>,
+    Path(slug): Path<String>,
+) -> Result<impl IntoResponse, ServerError> {
+    let token = query!(
+        "delete from email_verification_link
+        where slug = $1
+        returning user_id, created_at",
+        slug
+    )
+    .fetch_optional(&db)
+    .await?;
+    let verified = match token {
+        Some(token)
+            if utc_now()
+                .signed_duration_since(token.created_at)
+                .num_hours()
+                < VERIFICATION_TOKEN_TIMEOUT_HOURS =>
+        {
+            query!(
+                "update users set email_verified = true where id = $1",
+                token.user_id
+            )
+            .execute(&db)
+            .await?;
+            true
+        }
+        _ => false,
+    };
+    Ok(Page {
+        title: "Email Verification",
+        children: &PageContainer {
+            children: &VerificationResult { verified },
+        },
+    }
+    .render())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_can_resend() {
+        let now = utc_now();
+        assert!(can_resend(None, now));
+        assert!(!can_resend(Some(now), now));
+        assert!(!can_resend(Some(now - chrono::Duration::minutes(4)), now));
+        assert!(can_resend(Some(now - chrono::Duration::minutes(5)), now));
+    }
+}
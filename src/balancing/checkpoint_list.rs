@@ -129,7 +129,7 @@ impl Component for CheckpointList<'_> {
 }
 
 pub async fn checkpoint_list(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers_err(&headers, "checkpoint list")?;
@@ -154,7 +154,7 @@ pub async fn checkpoint_list(
 }
 
 pub async fn create_checkpoint(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Form(checkpoint): Form<Checkpoint>,
 ) -> Result<impl IntoResponse, ServerError> {
@@ -183,7 +183,7 @@ pub async fn create_checkpoint(
 }
 
 pub async fn delete_checkpoint(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Query(checkpoint): Query<Checkpoint>,
 ) -> Result<impl IntoResponse, ServerError> {
@@ -29,6 +29,77 @@ pub struct BalancingEvent<'a> {
     calories_to_be_applied_at_a_later_date: i32,
     food_items: &'a [FoodItem],
     user_tz: Tz,
+    /// Present if the user has a protein target.
+    protein: Option<ProteinCarryover>,
+}
+
+/// Protein deficits are carried forward like calories, but protein surplus
+/// is not; eating extra protein one day doesn't lower tomorrow's target.
+#[derive(Debug)]
+pub struct ProteinCarryover {
+    /// The protein target that the user has set, in grams.
+    user_input_goal: i32,
+    previous_goal: i32,
+    previous_excess: i32,
+    consumed_during_period: i32,
+    new_goal: i32,
+    /// Protein beyond the limits (which are scaled from the calorie limits)
+    /// goes into this bucket.
+    to_be_applied_at_a_later_date: i32,
+}
+
+impl Component for ProteinCarryover {
+    fn render(&self) -> String {
+        let prev = self.previous_goal;
+        let consumed = self.consumed_during_period;
+        let user_goal = self.user_input_goal;
+        let deficit = max(0, prev.saturating_sub(consumed));
+        let new = self.new_goal;
+        let extra = self.to_be_applied_at_a_later_date;
+        let prev_extra = if self.previous_excess == 0 {
+            "".to_string()
+        } else {
+            format!(
+                "<p>+ {} <sub>rollover from previous days</sub></p>",
+                self.previous_excess
+            )
+        };
+        format!(
+            r#"
+            <div class="font-mono mt-2">
+                <p>{prev}g <sub>starting protein goal</sub></p>
+                <p>- {consumed}g <sub>protein consumed</sub></p>
+                <p>= {deficit}g <sub>deficit carried over</sub></p>
+                {prev_extra}
+                <p>+ {user_goal}g <sub>your goal</sub></p>
+                <hr class="my-2" />
+                <p>=> {new}g <sub>new protein goal</sub></p>
+                <p>=> {extra}g <sub>protein exceeding limits</sub></p>
+            </div>
+            "#
+        )
+    }
+}
+
+/// Protein limits are derived from the calorie limits, so that the protein
+/// goal can stretch as far as the calorie goal can.
+fn scale_limit(
+    calorie_limit: i32,
+    calorie_goal: i32,
+    protein_goal: i32,
+) -> i32 {
+    if calorie_goal <= 0 || calorie_limit == i32::MAX {
+        return calorie_limit;
+    }
+    let scaled = i64::from(protein_goal) * i64::from(calorie_limit)
+        / i64::from(calorie_goal);
+    scaled.try_into().unwrap_or(i32::MAX)
+}
+
+/// Protein is carried forward in `i64`, so that a large target plus a
+/// deficit can't overflow; this brings the result back into `i32`.
+fn saturate(value: i64) -> i32 {
+    value.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
 }
 
 impl Component for BalancingEvent<'_> {
@@ -71,6 +142,17 @@ impl Component for BalancingEvent<'_> {
         let extra = self.calories_to_be_applied_at_a_later_date;
         let naive_goal =
             self.new_calorie_goal + self.calories_to_be_applied_at_a_later_date;
+        let protein = self.protein.as_ref().map_or("".to_string(), |p| {
+            let carryover = p.render();
+            format!(
+                r#"
+                <details>
+                    <summary>Protein</summary>
+                    {carryover}
+                </details>
+                "#
+            )
+        });
         let abs_prev_extra = self.previous_excess_calories.abs();
         let prev_extra = if self.previous_excess_calories == 0 {
             "".to_string()
@@ -93,6 +175,7 @@ impl Component for BalancingEvent<'_> {
                     <p>=> {new} <sub>{new_goal_description}</sub></p>
                     <p>=> {extra} <sub>calories exceeding limits</sub></p>
                 </div>
+                {protein}
                 {food_container}
             </div>
             "#
@@ -104,16 +187,19 @@ impl Component for BalancingEvent<'_> {
 pub struct BalancedCaloriesResult<'a> {
     /// The net calorie goal post-balancing
     pub current_calorie_goal: i32,
+    /// The net protein goal post-balancing, if the user has a protein target.
+    pub current_protein_goal: Option<i32>,
     pub details: Vec<BalancingEvent<'a>>,
 }
 
-/// `food` must be provided sorted by date.
+/// `food` must be provided sorted by date. `protein_goal` is in grams.
 pub fn compute_balancing(
     now: DateTime<Utc>,
     user_timezone: Tz,
     calorie_goal: i32,
     max_calories: Option<i32>,
     min_calories: Option<i32>,
+    protein_goal: Option<i32>,
     food_items: &[FoodItem],
 ) -> BalancedCaloriesResult<'_> {
     let max_calories = max_calories.unwrap_or(i32::MAX);
@@ -142,6 +228,7 @@ pub fn compute_balancing(
     let mut food_ptr = 0;
     while date < now {
         let mut calories_consumed = 0;
+        let mut protein_consumed = 0_i64;
         let this_day_slice_start = food_ptr;
         for food in food_items[food_ptr..].iter() {
             let offset_from_date =
@@ -150,6 +237,7 @@ pub fn compute_balancing(
                 && offset_from_date < Duration::days(1)
             {
                 calories_consumed += food.details.calories;
+                protein_consumed += i64::from(food.details.protein_grams);
                 food_ptr += 1;
             }
         }
@@ -170,6 +258,34 @@ pub fn compute_balancing(
             min(max_calories, max(min_calories, goal_with_remainder));
         let new_remainder = goal_with_remainder - limited_goal;
 
+        let protein = protein_goal.map(|protein_goal| {
+            let previous = details.last().and_then(|e| e.protein.as_ref());
+            let previous_goal = previous.map_or(protein_goal, |p| p.new_goal);
+            let previous_excess =
+                previous.map_or(0, |p| p.to_be_applied_at_a_later_date);
+            let deficit = max(0, i64::from(previous_goal) - protein_consumed);
+            let goal_with_remainder =
+                i64::from(protein_goal) + deficit + i64::from(previous_excess);
+            let min_protein =
+                scale_limit(min_calories, calorie_goal, protein_goal);
+            let max_protein =
+                scale_limit(max_calories, calorie_goal, protein_goal);
+            let limited_goal = min(
+                i64::from(max_protein),
+                max(i64::from(min_protein), goal_with_remainder),
+            );
+            ProteinCarryover {
+                user_input_goal: protein_goal,
+                previous_goal,
+                previous_excess,
+                consumed_during_period: saturate(protein_consumed),
+                new_goal: saturate(limited_goal),
+                to_be_applied_at_a_later_date: saturate(
+                    goal_with_remainder - limited_goal,
+                ),
+            }
+        });
+
         details.push(BalancingEvent {
             start: date.with_timezone(&Utc),
             end: (date + Duration::days(1)).with_timezone(&Utc),
@@ -181,6 +297,7 @@ pub fn compute_balancing(
             calories_to_be_applied_at_a_later_date: new_remainder,
             food_items: &food_items[this_day_slice_start..food_ptr],
             user_tz: user_timezone,
+            protein,
         });
         date += Duration::days(1);
     }
@@ -193,11 +310,17 @@ pub fn compute_balancing(
             std::cmp::Ordering::Less
         }
     });
+    let latest_complete_day = details
+        .iter()
+        .find(|i| i.end < utc_now().with_timezone(&user_timezone));
     BalancedCaloriesResult {
-        current_calorie_goal: details
-            .iter()
-            .find(|i| i.end < utc_now().with_timezone(&user_timezone))
+        current_calorie_goal: latest_complete_day
             .map_or(calorie_goal, |d| d.new_calorie_goal),
+        current_protein_goal: protein_goal.map(|protein_goal| {
+            latest_complete_day
+                .and_then(|d| d.protein.as_ref())
+                .map_or(protein_goal, |p| p.new_goal)
+        }),
         details,
     }
 }
@@ -224,8 +347,15 @@ mod test {
                         .expect("can convert days to std"),
             },
         }];
-        let result =
-            compute_balancing(utc_now(), Tz::UTC, 2000, None, None, &history);
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            None,
+            None,
+            None,
+            &history,
+        );
         assert_eq!(result.current_calorie_goal, 1900);
     }
     #[test]
@@ -264,8 +394,15 @@ mod test {
                 },
             },
         ];
-        let result =
-            compute_balancing(utc_now(), Tz::UTC, 2000, None, None, &history);
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            None,
+            None,
+            None,
+            &history,
+        );
         assert_eq!(result.current_calorie_goal, 1800);
     }
     #[test]
@@ -306,7 +443,7 @@ mod test {
             },
         ];
         let result =
-            compute_balancing(now, Tz::UTC, 2000, None, None, &history);
+            compute_balancing(now, Tz::UTC, 2000, None, None, None, &history);
         assert_eq!(result.current_calorie_goal, 3800);
     }
     #[test]
@@ -327,8 +464,15 @@ mod test {
                         .expect("can convert days to std"),
             },
         }];
-        let result =
-            compute_balancing(utc_now(), Tz::UTC, 2000, None, None, &history);
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            None,
+            None,
+            None,
+            &history,
+        );
         assert_eq!(result.current_calorie_goal, 2100);
     }
     #[test]
@@ -369,7 +513,7 @@ mod test {
             },
         ];
         let result =
-            compute_balancing(now, Tz::UTC, 2000, None, None, &history);
+            compute_balancing(now, Tz::UTC, 2000, None, None, None, &history);
         // If we skip a day, we want an entry to exist for the skipped day,
         // which will show zero calories consumed.
         let skipped_day = result.details.iter().find(|e| {
@@ -405,8 +549,15 @@ mod test {
                         .expect("can convert days to std"),
             },
         }];
-        let result =
-            compute_balancing(now, Tz::UTC, 2000, Some(2200), None, &history);
+        let result = compute_balancing(
+            now,
+            Tz::UTC,
+            2000,
+            Some(2200),
+            None,
+            None,
+            &history,
+        );
         assert_eq!(result.current_calorie_goal, 2200);
         assert_eq!(
             result
@@ -436,8 +587,15 @@ mod test {
                         .expect("can convert days to std"),
             },
         }];
-        let result =
-            compute_balancing(now, Tz::UTC, 2000, None, Some(1800), &history);
+        let result = compute_balancing(
+            now,
+            Tz::UTC,
+            2000,
+            None,
+            Some(1800),
+            None,
+            &history,
+        );
         assert_eq!(result.current_calorie_goal, 1800);
         assert_eq!(
             result
@@ -539,6 +697,7 @@ mod test {
             2000,
             Some(2200),
             Some(1800),
+            None,
             &history,
         );
         // This is the day that we eat 2400 calories. Since our goal is 2000,
@@ -556,4 +715,122 @@ mod test {
 
         assert_eq!(result.current_calorie_goal, 2200);
     }
+    fn protein_history(days_ago: &[(i64, i32)]) -> Vec<FoodItem> {
+        days_ago
+            .iter()
+            .map(|(days, protein_grams)| FoodItem {
+                id: 1,
+                eaten_event_id: 1,
+                hide_calories: false,
+                details: FoodItemDetails {
+                    calories: 2000,
+                    fat_grams: 0,
+                    protein_grams: *protein_grams,
+                    carbohydrates_grams: 0,
+                    food_name: "test".into(),
+                    eaten_at: utc_now()
+                        - Duration::days(*days)
+                            .to_std()
+                            .expect("can convert days to std"),
+                },
+            })
+            .collect()
+    }
+    #[test]
+    fn test_protein_deficit_carries_forward() {
+        let history = protein_history(&[(2, 60), (1, 100)]);
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            None,
+            None,
+            Some(100),
+            &history,
+        );
+        // 40g short two days ago makes yesterday's goal 140g, and eating
+        // 100g yesterday leaves another 40g deficit.
+        assert_eq!(result.current_protein_goal, Some(140));
+        assert_eq!(result.current_calorie_goal, 2000);
+    }
+    #[test]
+    fn test_protein_surplus_does_not_carry_forward() {
+        let history = protein_history(&[(1, 180)]);
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            None,
+            None,
+            Some(100),
+            &history,
+        );
+        assert_eq!(result.current_protein_goal, Some(100));
+    }
+    #[test]
+    fn test_protein_limit_scales_with_calorie_limit() {
+        let history = protein_history(&[(2, 40), (1, 110)]);
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            Some(2200),
+            None,
+            Some(100),
+            &history,
+        );
+        // A 60g deficit is clamped to a 110g goal (10% above the target,
+        // like the calorie limit), and the other 50g is applied later.
+        let clamped_day = &result.details[2];
+        let protein = clamped_day.protein.as_ref().unwrap();
+        assert_eq!(protein.new_goal, 110);
+        assert_eq!(protein.to_be_applied_at_a_later_date, 50);
+        // Yesterday's target of 110g was met, so the 50g rolls in and is
+        // clamped again.
+        assert_eq!(result.current_protein_goal, Some(110));
+        assert_eq!(
+            result.details[1]
+                .protein
+                .as_ref()
+                .unwrap()
+                .to_be_applied_at_a_later_date,
+            40
+        );
+    }
+    #[test]
+    fn test_large_protein_goal_does_not_overflow() {
+        let history = protein_history(&[(3, 0), (2, 0), (1, 0)]);
+        let protein_goal = i32::MAX / 2;
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            None,
+            None,
+            Some(protein_goal),
+            &history,
+        );
+        // Without a calorie limit, there's no protein limit either, so the
+        // deficit piles up until it saturates.
+        assert_eq!(result.current_protein_goal, Some(i32::MAX));
+        let protein = result.details[0].protein.as_ref().unwrap();
+        assert_eq!(protein.user_input_goal, protein_goal);
+        assert!(protein.new_goal > 0);
+        assert!(protein.render().contains(&i32::MAX.to_string()));
+    }
+    #[test]
+    fn test_no_protein_goal() {
+        let history = protein_history(&[(1, 10)]);
+        let result = compute_balancing(
+            utc_now(),
+            Tz::UTC,
+            2000,
+            None,
+            None,
+            None,
+            &history,
+        );
+        assert_eq!(result.current_protein_goal, None);
+        assert!(result.details.iter().all(|d| d.protein.is_none()));
+    }
 }
@@ -1,6 +1,7 @@
 use super::compute_balancing::{compute_balancing, BalancedCaloriesResult};
 use crate::{
     count_chat::{FoodItem, FoodItemDetails},
+    preferences::MacroGrams,
     prelude::*,
 };
 
@@ -112,44 +113,74 @@ pub async fn get_relevant_food(
     .await?)
 }
 
+/// The user's goals for today, after balancing (if it's enabled).
+pub struct CurrentGoal {
+    pub calories: Option<i32>,
+    pub macros: MacroGrams,
+}
+
+/// Protein targets are balanced on their own. Other macro targets which are
+/// set as a percentage just follow the balanced calorie goal.
 pub async fn get_current_goal(
     db: impl PgExecutor<'_>,
     user_id: i32,
     preferences: &UserPreference,
-) -> Aresult<Option<i32>> {
+) -> Aresult<CurrentGoal> {
     if !preferences.calorie_balancing_enabled {
-        return Ok(preferences.caloric_intake_goal);
+        return Ok(CurrentGoal {
+            calories: preferences.caloric_intake_goal,
+            macros: preferences
+                .macro_targets
+                .to_grams(preferences.caloric_intake_goal),
+        });
     };
+    let calorie_goal = preferences
+        .caloric_intake_goal
+        .ok_or(Error::msg("user does not have caloric intake goal"))?;
     let relevant_food = get_relevant_food(db, user_id, preferences).await?;
     let balancing_history = compute_balancing(
         utc_now(),
         preferences.timezone,
-        preferences
-            .caloric_intake_goal
-            .ok_or(Error::msg("user does not have caloric intake goal"))?,
+        calorie_goal,
         preferences.calorie_balancing_max_calories,
         preferences.calorie_balancing_min_calories,
+        preferences
+            .macro_targets
+            .to_grams(Some(calorie_goal))
+            .protein,
         &relevant_food,
     );
-    Ok(Some(balancing_history.current_calorie_goal))
+    let calories = balancing_history.current_calorie_goal;
+    Ok(CurrentGoal {
+        calories: Some(calories),
+        macros: MacroGrams {
+            protein: balancing_history.current_protein_goal,
+            ..preferences.macro_targets.to_grams(Some(calories))
+        },
+    })
 }
 
 pub async fn history(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers_err(&headers, "balancing history")?;
     let preferences = session.get_preferences(&db).await?;
     let relevant_food =
         get_relevant_food(&db, session.user_id, &preferences).await?;
+    let calorie_goal = preferences
+        .caloric_intake_goal
+        .expect("user has caloric intake goal");
     let balancing_history = compute_balancing(
         utc_now(),
         preferences.timezone,
-        preferences
-            .caloric_intake_goal
-            .expect("user has caloric intake goal"),
+        calorie_goal,
         preferences.calorie_balancing_max_calories,
         preferences.calorie_balancing_min_calories,
+        preferences
+            .macro_targets
+            .to_grams(Some(calorie_goal))
+            .protein,
         &relevant_food,
     );
 
@@ -5,4 +5,4 @@ mod history_page;
 pub use checkpoint_list::{
     checkpoint_list, create_checkpoint, delete_checkpoint,
 };
-pub use history_page::{get_current_goal, history};
+pub use history_page::{get_current_goal, history, CurrentGoal};
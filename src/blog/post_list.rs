@@ -26,7 +26,7 @@ pub struct PostListParams {
 }
 
 pub async fn post_list(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     Query(PostListParams { page }): Query<PostListParams>,
 ) -> Result<impl IntoResponse, ServerError> {
     let limit: i64 = 100;
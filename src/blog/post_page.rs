@@ -244,7 +244,7 @@ pub async fn post_page(
     Path(id): Path<i32>,
     headers: HeaderMap,
     Query(PostPageQuery { comment_page }): Query<PostPageQuery>,
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers(&headers);
     let comment_limit: i64 = 100;
@@ -319,7 +319,7 @@ pub struct CommentForm {
 }
 
 pub async fn handle_comment_submission(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Form(form): Form<CommentForm>,
 ) -> Result<impl IntoResponse, ServerError> {
@@ -345,7 +345,7 @@ pub async fn handle_comment_submission(
 }
 
 pub async fn handle_delete_comment(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, ServerError> {
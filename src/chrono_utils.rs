@@ -58,6 +58,31 @@ pub fn fmt_date(datetime: &DateTime<Utc>, user_timezone: Tz) -> String {
     }
 }
 
+/// The moment that `date` began in the user's timezone. If midnight does not
+/// exist on that date (DST transitions), we'll fall back to midnight UTC.
+pub fn start_of_date(date: NaiveDate, user_timezone: Tz) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    user_timezone
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map_or(midnight.and_utc(), |dt| dt.to_utc())
+}
+
+/// The moment that the current day began in the user's timezone.
+pub fn start_of_today(user_timezone: Tz) -> DateTime<Utc> {
+    let today = utc_now().with_timezone(&user_timezone).date_naive();
+    start_of_date(today, user_timezone)
+}
+
+/// The moment that the current month began in the user's timezone.
+pub fn start_of_month(user_timezone: Tz) -> DateTime<Utc> {
+    let today = utc_now().with_timezone(&user_timezone).date_naive();
+    start_of_date(
+        today.with_day(1).expect("every month has a first day"),
+        user_timezone,
+    )
+}
+
 pub fn as_days(duration: Duration) -> u64 {
     duration.as_secs() / 24 / 60 / 60
 }
@@ -188,6 +213,36 @@ mod test {
         assert_eq!(text, "Yesterday");
     }
 
+    #[test]
+    fn test_start_of_today() {
+        // utc_now is 2024-06-29T23:47:33Z, which is 19:47 in New York.
+        assert_eq!(
+            start_of_today(Tz::UTC).to_rfc3339(),
+            "2024-06-29T00:00:00+00:00"
+        );
+        assert_eq!(
+            start_of_today(Tz::America__New_York).to_rfc3339(),
+            "2024-06-29T04:00:00+00:00"
+        );
+        // ...and it's already tomorrow in Tokyo.
+        assert_eq!(
+            start_of_today(Tz::Asia__Tokyo).to_rfc3339(),
+            "2024-06-29T15:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_start_of_month() {
+        assert_eq!(
+            start_of_month(Tz::America__New_York).to_rfc3339(),
+            "2024-06-01T04:00:00+00:00"
+        );
+        assert_eq!(
+            start_of_month(Tz::Asia__Tokyo).to_rfc3339(),
+            "2024-05-31T15:00:00+00:00"
+        );
+    }
+
     #[test]
     fn test_fmt_old_date() {
         let date =
@@ -6,7 +6,10 @@
 // are and clippy knows more than me, maybe not.
 #![allow(clippy::let_and_return)]
 
-use super::{auth, chrono_utils, count_chat, metrics, models, prelude::*};
+use super::{
+    auth, balancing, chrono_utils, count_chat, metrics, models, prelude::*,
+    weight,
+};
 
 #[cfg(feature = "live_reload")]
 const LIVE_RELOAD_SCRIPT: &str = r#"<script>
@@ -339,34 +342,14 @@ impl Component for Brand {
     }
 }
 
-pub struct ExternalLink<'a> {
-    pub href: &'a str,
-    pub children: Box<dyn Component>,
-}
-impl Component for ExternalLink<'_> {
-    fn render(&self) -> String {
-        let children = self.children.render();
-        let href = clean(self.href);
-        format!(
-            r#"
-            <a href={href}>
-                {children}
-                <svg xmlns="http://www.w3.org/2000/svg" fill="none" viewBox="0 0 24 24" stroke-width="1.5" stroke="currentColor" class="w-3 h-3 inline">
-                  <path stroke-linecap="round" stroke-linejoin="round" d="M4.5 19.5l15-15m0 0H8.25m11.25 0v11.25" />
-                </svg>
-            </a>
-            "#
-        )
-    }
-}
-
 pub struct UserHome<'a> {
     pub user: &'a models::User,
     pub preferences: UserPreference,
     pub food_items: &'a Vec<count_chat::FoodItem>,
     pub macros: &'a metrics::Macros,
     pub subscription_type: SubscriptionTypes,
-    pub caloric_intake_goal: Option<i32>,
+    pub current_goal: balancing::CurrentGoal,
+    pub weight_unit: weight::WeightUnit,
 }
 impl Component for UserHome<'_> {
     fn render(&self) -> String {
@@ -375,7 +358,7 @@ impl Component for UserHome<'_> {
         {
             metrics::MacroPlaceholder {}.render()
         } else {
-            self.macros.render_status(self.caloric_intake_goal)
+            self.macros.render_status(&self.current_goal)
         };
         let profile = ProfileChip {
             username: &self.user.username,
@@ -384,6 +367,21 @@ impl Component for UserHome<'_> {
             user_created_time: self.user.created_at,
         }
         .render();
+        let verify_email =
+            if self.user.email_verified || auth::is_anon(&self.user.username) {
+                "".into()
+            } else {
+                auth::VerifyEmailBanner {
+                    email: &self.user.email,
+                    message: None,
+                }
+                .render()
+            };
+        let weight = weight::WeightEntryForm {
+            saved: None,
+            unit: self.weight_unit,
+        }
+        .render();
         let chat = count_chat::ChatContainer {
             food_items: self.food_items,
             preferences: &self.preferences,
@@ -396,8 +394,10 @@ impl Component for UserHome<'_> {
         format!(
             r#"
             <div class="flex flex-col gap-2">
+                {verify_email}
                 {profile}
                 {macros}
+                {weight}
                 {chat}
             </div>
             "#
@@ -420,6 +420,28 @@ impl Component for ProfileChip<'_> {
         };
         let timezone = self.timezone;
         let preferences = Route::UserPreference;
+        let saved_meals = Route::SavedMeals;
+        let trends = Route::Trends;
+        let devices = Route::Devices;
+        // Anonymous users can't turn on two-factor authentication.
+        let two_factor = if auth::is_anon(self.username) {
+            "".into()
+        } else {
+            let two_factor = Route::TwoFactor;
+            format!(
+                r#"
+                <a class="inline" href="{two_factor}" tabindex="-1">
+                    <button
+                        style="margin-left: auto"
+                        class="text-xs p-1 bg-slate-100 hover:bg-slate-200
+                        rounded-full text-black"
+                    >
+                        Two-Factor
+                    </button>
+                </a>
+                "#
+            )
+        };
         let trial_warning = if let SubscriptionTypes::FreeTrial(duration) =
             self.subscription_type
         {
@@ -528,6 +550,34 @@ impl Component for ProfileChip<'_> {
                             Goals & Preferences
                         </button>
                     </a>
+                    <a class="inline" href="{saved_meals}" tabindex="-1">
+                        <button
+                            style="margin-left: auto"
+                            class="text-xs p-1 bg-indigo-100 hover:bg-indigo-200
+                            rounded-full text-black"
+                        >
+                            Saved Meals
+                        </button>
+                    </a>
+                    <a class="inline" href="{trends}" tabindex="-1">
+                        <button
+                            style="margin-left: auto"
+                            class="text-xs p-1 bg-amber-100 hover:bg-amber-200
+                            rounded-full text-black"
+                        >
+                            Trends
+                        </button>
+                    </a>
+                    <a class="inline" href="{devices}" tabindex="-1">
+                        <button
+                            style="margin-left: auto"
+                            class="text-xs p-1 bg-slate-100 hover:bg-slate-200
+                            rounded-full text-black"
+                        >
+                            Devices
+                        </button>
+                    </a>
+                    {two_factor}
                     {billing_portal_button}
                 </div>
                 <p class="text-xs inline-block">Timezone: {timezone}</p>
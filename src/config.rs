@@ -1,22 +1,83 @@
 use std::time::Duration;
 
-/// This is for all authentication sessions; users will need to log in again
-/// every 30 days, since we basically have JWT authentication.
-pub const SESSION_EXPIRY_TIME_DAYS: i64 = 30;
+/// Users will need to log in again every 30 days.
+pub const SESSION_EXPIRY_TIME_DAYS: u64 = 30;
+
+/// Anonymous users don't know their password until they convert into a
+/// registered user, so we don't want to surprise log them out.
+pub const ANON_SESSION_EXPIRY_TIME_DAYS: u64 = 365;
+
+/// Sessions which were recently found in the `session` table aren't checked
+/// again until this much time has passed. Revoking a session on another
+/// server instance may take this long to take effect.
+pub const SESSION_CACHE_TTL: Duration = Duration::from_secs(60);
 
 /// Password reset links will expire after 15 minutes.
 pub const RESET_TOKEN_TIMEOUT_MINUTES: i64 = 15;
 
+/// After entering their password, users with two-factor authentication have
+/// this long to enter a code.
+pub const TWO_FACTOR_LOGIN_TIMEOUT_MINUTES: i64 = 5;
+
+/// Email verification links will expire after 1 day.
+pub const VERIFICATION_TOKEN_TIMEOUT_HOURS: i64 = 24;
+
+/// Users can ask for another verification email once this many minutes have
+/// passed since the last one.
+pub const VERIFICATION_RESEND_COOLDOWN_MINUTES: i64 = 5;
+
+/// Failed logins and password reset requests are allowed this many times
+/// for each IP address, username, or email before they start to be locked
+/// out.
+pub const RATE_LIMIT_FREE_ATTEMPTS: i32 = 5;
+
+/// Each failure after the free attempts doubles the lockout, starting at one
+/// minute, up to this limit. Anyone can lock out a username by guessing
+/// wrong, so the limit is kept short.
+pub const RATE_LIMIT_MAX_LOCKOUT_MINUTES: i64 = 60;
+
+/// Failures are forgotten when there haven't been any for this many hours.
+pub const RATE_LIMIT_RESET_HOURS: i64 = 24;
+
 #[cfg(not(feature = "localhost_base_url"))]
 pub const BASE_URL: &str = "https://beancount.bot";
 
 #[cfg(feature = "localhost_base_url")]
 pub const BASE_URL: &str = "http://localhost:8000";
 
+/// Upper bound for a user's caloric intake goal. Nobody needs to eat more
+/// than this, and it keeps calculations based on the goal from overflowing.
+pub const MAX_CALORIC_INTAKE_GOAL: i32 = 20_000;
+
+/// Upper bound for each macro target, when targets are set in grams. Like
+/// [MAX_CALORIC_INTAKE_GOAL], this keeps calculations based on the target
+/// from overflowing.
+pub const MAX_MACRO_TARGET_GRAMS: i32 = 2000;
+
 /// Messages which exceed this length limit will not be forwarded to OpenAI,
 /// and will instead provide the user with an error message.
 pub const CHAT_MAX_LEN: usize = 200;
 
+/// Upper bound on the length of each LLM response. A structured estimate
+/// takes about 80 tokens per food, so this leaves room for one estimate per
+/// food in even the longest chat.
+pub const LLM_MAX_COMPLETION_TOKENS: i32 = 1000;
+
+/// When the LLM asks a follow-up question, the user can answer it this many
+/// times before we ask them to start over with a new description.
+pub const CHAT_MAX_FOLLOW_UPS: usize = 3;
+
+/// Default OpenAI token budgets for each user. These can be overridden for
+/// individual users from the usage dashboard. A typical chat uses about 600
+/// tokens, most of which is the system prompt.
+pub const DAILY_TOKEN_QUOTA: i32 = 30_000;
+pub const MONTHLY_TOKEN_QUOTA: i32 = 300_000;
+
+/// OpenAI prices in USD per million tokens, used to estimate costs on the
+/// usage dashboard.
+pub const PROMPT_TOKEN_COST_PER_MILLION: f64 = 0.15;
+pub const COMPLETION_TOKEN_COST_PER_MILLION: f64 = 0.6;
+
 /// We advertise a 30-day free trial, but we'll make it 31 days in duration,
 /// since the clock starts as soon as registration happens and we want to
 /// ensure that everyone gets 30 full days. Consider -- in the moment after
@@ -38,3 +99,10 @@ pub const FOOD_PAGE_SIZE: u8 = 50;
 pub const MINIMUM_PASSWORD_LENGTH: u8 = 8;
 
 pub const ADMINISTRATOR_USER_IDS: [i32; 1] = [1];
+
+/// Email digests are sent at (or shortly after) this hour, in each user's
+/// local time, and cover the day or week which has just ended.
+pub const DIGEST_SEND_HOUR: u32 = 6;
+
+/// How often the background scheduler checks for digests which are due.
+pub const DIGEST_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
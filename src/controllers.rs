@@ -1,7 +1,7 @@
 use super::{
     auth::Session, balancing, chrono_utils, client_events, components,
     components::Component, count_chat, errors::ServerError, htmx, metrics,
-    models::AppState, stripe,
+    models::AppState, stripe, weight,
 };
 use anyhow::Result;
 use axum::{
@@ -147,7 +147,7 @@ pub async fn get_robots_txt() -> impl IntoResponse {
 }
 
 pub async fn user_home(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers_err(&headers, "user home")?;
@@ -155,16 +155,18 @@ pub async fn user_home(
         join![session.get_user(&db), session.get_preferences(&db)];
     let user = user?;
     let preferences = preferences?;
-    let (macros, meals, sub_type, caloric_intake_goal) = join![
+    let (macros, meals, sub_type, current_goal, weight_unit) = join![
         metrics::get_macros(&db, session.user_id, &preferences),
         count_chat::list_meals_op(&db, user.id, &preferences, 0),
         stripe::get_subscription_type(&db, user.id),
-        balancing::get_current_goal(&db, user.id, &preferences)
+        balancing::get_current_goal(&db, user.id, &preferences),
+        weight::get_weight_unit(&db, user.id)
     ];
     let macros = macros?;
     let meals = meals?;
     let sub_type = sub_type?;
-    let caloric_intake_goal = caloric_intake_goal?;
+    let current_goal = current_goal?;
+    let weight_unit = weight_unit?;
     let html = components::Page {
         title: "Home Page",
         children: &components::PageContainer {
@@ -174,7 +176,8 @@ pub async fn user_home(
                 macros: &macros,
                 preferences,
                 subscription_type: sub_type,
-                caloric_intake_goal,
+                current_goal,
+                weight_unit,
             },
         },
     }
@@ -184,7 +187,7 @@ pub async fn user_home(
 }
 
 pub async fn delete_food(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, ServerError> {
@@ -227,7 +230,7 @@ pub async fn delete_food(
 }
 
 pub async fn add_food_to_today(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Path(id): Path<i32>,
 ) -> Result<impl IntoResponse, ServerError> {
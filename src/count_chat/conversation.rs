@@ -0,0 +1,155 @@
+//! When the LLM asks a follow-up question, the user can answer it inline. The
+//! pending conversation is stored server-side (one per user), so that the
+//! whole history can be re-sent to the LLM with each answer.
+
+use super::llm::{ChatMessage, Role};
+use crate::{config::CHAT_MAX_FOLLOW_UPS, prelude::*};
+use sqlx::types::Json;
+
+pub struct Conversation {
+    /// The user's original food description, plus each of their answers.
+    /// This becomes the name of the food if it is saved.
+    pub food_name: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl Conversation {
+    pub fn new(food_description: &str) -> Self {
+        Self {
+            food_name: food_description.to_string(),
+            messages: vec![ChatMessage {
+                role: Role::User,
+                content: food_description.to_string(),
+            }],
+        }
+    }
+    pub fn push_llm_response(&mut self, message: &str) {
+        self.messages.push(ChatMessage {
+            role: Role::Assistant,
+            content: message.to_string(),
+        });
+    }
+    pub fn push_answer(&mut self, answer: &str) {
+        self.food_name = format!("{}, {answer}", self.food_name);
+        self.messages.push(ChatMessage {
+            role: Role::User,
+            content: answer.to_string(),
+        });
+    }
+    /// We'll only let the exchange go on for so long before asking the user
+    /// to start over.
+    pub fn can_follow_up(&self) -> bool {
+        let answer_count = self
+            .messages
+            .iter()
+            .filter(|m| m.role == Role::User)
+            .count()
+            .saturating_sub(1);
+        answer_count < CHAT_MAX_FOLLOW_UPS
+    }
+}
+
+pub async fn get_conversation(
+    db: impl PgExecutor<'_>,
+    user_id: i32,
+) -> Aresult<Option<Conversation>> {
+    struct Qres {
+        food_name: String,
+        messages: Json<Vec<ChatMessage>>,
+    }
+    Ok(query_as!(
+        Qres,
+        r#"select
+            food_name,
+            messages "messages: Json<Vec<ChatMessage>>"
+        from chat_conversation
+        where user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|row| Conversation {
+        food_name: row.food_name,
+        messages: row.messages.0,
+    }))
+}
+
+pub async fn save_conversation(
+    db: impl PgExecutor<'_>,
+    user_id: i32,
+    conversation: &Conversation,
+) -> Aresult<()> {
+    query!(
+        "insert into chat_conversation (user_id, food_name, messages)
+        values ($1, $2, $3)
+        on conflict (user_id)
+        do update set
+            food_name = excluded.food_name,
+            messages = excluded.messages,
+            updated_at = now()",
+        user_id,
+        conversation.food_name,
+        Json(&conversation.messages) as _
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub struct FollowUpForm;
+impl Component for FollowUpForm {
+    fn render(&self) -> String {
+        let route = Route::ChatFollowUp;
+        format!(
+            r#"
+            <form
+                class="flex flex-col gap-2 my-2"
+                hx-post="{route}"
+                hx-target="closest div[data-name='chat-turn']"
+            >
+                <label for="answer" class="text-sm">Answer the AI</label>
+                <input
+                    class="rounded dark:text-black"
+                    autocomplete="one-time-code"
+                    type="text"
+                    id="answer"
+                    name="answer"
+                    required
+                />
+                <button
+                    class="bg-emerald-100 dark:bg-emerald-700
+                    dark:hover:bg-emerald-800 hover:bg-emerald-200 p-1 rounded
+                    shadow"
+                >
+                    Reply
+                </button>
+            </form>
+            "#
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_follow_up_limit() {
+        let mut conversation = Conversation::new("sandwich");
+        for _ in 0..CHAT_MAX_FOLLOW_UPS {
+            assert!(conversation.can_follow_up());
+            conversation.push_llm_response("What kind of sandwich?");
+            conversation.push_answer("turkey");
+        }
+        assert!(!conversation.can_follow_up());
+    }
+
+    #[test]
+    fn test_answers_are_added_to_food_name() {
+        let mut conversation = Conversation::new("sandwich");
+        conversation.push_llm_response("What kind of sandwich?");
+        conversation.push_answer("turkey on rye");
+        assert_eq!(conversation.food_name, "sandwich, turkey on rye");
+        assert_eq!(conversation.messages.len(), 3);
+    }
+}
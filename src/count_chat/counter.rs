@@ -2,9 +2,15 @@
 //! are colocated here).
 
 use super::{
+    conversation::{
+        get_conversation, save_conversation, Conversation, FollowUpForm,
+    },
     food_card::{FoodCard, RenderingBehavior},
-    openai::OpenAI,
+    food_search::{search_previous_food, FoodSuggestions},
+    llm::LlmBackend,
+    llm_parse_response::FoodEstimate,
     prev_food_list::{FoodList, PrevDayFormActions, PreviousFood},
+    quota::{check_quota, OverQuota, QuotaStatus},
     FoodItem, FoodItemDetails,
 };
 use crate::{
@@ -131,11 +137,14 @@ impl Component for ChatUI<'_> {
 
 struct NewMealOptions<'a> {
     info: &'a FoodItemDetails,
+    /// Set when this is one of several candidates from a multi-item chat.
+    /// Adding a candidate removes its card, leaving the other candidates in
+    /// place.
+    is_candidate: bool,
 }
 impl Component for NewMealOptions<'_> {
     fn render(&self) -> String {
         let retry_route = Route::ChatForm;
-        let save_route = Route::SaveFood;
         let prev_day_route = Route::PreviousDayFood;
         let calories = self.info.calories;
         let protein = self.info.protein_grams;
@@ -143,6 +152,44 @@ impl Component for NewMealOptions<'_> {
         let fat = self.info.fat_grams;
         let eaten_at = self.info.eaten_at;
         let food_name = encode_quotes(&clean(&self.info.food_name));
+        // Candidates are rendered in a list, so we won't try to place focus
+        // on any one of them.
+        // Setting the date of a candidate must leave the other candidates in
+        // place, too.
+        let prev_day_route = if self.is_candidate {
+            format!("{prev_day_route}?candidate=true")
+        } else {
+            prev_day_route.to_string()
+        };
+        let (add_form_attrs, add_button_id, focus_script) = if self.is_candidate
+        {
+            (
+                format!(
+                    r#"
+                    hx-post="{}"
+                    hx-target="closest div[data-name='food-candidate']"
+                    hx-swap="delete"
+                    "#,
+                    Route::SaveFoodCandidate
+                ),
+                "",
+                "",
+            )
+        } else {
+            (
+                format!(
+                    r##"hx-post="{}" hx-target="#cal-chat-container""##,
+                    Route::SaveFood
+                ),
+                r#"id="add-meal""#,
+                r#"
+                <script>
+                    // place focus on the add-meal button (above)
+                    document.getElementById('add-meal').focus()
+                </script>
+                "#,
+            )
+        };
         format!(
             r##"
             <form
@@ -167,7 +214,7 @@ impl Component for NewMealOptions<'_> {
                     tabindex="2"
                 >Try Again</button>
             </form>
-            <form hx-post="{save_route}" hx-target="#cal-chat-container">
+            <form {add_form_attrs}>
                 <input type="hidden" value="{food_name}" name="food_name" />
                 <input type="hidden" value="{calories}" name="calories" />
                 <input type="hidden" value="{protein}" name="protein_grams" />
@@ -177,12 +224,9 @@ impl Component for NewMealOptions<'_> {
                 <button
                     class="bg-blue-100 p-1 rounded shadow hover:bg-blue-200"
                     tabindex="1"
-                    id="add-meal"
+                    {add_button_id}
                 >Add</button>
-                <script>
-                    // place focus on the add-meal button (above)
-                    document.getElementById('add-meal').focus()
-                </script>
+                {focus_script}
             </form>
             "##
         )
@@ -193,16 +237,24 @@ pub struct CannotParse<'a> {
     pub llm_response: &'a str,
     pub original_user_prompt: &'a str,
     pub retry_route: Route,
+    /// If set, the user can reply to the LLM instead of starting over.
+    pub can_follow_up: bool,
 }
 impl Component for CannotParse<'_> {
     fn render(&self) -> String {
         let retry_route = &self.retry_route;
         let llm_response = clean(self.llm_response);
         let prompt = encode_quotes(&clean(self.original_user_prompt));
+        let follow_up = if self.can_follow_up {
+            FollowUpForm {}.render()
+        } else {
+            "".into()
+        };
         format!(
             r##"
             <div class="prose max-w-[400px] dark:text-slate-200">
                 <p><b>AI response:</b> {llm_response}</p>
+                {follow_up}
                 <form hx-post="{retry_route}" hx-target="#cal-chat-container">
                     <input type="hidden" value="{prompt}" name="food_name" />
                     <button
@@ -224,6 +276,167 @@ impl Component for CannotParse<'_> {
     }
 }
 
+/// Extra context from a structured LLM response, shown above the food card.
+struct EstimateNotes<'a> {
+    estimate: &'a FoodEstimate,
+    /// If set, the user can answer the LLM's follow-up question.
+    can_follow_up: bool,
+}
+impl Component for EstimateNotes<'_> {
+    fn render(&self) -> String {
+        let name = clean(&self.estimate.name);
+        let serving_size = clean(&self.estimate.serving_size);
+        let confidence = self.estimate.confidence.as_str();
+        let follow_up = match &self.estimate.follow_up_question {
+            Some(question) => {
+                let question = clean(question);
+                let form = if self.can_follow_up {
+                    FollowUpForm {}.render()
+                } else {
+                    "".into()
+                };
+                format!(
+                    r#"
+                    <p class="text-sm"><b>The AI asks:</b> {question}</p>
+                    {form}
+                    "#
+                )
+            }
+            None => "".into(),
+        };
+        format!(
+            r#"
+            <div class="prose max-w-[400px] dark:text-slate-200 mb-2">
+                <p class="text-sm">
+                    <b>{name}:</b> {serving_size}
+                    ({confidence} confidence)
+                </p>
+                {follow_up}
+            </div>
+            "#
+        )
+    }
+}
+
+/// The food card for one candidate from a multi-item chat.
+struct CandidateCard<'a> {
+    info: &'a FoodItemDetails,
+    preferences: &'a UserPreference,
+}
+impl Component for CandidateCard<'_> {
+    fn render(&self) -> String {
+        FoodCard {
+            info: self.info,
+            identifiers: None,
+            actions: Some(&NewMealOptions {
+                info: self.info,
+                is_candidate: true,
+            }),
+            rendering_behavior: RenderingBehavior::UseTimezone(
+                self.preferences.timezone,
+            ),
+            show_ai_warning: true,
+            hide_calories: self.preferences.hide_calories,
+        }
+        .render()
+    }
+}
+
+/// One food from a multi-item chat.
+struct Candidate<'a> {
+    details: FoodItemDetails,
+    /// Only structured responses come with notes about each food.
+    estimate: Option<&'a FoodEstimate>,
+}
+impl<'a> Candidate<'a> {
+    /// Structured responses are preferred, but prose responses which list
+    /// one food per line are split up too.
+    fn from_response(
+        estimates: &'a [FoodEstimate],
+        llm_text: &str,
+    ) -> Vec<Self> {
+        if estimates.is_empty() {
+            FoodItemDetails::parse_items(llm_text)
+                .into_iter()
+                .map(|details| Self {
+                    details,
+                    estimate: None,
+                })
+                .collect()
+        } else {
+            estimates
+                .iter()
+                .map(|estimate| Self {
+                    details: estimate.to_details(&estimate.name),
+                    estimate: Some(estimate),
+                })
+                .collect()
+        }
+    }
+}
+
+/// Rendered when the user describes several foods in one chat. Each food
+/// gets its own card and actions, and they can all be added at once.
+struct FoodCandidates<'a> {
+    candidates: &'a [Candidate<'a>],
+    preferences: &'a UserPreference,
+    can_follow_up: bool,
+}
+impl Component for FoodCandidates<'_> {
+    fn render(&self) -> String {
+        let save_all_route = Route::SaveAllFood;
+        let candidates =
+            self.candidates
+                .iter()
+                .fold(String::new(), |mut acc, candidate| {
+                    let details = &candidate.details;
+                    let item = encode_quotes(&clean(
+                        &serde_json::to_string(details).unwrap_or_default(),
+                    ));
+                    let notes =
+                        candidate.estimate.map_or("".into(), |estimate| {
+                            EstimateNotes {
+                                estimate,
+                                can_follow_up: self.can_follow_up,
+                            }
+                            .render()
+                        });
+                    let card = CandidateCard {
+                        info: details,
+                        preferences: self.preferences,
+                    }
+                    .render();
+                    acc.push_str(&format!(
+                        r#"
+                        <div data-name="food-candidate">
+                            <input type="hidden" name="item" value="{item}" />
+                            {notes}
+                            {card}
+                        </div>
+                        "#
+                    ));
+                    acc
+                });
+        format!(
+            r##"
+            <div class="flex flex-col gap-2" data-name="food-candidates">
+                {candidates}
+                <form
+                    hx-post="{save_all_route}"
+                    hx-target="#cal-chat-container"
+                    hx-include="[data-name='food-candidate'] input[name='item']"
+                >
+                    <button
+                        class="bg-blue-100 p-1 rounded shadow hover:bg-blue-200
+                        dark:bg-blue-800 dark:hover:bg-blue-700"
+                    >Add All</button>
+                </form>
+            </div>
+            "##
+        )
+    }
+}
+
 pub struct InputTooLong;
 impl Component for InputTooLong {
     fn render(&self) -> String {
@@ -255,6 +468,10 @@ impl Component for InputTooLong {
 #[derive(Deserialize)]
 pub struct ChatPayload {
     pub chat: String,
+    /// Set when the user has already seen suggestions from their previously
+    /// entered food, and wants an estimate from the LLM instead.
+    #[serde(default)]
+    pub skip_suggestions: bool,
 }
 
 pub const SYSTEM_MSG: &str = "Act as an experienced nutritionist who can provide accurate calorie and macronutrient estimates. Your job is to give an approximation of calories and macros for foods that your client asks about. Do not hesitate to provide estimates for alcoholic beverages.
@@ -263,20 +480,95 @@ If the your client's query is extremely vague and you feel that you cannot provi
 
 Sometimes, users would like to manually add items. They may say, \"1200 calorie dinner.\" In this case, please just echo back, \"1200 calories, 0g of protein, 0g of fat, and 0g of carbohydrates.\" This is important to facilitate manually adding items. 
 
-To complete this task, respond with calorie estimates and macronutrient estimates for the food I describe. A macronutrient breakdown is the amount of protein, carbohydrates, and fat, each measured in grams. Always provide exactly one number each for calories, grams of protein, grams of carbohydrates, and grams of fat so that your response is easy to parse. Please match this style in your response: \"The food you asked about has {} calories, {}g of protein, {}g of fat, and {}g of carbohydrates.";
+To complete this task, respond with calorie estimates and macronutrient estimates for the food I describe. A macronutrient breakdown is the amount of protein, carbohydrates, and fat, each measured in grams. Always provide exactly one number each for calories, grams of protein, grams of carbohydrates, and grams of fat so that your response is easy to parse.
+
+If your client describes more than one food, like \"two eggs, toast, and a coffee,\" give a separate estimate for each food instead of one combined estimate. Put each food on its own line, starting with the name of the food, and do not include a total. Please match this style for each line: \"{food name}: {} calories, {}g of protein, {}g of fat, and {}g of carbohydrates.\"
+
+If your client describes a single food, please match this style in your response: \"The food you asked about has {} calories, {}g of protein, {}g of fat, and {}g of carbohydrates.\"";
 
 pub async fn handle_chat(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, llm }): State<AppState>,
     headers: HeaderMap,
-    Form(ChatPayload { chat }): Form<ChatPayload>,
+    Form(ChatPayload {
+        chat,
+        skip_suggestions,
+    }): Form<ChatPayload>,
 ) -> Result<impl IntoResponse, ServerError> {
     if chat.len() > config::CHAT_MAX_LEN {
         return Ok(InputTooLong {}.render());
     }
     let session = Session::from_headers_err(&headers, "handle chat")?;
     let preferences = session.get_preferences(&db).await?;
-    let response = OpenAI::from_env()?
-        .send_message(SYSTEM_MSG.into(), &chat)
+    if !skip_suggestions {
+        let suggestions =
+            search_previous_food(&db, session.user_id, &chat, &preferences)
+                .await?;
+        if !suggestions.is_empty() {
+            return Ok(FoodSuggestions {
+                food_items: &suggestions,
+                prompt: &chat,
+                user_timezone: preferences.timezone,
+            }
+            .render());
+        }
+    }
+    converse(
+        &db,
+        llm.as_ref(),
+        &session,
+        &preferences,
+        Conversation::new(&chat),
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct FollowUpPayload {
+    answer: String,
+}
+
+pub async fn handle_follow_up(
+    State(AppState { db, llm }): State<AppState>,
+    headers: HeaderMap,
+    Form(FollowUpPayload { answer }): Form<FollowUpPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    if answer.len() > config::CHAT_MAX_LEN {
+        return Ok(InputTooLong {}.render());
+    }
+    let session = Session::from_headers_err(&headers, "handle follow up")?;
+    let preferences = session.get_preferences(&db).await?;
+    let mut conversation = get_conversation(&db, session.user_id)
+        .await?
+        .ok_or_else(|| {
+            ServerError::bad_request("follow up without a conversation", None)
+        })?;
+    if !conversation.can_follow_up() {
+        return Err(ServerError::bad_request(
+            "conversation has too many follow ups",
+            None,
+        ));
+    }
+    conversation.push_answer(&answer);
+    converse(&db, llm.as_ref(), &session, &preferences, conversation).await
+}
+
+/// Send the conversation to the LLM, record token usage, and render the
+/// result. The conversation is stored so that the user can answer any
+/// follow-up question from the LLM.
+async fn converse(
+    db: &PgPool,
+    llm: &dyn LlmBackend,
+    session: &Session,
+    preferences: &UserPreference,
+    mut conversation: Conversation,
+) -> Result<String, ServerError> {
+    let status = check_quota(db, session.user_id, preferences).await?;
+    if status != QuotaStatus::Available {
+        let content = OverQuota { status }.render();
+        return Ok(format!(r#"<div data-name="chat-turn">{content}</div>"#));
+    }
+    let response = llm
+        .send_message(SYSTEM_MSG.into(), &conversation.messages)
         .await?;
     let Id { id } = query_as!(
         Id,
@@ -286,35 +578,71 @@ pub async fn handle_chat(
         response.usage.prompt_tokens,
         response.usage.completion_tokens,
         response.usage.total_tokens
-    ).fetch_one(&db).await?;
+    ).fetch_one(db).await?;
     query!(
         "insert into openai_usage_user (usage_id, user_id) values ($1, $2)",
         id,
         session.user_id
     )
-    .execute(&db)
+    .execute(db)
     .await?;
+    conversation.push_llm_response(&response.message);
+    save_conversation(db, session.user_id, &conversation).await?;
+    let can_follow_up = conversation.can_follow_up();
+    let chat = &conversation.food_name;
 
-    let parse_result = FoodItemDetails::parse(&response.message, &chat);
-    match parse_result {
-        Ok(meal) => Ok(FoodCard {
-            info: &meal,
-            identifiers: None,
-            actions: Some(&NewMealOptions { info: &meal }),
-            rendering_behavior: RenderingBehavior::UseTimezone(
-                preferences.timezone,
-            ),
-            show_ai_warning: true,
-            hide_calories: preferences.hide_calories,
+    let estimates = FoodEstimate::parse(&response.message).unwrap_or_default();
+    let candidates = Candidate::from_response(&estimates, &response.message);
+    let content = if candidates.len() > 1 {
+        FoodCandidates {
+            candidates: &candidates,
+            preferences,
+            can_follow_up,
         }
-        .render()),
-        _ => Ok(CannotParse {
-            llm_response: &response.message,
-            original_user_prompt: &chat,
-            retry_route: Route::ChatForm,
+        .render()
+    } else {
+        let estimate = estimates.first();
+        let parse_result = match estimate {
+            Some(estimate) => Ok(estimate.to_details(chat)),
+            None => FoodItemDetails::parse(&response.message, chat),
+        };
+        match parse_result {
+            Ok(meal) => {
+                let notes = estimate.map_or("".to_string(), |estimate| {
+                    EstimateNotes {
+                        estimate,
+                        can_follow_up,
+                    }
+                    .render()
+                });
+                let card = FoodCard {
+                    info: &meal,
+                    identifiers: None,
+                    actions: Some(&NewMealOptions {
+                        info: &meal,
+                        is_candidate: false,
+                    }),
+                    rendering_behavior: RenderingBehavior::UseTimezone(
+                        preferences.timezone,
+                    ),
+                    show_ai_warning: true,
+                    hide_calories: preferences.hide_calories,
+                }
+                .render();
+                format!("{notes}{card}")
+            }
+            _ => CannotParse {
+                llm_response: &response.message,
+                original_user_prompt: chat,
+                retry_route: Route::ChatForm,
+                can_follow_up,
+            }
+            .render(),
         }
-        .render()),
-    }
+    };
+    // Follow-up forms target this container, so that the LLM's next
+    // response replaces this one.
+    Ok(format!(r#"<div data-name="chat-turn">{content}</div>"#))
 }
 
 #[derive(Deserialize)]
@@ -328,7 +656,7 @@ pub struct Pagination {
 }
 
 pub async fn chat_form(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     page: Option<Query<Pagination>>,
     prev_prompt: Option<Form<PrevPrompt>>,
@@ -416,45 +744,125 @@ pub async fn list_meals_op(
         .collect::<Vec<FoodItem>>())
 }
 
+/// Insert a new food, and an eaten event pointing at it.
+pub async fn save_food_op(
+    db: impl PgExecutor<'_>,
+    user_id: i32,
+    food: &FoodItemDetails,
+) -> Aresult<()> {
+    query!(
+        "with new_food as (
+            insert into food
+            (
+                user_id,
+                name,
+                calories,
+                fat,
+                protein,
+                carbohydrates
+            )
+            values ($1, $2, $3, $4, $5, $6)
+            returning id
+        )
+        insert into food_eaten_event (food_id, user_id, eaten_at)
+        select id, $1, $7 from new_food",
+        user_id,
+        food.food_name,
+        food.calories,
+        food.fat_grams,
+        food.protein_grams,
+        food.carbohydrates_grams,
+        food.eaten_at
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
 pub async fn handle_save_food(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Form(meal): Form<FoodItemDetails>,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers(&headers)
         .ok_or_else(|| ServerError::forbidden("handle save meal"))?;
     let preferences = session.get_preferences(&db).await?;
-    let Id { id } = query_as!(
-        Id,
-        "insert into food
-        (
-            user_id,
-            name,
-            calories,
-            fat,
-            protein,
-            carbohydrates
-        )
-        values ($1, $2, $3, $4, $5, $6)
-        returning id",
-        session.user_id,
-        meal.food_name,
-        meal.calories,
-        meal.fat_grams,
-        meal.protein_grams,
-        meal.carbohydrates_grams,
-    )
-    .fetch_one(&db)
-    .await?;
-    query!(
-        "insert into food_eaten_event (food_id, user_id, eaten_at)
-        values ($1, $2, $3)",
-        id,
-        session.user_id,
-        meal.eaten_at
-    )
-    .execute(&db)
-    .await?;
+    save_food_op(&db, session.user_id, &meal).await?;
+    let response_headers = client_events::reload_macros(HeaderMap::new());
+    let meals = list_meals_op(&db, session.user_id, &preferences, 0).await?;
+    Ok((
+        response_headers,
+        Chat {
+            food_items: &meals,
+            preferences: &preferences,
+            prompt: None,
+            next_page: 1,
+            post_request_handler: Route::HandleChat,
+            is_anonymous: is_anon(&session.username),
+        }
+        .render(),
+    ))
+}
+
+/// Save one of several candidates from a multi-item chat. The candidate card
+/// is removed on the frontend, so we don't need to respond with any content.
+pub async fn handle_save_food_candidate(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(meal): Form<FoodItemDetails>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "save food candidate")?;
+    save_food_op(&db, session.user_id, &meal).await?;
+    let headers = client_events::reload_food(HeaderMap::new());
+    let headers = client_events::reload_macros(headers);
+    Ok((headers, ""))
+}
+
+/// Render a candidate from a multi-item chat again, when the user cancels
+/// setting its date.
+pub async fn food_candidate_card(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(meal): Form<FoodItemDetails>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "food candidate card")?;
+    let preferences = session.get_preferences(&db).await?;
+    Ok(CandidateCard {
+        info: &meal,
+        preferences: &preferences,
+    }
+    .render())
+}
+
+/// Save all remaining candidates from a multi-item chat. Each candidate is
+/// submitted as an `item` field containing JSON, since form encoding does not
+/// support lists.
+pub async fn handle_save_all_food(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "save all food")?;
+    let preferences = session.get_preferences(&db).await?;
+    let items = fields
+        .iter()
+        .filter(|(key, _)| key == "item")
+        .map(|(_, value)| serde_json::from_str::<FoodItemDetails>(value))
+        .collect::<Result<Vec<FoodItemDetails>, serde_json::Error>>()
+        .map_err(|_| {
+            ServerError::bad_request("could not parse food to save", None)
+        })?;
+    if items.is_empty() {
+        return Err(ServerError::bad_request(
+            "attempt to save an empty list of food",
+            None,
+        ));
+    }
+    let mut tx = db.begin().await?;
+    for item in &items {
+        save_food_op(&mut *tx, session.user_id, item).await?;
+    }
+    tx.commit().await?;
     let response_headers = client_events::reload_macros(HeaderMap::new());
     let meals = list_meals_op(&db, session.user_id, &preferences, 0).await?;
     Ok((
@@ -472,7 +880,7 @@ pub async fn handle_save_food(
 }
 
 pub async fn list_food(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     Query(Pagination { page }): Query<Pagination>,
 ) -> Result<impl IntoResponse, ServerError> {
@@ -495,9 +903,17 @@ pub async fn list_food(
     .render())
 }
 
+#[derive(Deserialize)]
+pub struct PrevDayOptions {
+    /// Set for one of several candidates from a multi-item chat.
+    #[serde(default)]
+    candidate: bool,
+}
+
 pub async fn prev_day_food_form(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
+    Query(PrevDayOptions { candidate }): Query<PrevDayOptions>,
     Form(meal): Form<FoodItemDetails>,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers_err(&headers, "prev day meal form")?;
@@ -505,7 +921,10 @@ pub async fn prev_day_food_form(
     Ok(FoodCard {
         info: &meal,
         identifiers: None,
-        actions: Some(&PrevDayFormActions { info: &meal }),
+        actions: Some(&PrevDayFormActions {
+            info: &meal,
+            is_candidate: candidate,
+        }),
         rendering_behavior: RenderingBehavior::UseTimezone(
             preferences.timezone,
         ),
@@ -517,12 +936,93 @@ pub async fn prev_day_food_form(
 
 #[cfg(test)]
 mod test {
-    use super::*;
+    use super::{super::llm_parse_response::Confidence, *};
 
     fn get_prefs() -> UserPreference {
         UserPreference::default()
     }
 
+    fn get_estimate(name: &str) -> FoodEstimate {
+        FoodEstimate {
+            name: name.into(),
+            serving_size: "1 serving".into(),
+            calories: 100,
+            protein_grams: 1,
+            fat_grams: 1,
+            carbohydrates_grams: 1,
+            confidence: Confidence::High,
+            follow_up_question: None,
+        }
+    }
+
+    #[test]
+    fn test_multi_item_chat_renders_each_candidate() {
+        let estimates = [get_estimate("Eggs"), get_estimate("Toast")];
+        let candidates = Candidate::from_response(&estimates, "");
+        let ui = FoodCandidates {
+            candidates: &candidates,
+            preferences: &get_prefs(),
+            can_follow_up: true,
+        }
+        .render();
+        assert_eq!(ui.matches(r#"name="item""#).count(), 2);
+        assert!(ui.contains("Add All"));
+        assert_eq!(ui.matches("(high confidence)").count(), 2);
+        // Each candidate has its own actions, and none of them steal focus.
+        assert_eq!(ui.matches("Try Again").count(), 2);
+        assert!(!ui.contains("add-meal"));
+        // Setting the date of one candidate leaves the others in place.
+        let set_date = format!("{}?candidate=true", Route::PreviousDayFood);
+        assert_eq!(ui.matches(&set_date).count(), 2);
+    }
+
+    /// Backends without structured output respond in prose, with one food
+    /// per line.
+    #[test]
+    fn test_multi_item_prose_response_is_split() {
+        let response = "Eggs: 140 calories, 12g of protein, 10g of fat, and 1g of carbohydrates.
+Toast: 80 calories, 3g of protein, 1g of fat, and 15g of carbohydrates.";
+        let candidates = Candidate::from_response(&[], response);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].details.food_name, "Eggs");
+        assert_eq!(candidates[1].details.calories, 80);
+        let ui = FoodCandidates {
+            candidates: &candidates,
+            preferences: &get_prefs(),
+            can_follow_up: true,
+        }
+        .render();
+        assert_eq!(ui.matches(r#"name="item""#).count(), 2);
+        assert!(!ui.contains("confidence"));
+    }
+
+    #[test]
+    fn test_single_food_prose_response_is_not_split() {
+        let response = "The food you asked about has 140 calories, 12g of protein, 10g of fat, and 1g of carbohydrates.";
+        assert!(Candidate::from_response(&[], response).is_empty());
+    }
+
+    #[test]
+    fn test_candidate_date_form_only_replaces_that_candidate() {
+        let details = get_estimate("Eggs").to_details("Eggs");
+        let ui = PrevDayFormActions {
+            info: &details,
+            is_candidate: true,
+        }
+        .render();
+        assert!(ui.contains(&Route::SaveFoodCandidate.as_string()));
+        assert!(ui.contains(&Route::FoodCandidate.as_string()));
+        assert!(!ui.contains("#cal-chat-container"));
+
+        let ui = PrevDayFormActions {
+            info: &details,
+            is_candidate: false,
+        }
+        .render();
+        assert!(ui.contains(&Route::SaveFood.as_string()));
+        assert!(ui.contains("#cal-chat-container"));
+    }
+
     /// If an anon user has less than 3 meals, they won't get a warning.
     #[test]
     fn test_no_anon_warning_for_inactive_anon() {
@@ -1,5 +1,6 @@
 // This is teensey bit of JS that we include with ./counter.rs::PrevDayForm.
-// It will handle the click event on our three submit buttons;
+// It will handle the click event on our four submit buttons, which are
+// identified by their `data-time` attribute;
 //
 // - breakfast
 // - lunch
@@ -11,13 +12,13 @@
 // at 6pm, and evening at 10pm. Then, the form submit event will propagate,
 // and the form will be submitted with the "eaten_at" datetime field filled
 // baased on whichever button the user pressed.
+//
+// Several of these forms can be on the page at once (one per candidate from
+// a multi-item chat), so everything is looked up within the form that
+// contains this script.
 
-const buttons = [
-  document.getElementById("breakfast"),
-  document.getElementById("lunch"),
-  document.getElementById("dinner"),
-  document.getElementById("evening"),
-];
+const form = document.currentScript.closest("form");
+const buttons = form.querySelectorAll("button[data-time]");
 
 /**
  * @param {number} hours
@@ -42,7 +43,7 @@ function addHours(date, hours) {
  * @param {Event} e
  */
 function handler(e) {
-  const dateEl = document.getElementById("created_date");
+  const dateEl = form.querySelector("input[name='created_date']");
   if (!dateEl.value) {
     // Form submission will be blocked, since this is a required field.
     return;
@@ -58,15 +59,15 @@ function handler(e) {
   /**
    * @type {'breakfast' | 'lunch' | 'dinner' | 'evening'}
    */
-  let id = e.target.id;
-  let datetime = datetimes[id];
+  let time = e.target.dataset.time;
+  let datetime = datetimes[time];
   if (!datetime) {
-    throw new Error(`could not find datetime for ID ${id}`);
+    throw new Error(`could not find datetime for ${time}`);
   }
   /**
    * @type {HTMLInputElement}
    */
-  const targetEl = document.getElementById("eaten_at");
+  const targetEl = form.querySelector("input[name='eaten_at']");
   targetEl.value = datetime.toISOString();
 }
 
@@ -0,0 +1,479 @@
+//! Editing food after it has been saved. Since a row in `food` can be shared
+//! by several eaten events (and by saved meals), the user chooses whether to
+//! edit just one eaten event, or the underlying food everywhere it's used.
+
+use super::{
+    food_card::{FoodCard, FoodIdentifiers, RenderingBehavior},
+    FoodItemDetails,
+};
+use crate::{chrono_utils::is_before_today, client_events, prelude::*};
+
+struct EditableFood {
+    food_id: i32,
+    eaten_event_id: i32,
+    details: FoodItemDetails,
+    /// Count of eaten events and saved meals which point at this food.
+    use_count: i64,
+}
+
+async fn get_editable_food(
+    db: impl PgExecutor<'_>,
+    user_id: i32,
+    eaten_event_id: i32,
+) -> Aresult<Option<EditableFood>> {
+    struct Qres {
+        food_id: i32,
+        eaten_event_id: i32,
+        food_name: String,
+        calories: i32,
+        protein_grams: i32,
+        carbohydrates_grams: i32,
+        fat_grams: i32,
+        eaten_at: DateTime<Utc>,
+        use_count: Option<i64>,
+    }
+    Ok(query_as!(
+        Qres,
+        "select
+            f.id food_id,
+            fee.id eaten_event_id,
+            f.name food_name,
+            f.calories,
+            f.protein protein_grams,
+            f.carbohydrates carbohydrates_grams,
+            f.fat fat_grams,
+            fee.eaten_at,
+            (
+                select count(*) from food_eaten_event
+                where food_id = f.id
+            ) + (
+                select count(*) from meal_food
+                where food_id = f.id
+            ) use_count
+        from food_eaten_event fee
+        join food f on f.id = fee.food_id
+        where
+            fee.id = $1
+            and fee.user_id = $2
+            and f.user_id = $2",
+        eaten_event_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?
+    .map(|row| EditableFood {
+        food_id: row.food_id,
+        eaten_event_id: row.eaten_event_id,
+        details: FoodItemDetails {
+            food_name: row.food_name,
+            calories: row.calories,
+            protein_grams: row.protein_grams,
+            carbohydrates_grams: row.carbohydrates_grams,
+            fat_grams: row.fat_grams,
+            eaten_at: row.eaten_at,
+        },
+        use_count: row.use_count.unwrap_or_default(),
+    }))
+}
+
+struct EditFoodForm<'a> {
+    food: &'a EditableFood,
+    user_timezone: Tz,
+    hide_calories: bool,
+}
+impl Component for EditFoodForm<'_> {
+    fn render(&self) -> String {
+        let submit = Route::EditFood(Some(self.food.eaten_event_id));
+        let cancel = Route::ShowFood(Some(self.food.eaten_event_id));
+        let details = &self.food.details;
+        let food_name = encode_quotes(&clean(&details.food_name));
+        let calories = details.calories;
+        let protein = details.protein_grams;
+        let carbs = details.carbohydrates_grams;
+        let fat = details.fat_grams;
+        let eaten_at = details
+            .eaten_at
+            .with_timezone(&self.user_timezone)
+            .format("%Y-%m-%dT%H:%M");
+        // If we hide calories, the calorie input is hidden, but we still need
+        // to submit the existing value.
+        let calories_input = if self.hide_calories {
+            format!(
+                r#"<input type="hidden" name="calories" value="{calories}" />"#
+            )
+        } else {
+            format!(
+                r#"
+                <label for="calories">Calories</label>
+                <input
+                    required
+                    type="number"
+                    min="0"
+                    id="calories"
+                    name="calories"
+                    value="{calories}"
+                />
+                "#
+            )
+        };
+        let scope = if self.food.use_count > 1 {
+            let use_count = self.food.use_count;
+            format!(
+                r#"
+                <p class="text-sm">
+                    This food is used {use_count} times, including saved
+                    meals. What would you like to change?
+                </p>
+                <label class="text-sm">
+                    <input type="radio" name="scope" value="event" checked />
+                    Only this entry
+                </label>
+                <label class="text-sm">
+                    <input type="radio" name="scope" value="food" />
+                    This food everywhere it's used
+                </label>
+                "#
+            )
+        } else {
+            r#"<input type="hidden" name="scope" value="event" />"#.into()
+        };
+        format!(
+            r##"
+            <div
+                class="rounded p-2 shadow sm:w-[20rem] bg-gradient-to-br
+                from-blue-100 via-sky-100 to-indigo-200 dark:text-black"
+                data-name="meal-card"
+                hx-swap="outerHTML"
+            >
+                <form
+                    class="flex flex-col gap-1"
+                    hx-post="{submit}"
+                    hx-target="closest div[data-name='meal-card']"
+                >
+                    <label for="food_name">Name</label>
+                    <input
+                        required
+                        type="text"
+                        id="food_name"
+                        name="food_name"
+                        value="{food_name}"
+                    />
+                    {calories_input}
+                    <label for="protein_grams">Protein (grams)</label>
+                    <input
+                        required
+                        type="number"
+                        min="0"
+                        id="protein_grams"
+                        name="protein_grams"
+                        value="{protein}"
+                    />
+                    <label for="carbohydrates_grams">Carbs (grams)</label>
+                    <input
+                        required
+                        type="number"
+                        min="0"
+                        id="carbohydrates_grams"
+                        name="carbohydrates_grams"
+                        value="{carbs}"
+                    />
+                    <label for="fat_grams">Fat (grams)</label>
+                    <input
+                        required
+                        type="number"
+                        min="0"
+                        id="fat_grams"
+                        name="fat_grams"
+                        value="{fat}"
+                    />
+                    <label for="eaten_at">Eaten at</label>
+                    <input
+                        required
+                        type="datetime-local"
+                        id="eaten_at"
+                        name="eaten_at"
+                        value="{eaten_at}"
+                    />
+                    {scope}
+                    <div class="flex justify-end gap-2 mt-2">
+                        <button
+                            type="button"
+                            hx-get="{cancel}"
+                            hx-target="closest div[data-name='meal-card']"
+                            class="bg-red-100 hover:bg-red-200 rounded p-1"
+                        >
+                            Cancel
+                        </button>
+                        <button
+                            class="bg-blue-100 hover:bg-blue-200 rounded p-1"
+                        >
+                            Save
+                        </button>
+                    </div>
+                </form>
+            </div>
+            "##
+        )
+    }
+}
+
+fn render_card(food: &EditableFood, preferences: &UserPreference) -> String {
+    FoodCard {
+        info: &food.details,
+        identifiers: Some(FoodIdentifiers {
+            meal_id: food.food_id,
+            eaten_event_id: food.eaten_event_id,
+        }),
+        actions: None,
+        rendering_behavior: RenderingBehavior::UseTimezone(
+            preferences.timezone,
+        ),
+        show_ai_warning: false,
+        hide_calories: preferences.hide_calories,
+    }
+    .render()
+}
+
+pub async fn edit_food_form(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "edit food form")?;
+    let preferences = session.get_preferences(&db).await?;
+    let food = get_editable_food(&db, session.user_id, id)
+        .await?
+        .ok_or_else(|| {
+            ServerError::bad_request("attempt to edit non-existent food", None)
+        })?;
+    Ok(EditFoodForm {
+        food: &food,
+        user_timezone: preferences.timezone,
+        hide_calories: preferences.hide_calories,
+    }
+    .render())
+}
+
+pub async fn show_food(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "show food")?;
+    let preferences = session.get_preferences(&db).await?;
+    let food = get_editable_food(&db, session.user_id, id)
+        .await?
+        .ok_or_else(|| {
+            ServerError::bad_request("attempt to show non-existent food", None)
+        })?;
+    Ok(render_card(&food, &preferences))
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EditScope {
+    /// Only change this eaten event. If the food is shared, it is copied.
+    Event,
+    /// Change the food everywhere it is used.
+    Food,
+}
+
+#[derive(Deserialize)]
+pub struct EditFoodPayload {
+    food_name: String,
+    calories: i32,
+    protein_grams: i32,
+    carbohydrates_grams: i32,
+    fat_grams: i32,
+    /// Value of a `datetime-local` input, in the user's timezone.
+    eaten_at: String,
+    scope: EditScope,
+}
+
+pub async fn handle_edit_food(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+    Form(form): Form<EditFoodPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "handle edit food")?;
+    let preferences = session.get_preferences(&db).await?;
+    if form.food_name.trim().is_empty()
+        || form.calories < 0
+        || form.protein_grams < 0
+        || form.carbohydrates_grams < 0
+        || form.fat_grams < 0
+    {
+        return Err(ServerError::bad_request("invalid food edit", None));
+    }
+    let eaten_at =
+        NaiveDateTime::parse_from_str(&form.eaten_at, "%Y-%m-%dT%H:%M")
+            .ok()
+            .and_then(|naive| {
+                preferences.timezone.from_local_datetime(&naive).earliest()
+            })
+            .ok_or_else(|| {
+                ServerError::bad_request("invalid eaten_at for food edit", None)
+            })?
+            .to_utc();
+
+    let mut tx = db.begin().await?;
+    let existing = get_editable_food(&mut *tx, session.user_id, id)
+        .await?
+        .ok_or_else(|| {
+            ServerError::bad_request("attempt to edit non-existent food", None)
+        })?;
+    let food_id = if form.scope == EditScope::Food || existing.use_count <= 1 {
+        query!(
+            "update food set
+                name = $1,
+                calories = $2,
+                protein = $3,
+                carbohydrates = $4,
+                fat = $5
+            where id = $6 and user_id = $7",
+            form.food_name.trim(),
+            form.calories,
+            form.protein_grams,
+            form.carbohydrates_grams,
+            form.fat_grams,
+            existing.food_id,
+            session.user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        existing.food_id
+    } else {
+        let Id { id } = query_as!(
+            Id,
+            "insert into food
+            (
+                user_id,
+                name,
+                calories,
+                protein,
+                carbohydrates,
+                fat
+            )
+            values ($1, $2, $3, $4, $5, $6)
+            returning id",
+            session.user_id,
+            form.food_name.trim(),
+            form.calories,
+            form.protein_grams,
+            form.carbohydrates_grams,
+            form.fat_grams
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        id
+    };
+    query!(
+        "update food_eaten_event set food_id = $1, eaten_at = $2
+        where id = $3 and user_id = $4",
+        food_id,
+        eaten_at,
+        id,
+        session.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Editing the food everywhere can change other eaten events, some of
+    // which might be from today.
+    struct EatenAt {
+        eaten_at: DateTime<Utc>,
+    }
+    let affected = if form.scope == EditScope::Food {
+        query_as!(
+            EatenAt,
+            "select eaten_at from food_eaten_event
+            where food_id = $1 and user_id = $2",
+            food_id,
+            session.user_id
+        )
+        .fetch_all(&mut *tx)
+        .await?
+    } else {
+        vec![EatenAt { eaten_at }]
+    };
+    tx.commit().await?;
+
+    let affects_today =
+        !is_before_today(&existing.details.eaten_at, preferences.timezone)
+            || affected
+                .iter()
+                .any(|e| !is_before_today(&e.eaten_at, preferences.timezone));
+    let response_headers = if affects_today {
+        client_events::reload_food(client_events::reload_macros(
+            HeaderMap::new(),
+        ))
+    } else {
+        HeaderMap::new()
+    };
+
+    let updated = get_editable_food(&db, session.user_id, id)
+        .await?
+        .ok_or_else(|| {
+            ServerError::bad_request("edited food does not exist", None)
+        })?;
+    Ok((response_headers, render_card(&updated, &preferences)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_food(use_count: i64) -> EditableFood {
+        EditableFood {
+            food_id: 1,
+            eaten_event_id: 2,
+            use_count,
+            details: FoodItemDetails {
+                food_name: "Protein Shake".into(),
+                calories: 180,
+                protein_grams: 30,
+                carbohydrates_grams: 8,
+                fat_grams: 3,
+                eaten_at: utc_now(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_scope_choice_only_for_shared_food() {
+        let food = get_food(1);
+        let form = EditFoodForm {
+            food: &food,
+            user_timezone: Tz::UTC,
+            hide_calories: false,
+        }
+        .render();
+        assert!(!form.contains(r#"type="radio""#));
+        assert!(form.contains(r#"type="hidden" name="scope" value="event""#));
+
+        let food = get_food(3);
+        let form = EditFoodForm {
+            food: &food,
+            user_timezone: Tz::UTC,
+            hide_calories: false,
+        }
+        .render();
+        assert!(form.contains("This food is used 3 times"));
+        assert!(form.contains(r#"value="food""#));
+    }
+
+    #[test]
+    fn test_eaten_at_is_in_user_timezone() {
+        let food = get_food(1);
+        let form = EditFoodForm {
+            food: &food,
+            user_timezone: Tz::America__New_York,
+            hide_calories: true,
+        }
+        .render();
+        // utc_now is 2024-06-29T23:47:33Z in tests
+        assert!(form.contains(r#"value="2024-06-29T19:47""#));
+        assert!(!form.contains(r#"id="calories""#));
+    }
+}
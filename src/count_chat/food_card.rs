@@ -3,6 +3,7 @@ use crate::{
     components::Void,
     prelude::*,
 };
+use serde::Serialize;
 
 #[derive(Debug)]
 pub struct FoodItem {
@@ -12,7 +13,7 @@ pub struct FoodItem {
     pub hide_calories: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct FoodItemDetails {
     pub calories: i32,
     pub protein_grams: i32,
@@ -81,6 +82,8 @@ impl Component for FoodCard<'_> {
                 Some(idents) => {
                     let delete_href =
                         Route::DeleteFood(Some(idents.eaten_event_id));
+                    let edit_href =
+                        Route::EditFood(Some(idents.eaten_event_id));
                     let add_to_today_href =
                         Route::AddFoodToToday(Some(idents.meal_id));
                     let add_to_today_button = if is_meal_before_today {
@@ -123,6 +126,14 @@ impl Component for FoodCard<'_> {
                     format!(
                         r#"
                         {add_to_today_button}
+                        <button
+                            hx-get="{edit_href}"
+                            hx-target="closest div[data-name='meal-card']"
+                            class="align-self-right bg-indigo-100
+                            hover:bg-indigo-200 rounded p-1 dark:text-black"
+                        >
+                            Edit
+                        </button>
                         <button
                             hx-delete="{delete_href}"
                             hx-target="closest div[data-name='meal-card']"
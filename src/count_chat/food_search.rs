@@ -0,0 +1,328 @@
+//! Before asking the LLM about a food, we look for similar food that the user
+//! has already counted. If we find a match, the user can reuse the existing
+//! `food` row, which gives us soft deduping and saves an OpenAI request.
+
+use super::{
+    counter::{list_meals_op, Chat},
+    food_card::{FoodCard, RenderingBehavior},
+    FoodItem, FoodItemDetails,
+};
+use crate::{auth::is_anon, client_events, prelude::*};
+use std::sync::OnceLock;
+
+/// Maximum number of suggestions to show before falling back to the LLM.
+const MAX_SUGGESTIONS: i64 = 3;
+
+/// Without `pg_trgm`, food names shorter than this are only suggested when
+/// the prompt is part of the name. Otherwise, a food called "pb" would be
+/// suggested for every prompt that happens to contain those two letters.
+const MIN_CONTAINED_NAME_LEN: i32 = 3;
+
+/// Whether the `pg_trgm` extension is installed. It might not be, since
+/// creating it can need superuser rights; see
+/// `migrations/33_food_name_trigram.sql`. Extensions don't come and go while
+/// we're running, so we only check once.
+static HAS_TRIGRAM: OnceLock<bool> = OnceLock::new();
+
+async fn has_trigram(db: &PgPool) -> Aresult<bool> {
+    if let Some(has_trigram) = HAS_TRIGRAM.get() {
+        return Ok(*has_trigram);
+    }
+    let has_trigram = query!(
+        r#"select exists(
+            select 1 from pg_extension where extname = 'pg_trgm'
+        ) "exists!""#
+    )
+    .fetch_one(db)
+    .await?
+    .exists;
+    if !has_trigram {
+        println!("pg_trgm is not installed; food search will use ilike");
+    }
+    Ok(*HAS_TRIGRAM.get_or_init(|| has_trigram))
+}
+
+/// Escape `%`, `_`, and `\` so that `ilike` matches them literally.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Fuzzy search over the user's own food using `pg_trgm` word similarity.
+/// Without `pg_trgm`, we fall back to food names which contain, or are
+/// contained by, the prompt; the names are escaped in the query, and the
+/// prompt by [escape_like]. Each distinct food name is returned once,
+/// alongside the most recent time that it was eaten.
+pub async fn search_previous_food(
+    db: &PgPool,
+    user_id: i32,
+    prompt: &str,
+    preferences: &UserPreference,
+) -> Aresult<Vec<FoodItem>> {
+    struct Qres {
+        id: i32,
+        food_name: String,
+        calories: i32,
+        fat_grams: i32,
+        protein_grams: i32,
+        carbohydrates_grams: i32,
+        eaten_at: DateTime<Utc>,
+        eaten_event_id: i32,
+    }
+    let res = if has_trigram(db).await? {
+        query_as!(
+            Qres,
+            r#"select
+                id "id!",
+                food_name "food_name!",
+                calories "calories!",
+                fat_grams "fat_grams!",
+                protein_grams "protein_grams!",
+                carbohydrates_grams "carbohydrates_grams!",
+                eaten_at "eaten_at!",
+                eaten_event_id "eaten_event_id!"
+            from (
+                select distinct on (lower(f.name))
+                    f.id,
+                    f.name food_name,
+                    f.calories,
+                    f.fat fat_grams,
+                    f.protein protein_grams,
+                    f.carbohydrates carbohydrates_grams,
+                    fee.eaten_at,
+                    fee.id eaten_event_id
+                from food f
+                join food_eaten_event fee on fee.food_id = f.id
+                where
+                    f.user_id = $1
+                    and fee.user_id = $1
+                    and $2 <% f.name
+                order by lower(f.name), fee.eaten_at desc
+            ) matches
+            order by word_similarity($2, food_name) desc, eaten_at desc
+            limit $3"#,
+            user_id,
+            prompt,
+            MAX_SUGGESTIONS
+        )
+        .fetch_all(db)
+        .await?
+    } else {
+        query_as!(
+            Qres,
+            r#"select
+                id "id!",
+                food_name "food_name!",
+                calories "calories!",
+                fat_grams "fat_grams!",
+                protein_grams "protein_grams!",
+                carbohydrates_grams "carbohydrates_grams!",
+                eaten_at "eaten_at!",
+                eaten_event_id "eaten_event_id!"
+            from (
+                select distinct on (lower(f.name))
+                    f.id,
+                    f.name food_name,
+                    f.calories,
+                    f.fat fat_grams,
+                    f.protein protein_grams,
+                    f.carbohydrates carbohydrates_grams,
+                    fee.eaten_at,
+                    fee.id eaten_event_id
+                from food f
+                join food_eaten_event fee on fee.food_id = f.id
+                where
+                    f.user_id = $1
+                    and fee.user_id = $1
+                    and (
+                        f.name ilike '%' || $2 || '%'
+                        or (
+                            length(f.name) >= $4
+                            and $5 ilike '%' || replace(replace(replace(
+                                f.name, '\', '\\'), '%', '\%'), '_', '\_'
+                            ) || '%'
+                        )
+                    )
+                order by lower(f.name), fee.eaten_at desc
+            ) matches
+            order by length(food_name) desc, eaten_at desc
+            limit $3"#,
+            user_id,
+            escape_like(prompt),
+            MAX_SUGGESTIONS,
+            MIN_CONTAINED_NAME_LEN,
+            prompt
+        )
+        .fetch_all(db)
+        .await?
+    };
+
+    Ok(res
+        .into_iter()
+        .map(|r| FoodItem {
+            id: r.id,
+            eaten_event_id: r.eaten_event_id,
+            hide_calories: preferences.hide_calories,
+            details: FoodItemDetails {
+                food_name: r.food_name,
+                calories: r.calories,
+                carbohydrates_grams: r.carbohydrates_grams,
+                fat_grams: r.fat_grams,
+                protein_grams: r.protein_grams,
+                eaten_at: r.eaten_at,
+            },
+        })
+        .collect())
+}
+
+struct UseThisFood {
+    food_id: i32,
+}
+impl Component for UseThisFood {
+    fn render(&self) -> String {
+        let href = Route::UsePreviousFood(Some(self.food_id));
+        format!(
+            r##"
+            <button
+                hx-post="{href}"
+                hx-target="#cal-chat-container"
+                class="bg-emerald-100 hover:bg-emerald-200 rounded p-1
+                dark:text-black"
+            >
+                Use This
+            </button>
+            "##
+        )
+    }
+}
+
+pub struct FoodSuggestions<'a> {
+    pub food_items: &'a [FoodItem],
+    pub prompt: &'a str,
+    pub user_timezone: Tz,
+}
+impl Component for FoodSuggestions<'_> {
+    fn render(&self) -> String {
+        let handle_chat = Route::HandleChat;
+        let prompt = encode_quotes(&clean(self.prompt));
+        let cards =
+            self.food_items.iter().fold(String::new(), |mut acc, item| {
+                acc.push_str(
+                    &FoodCard {
+                        info: &item.details,
+                        identifiers: None,
+                        actions: Some(&UseThisFood { food_id: item.id }),
+                        rendering_behavior: RenderingBehavior::UseTimezone(
+                            self.user_timezone,
+                        ),
+                        show_ai_warning: false,
+                        hide_calories: item.hide_calories,
+                    }
+                    .render(),
+                );
+                acc
+            });
+        format!(
+            r##"
+            <div class="flex flex-col gap-2" data-name="food-suggestions">
+                <h2 class="text-xl bold">Have you eaten this before?</h2>
+                <p class="text-sm max-w-prose">
+                    These look like food you've already counted.
+                </p>
+                {cards}
+                <form
+                    hx-post="{handle_chat}"
+                    hx-target="closest div[data-name='food-suggestions']"
+                >
+                    <input type="hidden" value="{prompt}" name="chat" />
+                    <input type="hidden" value="true" name="skip_suggestions" />
+                    <button
+                        class="bg-blue-100 hover:bg-blue-200 dark:bg-blue-800
+                        dark:hover:bg-blue-700 p-1 rounded shadow"
+                    >
+                        None of these; ask the AI
+                    </button>
+                </form>
+            </div>
+            "##
+        )
+    }
+}
+
+/// Count a previously entered food again, by creating a new eaten event
+/// which points back at the same row in `food`.
+pub async fn use_previous_food(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "use previous food")?;
+    let preferences = session.get_preferences(&db).await?;
+    let result = query!(
+        "insert into food_eaten_event (user_id, food_id)
+        select user_id, id from food where id = $1 and user_id = $2",
+        id,
+        session.user_id
+    )
+    .execute(&db)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(ServerError::bad_request(
+            "attempt to use non-existent previous food",
+            None,
+        ));
+    }
+    let response_headers = client_events::reload_macros(HeaderMap::new());
+    let meals = list_meals_op(&db, session.user_id, &preferences, 0).await?;
+    Ok((
+        response_headers,
+        Chat {
+            food_items: &meals,
+            preferences: &preferences,
+            prompt: None,
+            next_page: 1,
+            post_request_handler: Route::HandleChat,
+            is_anonymous: is_anon(&session.username),
+        }
+        .render(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_suggestions_allow_falling_back_to_llm() {
+        let food_items = [FoodItem {
+            id: 7,
+            eaten_event_id: 1,
+            hide_calories: false,
+            details: FoodItemDetails {
+                calories: 180,
+                protein_grams: 30,
+                carbohydrates_grams: 8,
+                fat_grams: 3,
+                food_name: "Protein Shake".into(),
+                eaten_at: utc_now(),
+            },
+        }];
+        let rendered = FoodSuggestions {
+            food_items: &food_items,
+            prompt: r#"protein "shake""#,
+            user_timezone: Tz::UTC,
+        }
+        .render();
+        assert!(rendered.contains(&Route::UsePreviousFood(Some(7)).as_string()));
+        assert!(rendered.contains("value=\"protein &quot;shake&quot;\""));
+        assert!(rendered.contains("name=\"skip_suggestions\""));
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("pb&j"), "pb&j");
+        assert_eq!(escape_like("100% juice"), "100\\% juice");
+        assert_eq!(escape_like("a_b\\c"), "a\\_b\\\\c");
+    }
+}
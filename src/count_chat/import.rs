@@ -0,0 +1,742 @@
+//! Bulk import of food history from a CSV file, so that people coming from
+//! another tracker can bring their history with them. The layout of the file
+//! is detected from its header row; we support our own export, a simple
+//! `date,name,calories,protein,carbs,fat` layout, and exports from
+//! MyFitnessPal and Cronometer.
+//!
+//! The file is read into a textarea in the browser, so the import is a plain
+//! form submission. Submitting it once renders a preview, and submitting it
+//! again (from the preview) saves every valid row in one transaction.
+
+use super::{
+    counter::save_food_op,
+    food_card::{FoodCard, RenderingBehavior},
+    FoodItemDetails,
+};
+use crate::prelude::*;
+
+/// Number of food cards that we'll render in the preview; the rest are
+/// summarized in a count.
+const IMPORT_PREVIEW_SIZE: usize = 20;
+
+/// Largest file that we'll import. Bigger requests are already turned away
+/// by axum's default body limit of 2 MB, but this gives a friendlier error.
+const MAX_IMPORT_BYTES: usize = 1024 * 1024;
+
+/// Most rows that we'll import from one file.
+const MAX_IMPORT_ROWS: usize = 5000;
+
+/// Nobody eats more than this in one sitting; bigger values are mistakes.
+/// Together with [MAX_IMPORT_ROWS], these keep the daily totals, which are
+/// summed as `i32`, from overflowing.
+const MAX_CALORIES: i32 = 10_000;
+const MAX_GRAMS: i32 = 1000;
+
+/// Maps our columns onto the column headers of a CSV layout. Headers are
+/// matched case-insensitively.
+struct Layout {
+    name: &'static str,
+    date: &'static str,
+    /// If the layout has no time column (or a row's time is blank), we'll
+    /// guess the time from the food name; see [default_time].
+    time: Option<&'static str>,
+    food_name: &'static str,
+    calories: &'static str,
+    protein: &'static str,
+    carbohydrates: &'static str,
+    fat: &'static str,
+}
+
+const LAYOUTS: [Layout; 4] = [
+    Layout {
+        name: "Calcount export",
+        date: "eaten_at",
+        time: None,
+        food_name: "food_name",
+        calories: "calories",
+        protein: "protein_grams",
+        carbohydrates: "carbohydrates_grams",
+        fat: "fat_grams",
+    },
+    Layout {
+        name: "CSV",
+        date: "date",
+        time: None,
+        food_name: "name",
+        calories: "calories",
+        protein: "protein",
+        carbohydrates: "carbs",
+        fat: "fat",
+    },
+    // MyFitnessPal's nutrition export has one row per meal, rather than one
+    // row per food.
+    Layout {
+        name: "MyFitnessPal",
+        date: "date",
+        time: None,
+        food_name: "meal",
+        calories: "calories",
+        protein: "protein (g)",
+        carbohydrates: "carbohydrates (g)",
+        fat: "fat (g)",
+    },
+    Layout {
+        name: "Cronometer",
+        date: "day",
+        time: Some("time"),
+        food_name: "food name",
+        calories: "energy (kcal)",
+        protein: "protein (g)",
+        carbohydrates: "carbs (g)",
+        fat: "fat (g)",
+    },
+];
+
+/// Column indices for a [Layout], resolved against a header row.
+struct Columns {
+    date: usize,
+    time: Option<usize>,
+    food_name: usize,
+    calories: usize,
+    protein: usize,
+    carbohydrates: usize,
+    fat: usize,
+}
+
+impl Layout {
+    fn resolve(&self, header: &[String]) -> Option<Columns> {
+        let find = |name: &str| {
+            header
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        };
+        Some(Columns {
+            date: find(self.date)?,
+            time: match self.time {
+                Some(time) => Some(find(time)?),
+                None => None,
+            },
+            food_name: find(self.food_name)?,
+            calories: find(self.calories)?,
+            protein: find(self.protein)?,
+            carbohydrates: find(self.carbohydrates)?,
+            fat: find(self.fat)?,
+        })
+    }
+}
+
+/// Split CSV text into records, per RFC 4180. Quoted fields may contain
+/// commas, escaped quotes, and newlines. Each record is returned with the
+/// (1-indexed) line number where it starts. Blank lines are skipped.
+fn parse_csv(text: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(char) = chars.next() {
+        match (char, in_quotes) {
+            ('"', true) => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|f| !f.trim().is_empty()) {
+                    records.push((record_line, std::mem::take(&mut record)));
+                } else {
+                    record.clear();
+                }
+                line += 1;
+                record_line = line;
+            }
+            (char, _) => {
+                if char == '\n' {
+                    line += 1;
+                }
+                field.push(char);
+            }
+        }
+    }
+    record.push(field);
+    if record.iter().any(|f| !f.trim().is_empty()) {
+        records.push((record_line, record));
+    }
+    records
+}
+
+/// Guess a time of day for rows which only have a date. MyFitnessPal rows
+/// are named after meals, so those can be placed sensibly; anything else
+/// goes at noon.
+fn default_time(food_name: &str) -> NaiveTime {
+    let name = food_name.to_lowercase();
+    let hour = if name.contains("breakfast") {
+        8
+    } else if name.contains("lunch") {
+        12
+    } else if name.contains("dinner") {
+        18
+    } else if name.contains("snack") {
+        15
+    } else {
+        12
+    };
+    NaiveTime::from_hms_opt(hour, 0, 0).expect("hour is valid")
+}
+
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    ["%I:%M %p", "%I:%M:%S %p", "%H:%M", "%H:%M:%S"]
+        .iter()
+        .find_map(|fmt| NaiveTime::parse_from_str(value, fmt).ok())
+}
+
+fn parse_eaten_at(
+    date: &str,
+    time: Option<&str>,
+    food_name: &str,
+    timezone: Tz,
+) -> Result<DateTime<Utc>, String> {
+    let date = date.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Ok(datetime.to_utc());
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(date, fmt).ok());
+    let naive = match naive {
+        Some(naive) => naive,
+        None => {
+            let day = ["%Y-%m-%d", "%m/%d/%Y"]
+                .iter()
+                .find_map(|fmt| NaiveDate::parse_from_str(date, fmt).ok())
+                .ok_or_else(|| format!("{date} is not a valid date"))?;
+            let time = match time.map(str::trim).filter(|t| !t.is_empty()) {
+                Some(time) => parse_time(time)
+                    .ok_or_else(|| format!("{time} is not a valid time"))?,
+                None => default_time(food_name),
+            };
+            day.and_time(time)
+        }
+    };
+    timezone
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.to_utc())
+        .ok_or_else(|| format!("{naive} does not exist in {timezone}"))
+}
+
+/// Nutrition values may be decimals, and are rounded. Blank macros are zero,
+/// but calories are required.
+fn parse_amount(
+    value: &str,
+    column: &str,
+    is_required: bool,
+    max: i32,
+) -> Result<i32, String> {
+    let value = value.trim().replace(',', "");
+    if value.is_empty() {
+        return if is_required {
+            Err(format!("{column} is missing"))
+        } else {
+            Ok(0)
+        };
+    }
+    match value.parse::<f64>() {
+        Ok(amount) if !amount.is_finite() => {
+            Err(format!("{column} is not a number ({value})"))
+        }
+        Ok(amount) if amount < 0.0 => {
+            Err(format!("{column} cannot be negative"))
+        }
+        Ok(amount) if amount.round() > f64::from(max) => {
+            Err(format!("{column} cannot be more than {max}"))
+        }
+        Ok(amount) => Ok(amount.round() as i32),
+        Err(_) => Err(format!("{column} is not a number ({value})")),
+    }
+}
+
+fn parse_row(
+    row: &[String],
+    columns: &Columns,
+    timezone: Tz,
+) -> Result<FoodItemDetails, String> {
+    let get = |i: usize| {
+        row.get(i)
+            .map(|v| v.as_str())
+            .ok_or_else(|| "row is missing columns".to_string())
+    };
+    let food_name = get(columns.food_name)?.trim();
+    if food_name.is_empty() {
+        return Err("name is missing".into());
+    }
+    let time = match columns.time {
+        Some(i) => Some(get(i)?),
+        None => None,
+    };
+    Ok(FoodItemDetails {
+        eaten_at: parse_eaten_at(
+            get(columns.date)?,
+            time,
+            food_name,
+            timezone,
+        )?,
+        food_name: food_name.to_string(),
+        calories: parse_amount(
+            get(columns.calories)?,
+            "calories",
+            true,
+            MAX_CALORIES,
+        )?,
+        protein_grams: parse_amount(
+            get(columns.protein)?,
+            "protein",
+            false,
+            MAX_GRAMS,
+        )?,
+        carbohydrates_grams: parse_amount(
+            get(columns.carbohydrates)?,
+            "carbs",
+            false,
+            MAX_GRAMS,
+        )?,
+        fat_grams: parse_amount(get(columns.fat)?, "fat", false, MAX_GRAMS)?,
+    })
+}
+
+struct InvalidLine {
+    line: usize,
+    reason: String,
+}
+
+struct ParsedImport {
+    layout: &'static str,
+    food: Vec<FoodItemDetails>,
+    invalid_lines: Vec<InvalidLine>,
+}
+
+fn parse_import(csv: &str, timezone: Tz) -> Result<ParsedImport, ServerError> {
+    if csv.len() > MAX_IMPORT_BYTES {
+        return Err(ServerError::bad_request(
+            "import is too large",
+            Some(
+                "The file is too large; please import at most 1 MB at a time"
+                    .into(),
+            ),
+        ));
+    }
+    let records = parse_csv(csv);
+    // The header doesn't count towards the limit.
+    if records.len() > MAX_IMPORT_ROWS + 1 {
+        return Err(ServerError::bad_request(
+            "import has too many rows",
+            Some(format!(
+                "The file has too many rows; please import at most {MAX_IMPORT_ROWS} at a time"
+            )),
+        ));
+    }
+    let mut records = records.iter();
+    let (_, header) = records.next().ok_or_else(|| {
+        ServerError::bad_request(
+            "empty import",
+            Some("The file is empty".into()),
+        )
+    })?;
+    let (layout, columns) = LAYOUTS
+        .iter()
+        .find_map(|layout| Some((layout, layout.resolve(header)?)))
+        .ok_or_else(|| {
+            ServerError::bad_request(
+                "unknown import layout",
+                Some("The columns in this file were not recognized".into()),
+            )
+        })?;
+    let mut food = vec![];
+    let mut invalid_lines = vec![];
+    for (line, row) in records {
+        match parse_row(row, &columns, timezone) {
+            Ok(item) => food.push(item),
+            Err(reason) => invalid_lines.push(InvalidLine {
+                line: *line,
+                reason,
+            }),
+        }
+    }
+    Ok(ParsedImport {
+        layout: layout.name,
+        food,
+        invalid_lines,
+    })
+}
+
+struct ImportForm;
+impl Component for ImportForm {
+    fn render(&self) -> String {
+        let preview = Route::ImportFoodPreview;
+        let home = Route::UserHome;
+        format!(
+            r##"
+            <div class="flex flex-col gap-2 max-w-prose dark:text-slate-200">
+                <a href="{home}">
+                    <button
+                        class="dark:bg-emerald-700 dark:hover:bg-emerald-800
+                        bg-emerald-100 hover:bg-emerald-200 p-1 rounded"
+                    >
+                        Home
+                    </button>
+                </a>
+                <h1 class="text-2xl font-extrabold">Import Food</h1>
+                <p>
+                    Upload a CSV file with the columns <code>date, name,
+                    calories, protein, carbs, fat</code>, or an export from
+                    MyFitnessPal (nutrition export) or Cronometer (servings
+                    export). Dates without a time are placed at a sensible
+                    time in your timezone.
+                </p>
+                <form
+                    id="import-form"
+                    class="flex flex-col gap-2"
+                    hx-post="{preview}"
+                    hx-target="#import-preview"
+                    hx-swap="innerHTML"
+                >
+                    <input type="file" accept=".csv,text/csv" id="import-file" />
+                    <label for="import-csv" class="text-sm">
+                        Or, paste CSV here
+                    </label>
+                    <textarea
+                        class="rounded dark:text-black font-mono text-xs"
+                        rows="8"
+                        id="import-csv"
+                        name="csv"
+                        required
+                    ></textarea>
+                    <button
+                        class="bg-blue-100 hover:bg-blue-200 dark:bg-blue-800
+                        dark:hover:bg-blue-700 p-1 rounded"
+                    >
+                        Preview
+                    </button>
+                </form>
+                <div id="import-preview"></div>
+            </div>
+            <script>
+                document.getElementById('import-file').addEventListener(
+                    'change',
+                    async (e) => {{
+                        const file = e.target.files[0];
+                        if (file) {{
+                            document.getElementById('import-csv').value =
+                                await file.text();
+                        }}
+                    }}
+                );
+            </script>
+            "##
+        )
+    }
+}
+
+struct ImportPreview<'a> {
+    import: &'a ParsedImport,
+    preferences: &'a UserPreference,
+}
+impl Component for ImportPreview<'_> {
+    fn render(&self) -> String {
+        let layout = self.import.layout;
+        let save = Route::ImportFood;
+        let valid_count = self.import.food.len();
+        let invalid_count = self.import.invalid_lines.len();
+        let invalid_lines = if self.import.invalid_lines.is_empty() {
+            "".into()
+        } else {
+            let items = self.import.invalid_lines.iter().fold(
+                String::new(),
+                |mut acc, invalid| {
+                    let line = invalid.line;
+                    let reason = clean(&invalid.reason);
+                    acc.push_str(&format!("<li>Line {line}: {reason}</li>"));
+                    acc
+                },
+            );
+            format!(
+                r#"
+                <div class="bg-yellow-100 dark:bg-yellow-800 p-2 rounded">
+                    <p>
+                        {invalid_count} line(s) could not be read, and will be
+                        skipped:
+                    </p>
+                    <ul class="list-disc list-inside text-sm">{items}</ul>
+                </div>
+                "#
+            )
+        };
+        let cards = self.import.food.iter().take(IMPORT_PREVIEW_SIZE).fold(
+            String::new(),
+            |mut acc, info| {
+                acc.push_str(
+                    &FoodCard {
+                        info,
+                        identifiers: None,
+                        actions: None,
+                        rendering_behavior: RenderingBehavior::UseTimezone(
+                            self.preferences.timezone,
+                        ),
+                        show_ai_warning: false,
+                        hide_calories: self.preferences.hide_calories,
+                    }
+                    .render(),
+                );
+                acc
+            },
+        );
+        let remaining = valid_count.saturating_sub(IMPORT_PREVIEW_SIZE);
+        let remaining = if remaining > 0 {
+            format!("<p>...and {remaining} more.</p>")
+        } else {
+            "".into()
+        };
+        let save_button = if valid_count > 0 {
+            format!(
+                r##"
+                <button
+                    hx-post="{save}"
+                    hx-include="#import-form"
+                    hx-target="#import-preview"
+                    hx-swap="innerHTML"
+                    class="bg-emerald-100 hover:bg-emerald-200
+                    dark:bg-emerald-700 dark:hover:bg-emerald-800 p-1 rounded"
+                >
+                    Import {valid_count} item(s)
+                </button>
+                "##
+            )
+        } else {
+            "".into()
+        };
+        format!(
+            r#"
+            <div class="flex flex-col gap-2">
+                <p>
+                    Found {valid_count} item(s) in this {layout} file.
+                </p>
+                {invalid_lines}
+                {save_button}
+                <div class="flex flex-wrap gap-2">{cards}</div>
+                {remaining}
+            </div>
+            "#
+        )
+    }
+}
+
+pub async fn import_food_page(
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    Session::from_headers_err(&headers, "import food page")?;
+    Ok(Page {
+        title: "Import Food",
+        children: &PageContainer {
+            children: &ImportForm,
+        },
+    }
+    .render())
+}
+
+#[derive(Deserialize)]
+pub struct ImportPayload {
+    csv: String,
+}
+
+pub async fn preview_food_import(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(ImportPayload { csv }): Form<ImportPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "preview food import")?;
+    let preferences = session.get_preferences(&db).await?;
+    let import = parse_import(&csv, preferences.timezone)?;
+    Ok(ImportPreview {
+        import: &import,
+        preferences: &preferences,
+    }
+    .render())
+}
+
+pub async fn handle_food_import(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(ImportPayload { csv }): Form<ImportPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "food import")?;
+    let preferences = session.get_preferences(&db).await?;
+    let import = parse_import(&csv, preferences.timezone)?;
+    let mut tx = db.begin().await?;
+    for food in &import.food {
+        save_food_op(&mut *tx, session.user_id, food).await?;
+    }
+    tx.commit().await?;
+    let count = import.food.len();
+    let home = Route::UserHome;
+    Ok(format!(
+        r#"
+        <p class="bg-emerald-100 dark:bg-emerald-800 p-2 rounded">
+            Imported {count} item(s). <a class="link" href="{home}">Go home</a>
+        </p>
+        "#
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_quoting() {
+        let records =
+            parse_csv("a,b\r\n\"x, \"\"y\"\"\",\"multi\nline\"\n\n1,2");
+        assert_eq!(
+            records,
+            vec![
+                (1, vec!["a".to_string(), "b".to_string()]),
+                (2, vec![r#"x, "y""#.to_string(), "multi\nline".to_string()]),
+                (5, vec!["1".to_string(), "2".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simple_layout() {
+        let import = parse_import(
+            "date,name,calories,protein,carbs,fat\n\
+            2024-06-01,Oatmeal,300,10,50,5\n\
+            2024-06-01,Mystery,,1,1,1\n\
+            yesterday,Toast,100,3,20,1",
+            Tz::America__New_York,
+        )
+        .unwrap();
+        assert_eq!(import.layout, "CSV");
+        assert_eq!(import.food.len(), 1);
+        assert_eq!(import.food[0].food_name, "Oatmeal");
+        assert_eq!(import.food[0].calories, 300);
+        assert_eq!(
+            import.food[0].eaten_at.to_rfc3339(),
+            "2024-06-01T16:00:00+00:00"
+        );
+        assert_eq!(import.invalid_lines.len(), 2);
+        assert_eq!(import.invalid_lines[0].line, 3);
+        assert_eq!(import.invalid_lines[0].reason, "calories is missing");
+        assert_eq!(import.invalid_lines[1].line, 4);
+    }
+
+    #[test]
+    fn test_calcount_export_layout() {
+        let import = parse_import(
+            "eaten_at,food_name,calories,protein_grams,carbohydrates_grams,fat_grams\n\
+            2024-06-29T19:47:33-04:00,\"Sandwich, turkey\",500,20,50,25",
+            Tz::UTC,
+        )
+        .unwrap();
+        assert_eq!(import.layout, "Calcount export");
+        assert_eq!(import.food[0].food_name, "Sandwich, turkey");
+        assert_eq!(import.food[0].eaten_at, utc_now());
+    }
+
+    #[test]
+    fn test_myfitnesspal_layout() {
+        let import = parse_import(
+            "Date,Meal,Calories,Fat (g),Saturated Fat,Carbohydrates (g),Protein (g)\n\
+            2024-06-01,Breakfast,410.5,12.2,3,55.7,20.4\n\
+            2024-06-01,Dinner,\"1,200\",40,10,100,60",
+            Tz::UTC,
+        )
+        .unwrap();
+        assert_eq!(import.layout, "MyFitnessPal");
+        assert_eq!(import.food[0].calories, 411);
+        assert_eq!(import.food[0].carbohydrates_grams, 56);
+        assert_eq!(
+            import.food[0].eaten_at.to_rfc3339(),
+            "2024-06-01T08:00:00+00:00"
+        );
+        assert_eq!(import.food[1].calories, 1200);
+        assert_eq!(
+            import.food[1].eaten_at.to_rfc3339(),
+            "2024-06-01T18:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_cronometer_layout() {
+        let import = parse_import(
+            "Day,Time,Group,Food Name,Amount,Energy (kcal),Carbs (g),Fat (g),Protein (g)\n\
+            2024-06-01,7:30 AM,Breakfast,Eggs,2 large,143,0.7,9.5,12.6\n\
+            2024-06-01,,Snacks,Apple,1 medium,95,25,0.3,0.5\n\
+            2024-06-01,-3,Snacks,Pear,1 medium,-1,25,0.3,0.5",
+            Tz::UTC,
+        )
+        .unwrap();
+        assert_eq!(import.layout, "Cronometer");
+        assert_eq!(import.food.len(), 2);
+        assert_eq!(
+            import.food[0].eaten_at.to_rfc3339(),
+            "2024-06-01T07:30:00+00:00"
+        );
+        assert_eq!(import.food[0].protein_grams, 13);
+        assert_eq!(
+            import.food[1].eaten_at.to_rfc3339(),
+            "2024-06-01T12:00:00+00:00"
+        );
+        assert_eq!(import.invalid_lines[0].reason, "-3 is not a valid time");
+    }
+
+    #[test]
+    fn test_parse_amount_limits() {
+        assert_eq!(parse_amount("10000", "calories", true, 10_000), Ok(10_000));
+        assert_eq!(
+            parse_amount("10001", "calories", true, 10_000),
+            Err("calories cannot be more than 10000".into())
+        );
+        assert_eq!(
+            parse_amount("2000000000", "fat", false, 1000),
+            Err("fat cannot be more than 1000".into())
+        );
+        assert_eq!(
+            parse_amount("-1", "fat", false, 1000),
+            Err("fat cannot be negative".into())
+        );
+        for value in ["NaN", "inf", "-infinity"] {
+            assert_eq!(
+                parse_amount(value, "fat", false, 1000),
+                Err(format!("fat is not a number ({value})"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_row_limit() {
+        let header = "date,name,calories,protein,carbs,fat\n";
+        let row = "2024-06-01,Oatmeal,300,10,50,5\n";
+        let csv = format!("{header}{}", row.repeat(MAX_IMPORT_ROWS));
+        assert_eq!(
+            parse_import(&csv, Tz::UTC).unwrap().food.len(),
+            MAX_IMPORT_ROWS
+        );
+        let csv = format!("{csv}{row}");
+        assert!(parse_import(&csv, Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn test_unknown_layout() {
+        assert!(parse_import("foo,bar\n1,2", Tz::UTC).is_err());
+        assert!(parse_import("", Tz::UTC).is_err());
+    }
+}
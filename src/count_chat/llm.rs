@@ -0,0 +1,162 @@
+//! The LLM backend is chosen at startup via the `LLM_BACKEND` environment
+//! variable, and held in [crate::models::AppState].
+//!
+//! - `openai` (default); the OpenAI API, using `OPENAI_API_KEY`
+//! - `openai_compatible`; any server which implements the OpenAI chat
+//!   completions API. Configured via `LLM_BASE_URL`, `LLM_MODEL`, and
+//!   (optionally) `LLM_API_KEY`
+//! - `fixture`; a deterministic offline stub which reads canned responses
+//!   from the JSON file at `LLM_FIXTURE_PATH`
+//!
+//! The `openai` backend always asks for structured output matching
+//! [super::llm_parse_response::food_estimate_schema]. For the
+//! `openai_compatible` backend, set `LLM_STRUCTURED_OUTPUT=true` if the server
+//! supports JSON schema response formats. Otherwise, responses are prose.
+
+use super::openai::OpenAI;
+use anyhow::{bail, Error, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, env, fmt::Debug, fs, sync::Arc};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: i32,
+    pub completion_tokens: i32,
+    pub total_tokens: i32,
+}
+
+pub struct Response {
+    pub message: String,
+    pub usage: Usage,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// One turn in a conversation with the LLM. The first message is always the
+/// user's food description; later messages are follow-up questions from the
+/// LLM, and the user's answers.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+#[async_trait]
+pub trait LlmBackend: Debug + Send + Sync {
+    async fn send_message(
+        &self,
+        system_msg: String,
+        messages: &[ChatMessage],
+    ) -> Result<Response>;
+}
+
+pub fn llm_backend_from_env() -> Result<Arc<dyn LlmBackend>> {
+    let backend = env::var("LLM_BACKEND").unwrap_or("openai".into());
+    Ok(match backend.as_str() {
+        "openai" => Arc::new(OpenAI::from_env()?),
+        "openai_compatible" => Arc::new(OpenAI::compatible_from_env()?),
+        "fixture" => Arc::new(Fixture::from_env()?),
+        other => bail!("unknown LLM_BACKEND {other}"),
+    })
+}
+
+/// Canned LLM responses, keyed by the latest message sent by the user; this
+/// is the food description, or the answer to a follow-up question. The
+/// special key `*` is used as a fallback for messages which are not in the
+/// fixture file. For example;
+///
+/// ```json
+/// {
+///   "protein shake": "180 calories, 30g of protein, 3g of fat, and 8g of carbohydrates",
+///   "*": "100 calories, 0g of protein, 0g of fat, and 25g of carbohydrates"
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Fixture {
+    responses: HashMap<String, String>,
+}
+
+impl Fixture {
+    pub fn from_env() -> Result<Self> {
+        let path = env::var("LLM_FIXTURE_PATH")?;
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+    fn from_json(json: &str) -> Result<Self> {
+        Ok(Self {
+            responses: serde_json::from_str(json)?,
+        })
+    }
+}
+
+#[async_trait]
+impl LlmBackend for Fixture {
+    async fn send_message(
+        &self,
+        _system_msg: String,
+        messages: &[ChatMessage],
+    ) -> Result<Response> {
+        let latest = messages
+            .iter()
+            .rev()
+            .find(|m| m.role == Role::User)
+            .map_or("", |m| &m.content);
+        let message = self
+            .responses
+            .get(latest)
+            .or_else(|| self.responses.get("*"))
+            .ok_or_else(|| {
+                Error::msg(format!("no fixture response for {latest}"))
+            })?;
+        Ok(Response {
+            message: message.clone(),
+            usage: Usage::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn user_message(content: &str) -> Vec<ChatMessage> {
+        vec![ChatMessage {
+            role: Role::User,
+            content: content.into(),
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_fixture_lookup() {
+        let fixture = Fixture::from_json(
+            r#"{"protein shake": "180 calories", "*": "100 calories"}"#,
+        )
+        .unwrap();
+        let res = fixture
+            .send_message("".into(), &user_message("protein shake"))
+            .await
+            .unwrap();
+        assert_eq!(res.message, "180 calories");
+        assert_eq!(res.usage.total_tokens, 0);
+        let res = fixture
+            .send_message("".into(), &user_message("apple"))
+            .await
+            .unwrap();
+        assert_eq!(res.message, "100 calories");
+    }
+
+    #[tokio::test]
+    async fn test_fixture_without_fallback() {
+        let fixture =
+            Fixture::from_json(r#"{"protein shake": "180 calories"}"#).unwrap();
+        assert!(fixture
+            .send_message("".into(), &user_message("apple"))
+            .await
+            .is_err());
+    }
+}
@@ -1,10 +1,136 @@
 //! An ad-hoc regex-y LLM response parser. Tries to tell the LLM to do better
 //! next time an a resonably well-structured declarative way. Inspired by
 //! https://www.youtube.com/watch?v=yj-wSRJwrrc.
+//!
+//! When the LLM backend supports structured output, we'll ask for a response
+//! matching [food_estimate_schema] instead, and the regex parser becomes the
+//! fallback.
 
 use super::FoodItemDetails;
 use crate::chrono_utils::utc_now;
 use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// A structured response from the LLM. This must be kept in sync with
+/// [food_estimate_schema].
+#[derive(Debug, Deserialize)]
+pub struct FoodEstimate {
+    pub name: String,
+    pub serving_size: String,
+    pub calories: i32,
+    pub protein_grams: i32,
+    pub fat_grams: i32,
+    pub carbohydrates_grams: i32,
+    pub confidence: Confidence,
+    /// The LLM may ask a question if the user was very vague.
+    pub follow_up_question: Option<String>,
+}
+
+/// A structured response may describe several foods, like "eggs, toast and
+/// coffee." Single estimates are also accepted, in case an OpenAI-compatible
+/// server ignores the outer object.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FoodEstimates {
+    List { items: Vec<FoodEstimate> },
+    Single(FoodEstimate),
+}
+
+/// JSON schema for a list of [FoodEstimate], in the shape expected by the
+/// OpenAI structured outputs API (all properties required, no additional
+/// properties).
+pub fn food_estimate_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "items": {
+                "type": "array",
+                "description": "One estimate for each distinct food that the client described",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "serving_size": { "type": "string" },
+                        "calories": { "type": "integer" },
+                        "protein_grams": { "type": "integer" },
+                        "fat_grams": { "type": "integer" },
+                        "carbohydrates_grams": { "type": "integer" },
+                        "confidence": {
+                            "type": "string",
+                            "enum": ["low", "medium", "high"]
+                        },
+                        "follow_up_question": { "type": ["string", "null"] }
+                    },
+                    "required": [
+                        "name",
+                        "serving_size",
+                        "calories",
+                        "protein_grams",
+                        "fat_grams",
+                        "carbohydrates_grams",
+                        "confidence",
+                        "follow_up_question"
+                    ],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["items"],
+        "additionalProperties": false
+    })
+}
+
+impl FoodEstimate {
+    /// Returns `None` if the LLM did not respond with JSON matching our
+    /// schema, in which case the caller should fall back to
+    /// [FoodItemDetails::parse].
+    pub fn parse(llm_text: &str) -> Option<Vec<Self>> {
+        // OpenAI-compatible servers without strict structured output support
+        // sometimes wrap JSON in a markdown code fence.
+        let text = llm_text
+            .trim()
+            .trim_start_matches("```json")
+            .trim_start_matches("```")
+            .trim_end_matches("```")
+            .trim();
+        match serde_json::from_str(text).ok()? {
+            FoodEstimates::List { items } if items.is_empty() => None,
+            FoodEstimates::List { items } => Some(items),
+            FoodEstimates::Single(estimate) => Some(vec![estimate]),
+        }
+    }
+    /// For a single food, the name is what the user typed, for consistency
+    /// with food parsed from prose responses.
+    pub fn to_details(&self, food_name: &str) -> FoodItemDetails {
+        FoodItemDetails {
+            food_name: food_name.to_string(),
+            calories: self.calories,
+            protein_grams: self.protein_grams,
+            carbohydrates_grams: self.carbohydrates_grams,
+            fat_grams: self.fat_grams,
+            eaten_at: utc_now(),
+        }
+    }
+}
 
 impl FoodItemDetails {
     pub fn parse(llm_text: &str, food_name: &str) -> Result<Self, ()> {
@@ -54,6 +180,30 @@ impl FoodItemDetails {
     }
 }
 
+impl FoodItemDetails {
+    /// Prose responses about several foods put each food on its own line,
+    /// like "Eggs: 140 calories, 12g of protein, 10g of fat, and 1g of
+    /// carbohydrates." Lines without a food name, or without a complete
+    /// estimate, are skipped. A "Total" line is skipped too, since we'd
+    /// otherwise count everything twice.
+    pub fn parse_items(llm_text: &str) -> Vec<Self> {
+        let list_marker = Regex::new(r"^\s*([-*•]|\d+[.)])\s+")
+            .expect("list marker regex is valid");
+        llm_text
+            .lines()
+            .filter_map(|line| {
+                let line = list_marker.replace(line, "");
+                let (name, estimate) = line.split_once(':')?;
+                let name = name.trim().trim_matches('*').trim();
+                if name.is_empty() || name.eq_ignore_ascii_case("total") {
+                    return None;
+                }
+                Self::parse(estimate, name).ok()
+            })
+            .collect()
+    }
+}
+
 /// Returns the parsed i32 inside the match object, or a response message for
 /// the LLM.
 fn handle_capture<'a>(
@@ -103,6 +253,136 @@ fn handle_capture<'a>(
 mod test {
     use super::*;
 
+    #[test]
+    fn test_parse_structured_response() {
+        let mut estimates = FoodEstimate::parse(
+            r#"{
+                "items": [
+                    {
+                        "name": "Oatmeal",
+                        "serving_size": "1 cup cooked",
+                        "calories": 160,
+                        "protein_grams": 6,
+                        "fat_grams": 3,
+                        "carbohydrates_grams": 27,
+                        "confidence": "high",
+                        "follow_up_question": null
+                    }
+                ]
+            }"#,
+        )
+        .expect("we should be able to parse this input");
+        assert_eq!(estimates.len(), 1);
+        let estimate = estimates.remove(0);
+        assert_eq!(estimate.confidence, Confidence::High);
+        assert!(estimate.follow_up_question.is_none());
+        let details = estimate.to_details("a bowl of oatmeal");
+        assert_eq!(details.food_name, "a bowl of oatmeal");
+        assert_eq!(details.calories, 160);
+        assert_eq!(details.protein_grams, 6);
+        assert_eq!(details.fat_grams, 3);
+        assert_eq!(details.carbohydrates_grams, 27);
+    }
+
+    #[test]
+    fn test_parse_structured_response_in_code_fence() {
+        let mut estimates = FoodEstimate::parse(
+            "```json
+            {
+                \"name\": \"Sandwich\",
+                \"serving_size\": \"1 sandwich\",
+                \"calories\": 400,
+                \"protein_grams\": 20,
+                \"fat_grams\": 15,
+                \"carbohydrates_grams\": 45,
+                \"confidence\": \"low\",
+                \"follow_up_question\": \"What was in the sandwich?\"
+            }
+            ```",
+        )
+        .expect("we should be able to parse this input");
+        let estimate = estimates.remove(0);
+        assert_eq!(estimate.confidence, Confidence::Low);
+        assert_eq!(
+            estimate.follow_up_question.as_deref(),
+            Some("What was in the sandwich?")
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_items() {
+        let estimates = FoodEstimate::parse(
+            r#"{
+                "items": [
+                    {
+                        "name": "Eggs",
+                        "serving_size": "2 large",
+                        "calories": 140,
+                        "protein_grams": 12,
+                        "fat_grams": 10,
+                        "carbohydrates_grams": 1,
+                        "confidence": "high",
+                        "follow_up_question": null
+                    },
+                    {
+                        "name": "Toast",
+                        "serving_size": "1 slice",
+                        "calories": 80,
+                        "protein_grams": 3,
+                        "fat_grams": 1,
+                        "carbohydrates_grams": 15,
+                        "confidence": "medium",
+                        "follow_up_question": null
+                    }
+                ]
+            }"#,
+        )
+        .expect("we should be able to parse this input");
+        assert_eq!(estimates.len(), 2);
+        assert_eq!(estimates[0].name, "Eggs");
+        assert_eq!(estimates[1].name, "Toast");
+    }
+
+    #[test]
+    fn test_empty_item_list_is_not_a_structured_response() {
+        assert!(FoodEstimate::parse(r#"{"items": []}"#).is_none());
+    }
+
+    #[test]
+    fn test_prose_is_not_a_structured_response() {
+        assert!(FoodEstimate::parse(
+            "100 calories, 10g of fat, 11g of protein, 12g of carbs"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_parse_items_from_prose() {
+        let items = FoodItemDetails::parse_items(
+            "Here are your estimates:
+
+            - **Eggs:** 140 calories, 12g of protein, 10g of fat, and 1g of carbohydrates.
+            - Toast: 80 calories, 3g of protein, 1g of fat, and 15g of carbohydrates.
+            3. 2 Coffees: 5 calories, 0g of protein, 0g of fat, and 0g of carbohydrates.
+            Total: 225 calories, 15g of protein, 11g of fat, and 16g of carbohydrates.
+            Note: these are estimates.",
+        );
+        let names: Vec<&str> =
+            items.iter().map(|i| i.food_name.as_str()).collect();
+        assert_eq!(names, ["Eggs", "Toast", "2 Coffees"]);
+        assert_eq!(items[0].calories, 140);
+        assert_eq!(items[1].carbohydrates_grams, 15);
+        assert_eq!(items[2].protein_grams, 0);
+    }
+
+    #[test]
+    fn test_parse_items_ignores_single_sentence() {
+        assert!(FoodItemDetails::parse_items(
+            "The food you asked about has 100 calories, 10g of protein, 5g of fat, and 12g of carbohydrates."
+        )
+        .is_empty());
+    }
+
     #[test]
     fn test_parse_food_info() {
         let result = FoodItemDetails::parse(
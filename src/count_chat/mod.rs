@@ -1,13 +1,30 @@
+mod conversation;
 mod counter;
+mod edit_food;
 mod food_card;
+mod food_search;
+mod import;
+mod llm;
 mod llm_parse_response;
 mod openai;
 mod prev_food_list;
+mod quota;
+mod saved_meal;
 
 pub use self::{
     counter::{
-        chat_form, handle_chat, handle_save_food, list_food, list_meals_op,
-        prev_day_food_form, Chat as ChatContainer,
+        chat_form, food_candidate_card, handle_chat, handle_follow_up,
+        handle_save_all_food, handle_save_food, handle_save_food_candidate,
+        list_food, list_meals_op, prev_day_food_form, Chat as ChatContainer,
     },
+    edit_food::{edit_food_form, handle_edit_food, show_food},
     food_card::{FoodItem, FoodItemDetails},
+    food_search::use_previous_food,
+    import::{handle_food_import, import_food_page, preview_food_import},
+    llm::{llm_backend_from_env, LlmBackend},
+    saved_meal::{
+        add_food_to_saved_meal, add_saved_meal_to_today, create_saved_meal,
+        delete_saved_meal, remove_food_from_saved_meal, rename_saved_meal,
+        saved_meal_editor, saved_meal_list,
+    },
 };
@@ -1,12 +1,34 @@
+use super::{
+    llm::{ChatMessage, LlmBackend, Response, Role, Usage},
+    llm_parse_response::food_estimate_schema,
+};
 use crate::config;
 use anyhow::{Error, Result};
+use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::{env, fmt};
 
+/// Client for the OpenAI chat completions API, or any other server which is
+/// compatible with it.
 pub struct OpenAI {
     client: Client,
-    api_key: String,
+    /// Self-hosted OpenAI-compatible servers may not require authentication.
+    api_key: Option<String>,
+    base_url: String,
+    model: String,
+    /// Whether the server supports JSON schema response formats.
+    structured_output: bool,
+}
+
+impl fmt::Debug for OpenAI {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenAI")
+            .field("base_url", &self.base_url)
+            .field("model", &self.model)
+            .field("structured_output", &self.structured_output)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Serialize)]
@@ -14,6 +36,8 @@ struct ChatCompletionRequest {
     model: String,
     messages: Vec<ChatCompletionMessage>,
     max_completion_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
 }
 
 #[derive(Serialize)]
@@ -26,6 +50,7 @@ struct ChatCompletionMessage {
 #[allow(non_camel_case_types)]
 enum MessageRole {
     user,
+    assistant,
     system,
 }
 
@@ -35,13 +60,6 @@ struct ChatCompletionResponse {
     usage: Usage,
 }
 
-#[derive(Deserialize)]
-pub struct Usage {
-    pub prompt_tokens: i32,
-    pub completion_tokens: i32,
-    pub total_tokens: i32,
-}
-
 #[derive(Deserialize)]
 struct ChatCompletionResponseMessage {
     message: ChatCompletionResponseMessageContent,
@@ -52,49 +70,92 @@ struct ChatCompletionResponseMessageContent {
     content: Option<String>,
 }
 
-pub struct Response {
-    pub message: String,
-    pub usage: Usage,
-}
-
 impl OpenAI {
     pub fn from_env() -> Result<Self> {
         let api_key = env::var("OPENAI_API_KEY")?;
         Ok(Self {
             client: Client::new(),
-            api_key,
+            api_key: Some(api_key),
+            base_url: "https://api.openai.com/v1".into(),
+            model: "gpt-4o-mini".into(),
+            structured_output: true,
+        })
+    }
+    pub fn compatible_from_env() -> Result<Self> {
+        let base_url = env::var("LLM_BASE_URL")?;
+        let model = env::var("LLM_MODEL")?;
+        Ok(Self {
+            client: Client::new(),
+            api_key: env::var("LLM_API_KEY").ok(),
+            base_url: base_url.trim_end_matches('/').into(),
+            model,
+            structured_output: env::var("LLM_STRUCTURED_OUTPUT")
+                .is_ok_and(|v| v == "true"),
         })
     }
-    pub async fn send_message(
+}
+
+#[async_trait]
+impl LlmBackend for OpenAI {
+    async fn send_message(
         &self,
         system_msg: String,
-        food_description: &str,
+        messages: &[ChatMessage],
     ) -> Result<Response> {
-        if food_description.len() > config::CHAT_MAX_LEN {
+        if messages.iter().any(|m| {
+            m.role == Role::User && m.content.len() > config::CHAT_MAX_LEN
+        }) {
             return Err(Error::msg("tried to send a chat which is too long"));
         };
-        let mut user_message =
-            String::from("The food I'd like a calorie estimate for is ");
-        user_message.push_str(food_description);
-        let payload = ChatCompletionRequest {
-            max_completion_tokens: 200,
-            model: "gpt-4o-mini".into(),
-            messages: vec![
-                ChatCompletionMessage {
-                    role: MessageRole::system,
-                    content: system_msg,
+        let mut payload_messages = vec![ChatCompletionMessage {
+            role: MessageRole::system,
+            content: system_msg,
+        }];
+        for (i, message) in messages.iter().enumerate() {
+            payload_messages.push(match message.role {
+                // The first message is the food description.
+                Role::User if i == 0 => ChatCompletionMessage {
+                    role: MessageRole::user,
+                    content: format!(
+                        "The food I'd like a calorie estimate for is {}",
+                        message.content
+                    ),
                 },
-                ChatCompletionMessage {
+                Role::User => ChatCompletionMessage {
                     role: MessageRole::user,
-                    content: user_message,
+                    content: message.content.clone(),
+                },
+                Role::Assistant => ChatCompletionMessage {
+                    role: MessageRole::assistant,
+                    content: message.content.clone(),
                 },
-            ],
+            });
+        }
+        let response_format = self.structured_output.then(|| {
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "food_estimate",
+                    "strict": true,
+                    "schema": food_estimate_schema()
+                }
+            })
+        });
+        let payload = ChatCompletionRequest {
+            max_completion_tokens: config::LLM_MAX_COMPLETION_TOKENS,
+            response_format,
+            model: self.model.clone(),
+            messages: payload_messages,
         };
         let req = self
             .client
-            .post("https://api.openai.com/v1/chat/completions");
-        let req =
-            req.header("Authorization", format!("Bearer {}", self.api_key));
+            .post(format!("{}/chat/completions", self.base_url));
+        let req = match &self.api_key {
+            Some(api_key) => {
+                req.header("Authorization", format!("Bearer {api_key}"))
+            }
+            None => req,
+        };
         let req = req.json(&payload);
         let res = req.send().await?;
         let text = res.text().await?;
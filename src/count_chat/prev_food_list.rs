@@ -89,10 +89,13 @@ impl Component for FoodList<'_> {
 
 pub struct PrevDayFormActions<'a> {
     pub info: &'a FoodItemDetails,
+    /// Set when this is one of several candidates from a multi-item chat.
+    /// Saving or cancelling only affects this candidate, leaving the others
+    /// in place.
+    pub is_candidate: bool,
 }
 impl Component for PrevDayFormActions<'_> {
     fn render(&self) -> String {
-        let save_meal = Route::SaveFood;
         let default = Route::ChatForm;
         let eaten_at = self.info.eaten_at.format("%d/%m/%Y");
         let script = include_str!("./custom_date_widget_helper.js");
@@ -101,11 +104,72 @@ impl Component for PrevDayFormActions<'_> {
         let protein = self.info.protein_grams;
         let carbs = self.info.carbohydrates_grams;
         let fat = self.info.fat_grams;
+        // Cancelling re-renders a candidate's card, which needs its original
+        // `eaten_at`. That goes in a separate form, since `eaten_at` in the
+        // date form is filled in by JS.
+        let (save_form_attrs, cancel_in_form, cancel_after_form) = if self
+            .is_candidate
+        {
+            let candidate_route = Route::FoodCandidate;
+            let original_eaten_at = self.info.eaten_at;
+            (
+                format!(
+                    r#"
+                    hx-post="{}"
+                    hx-target="closest div[data-name='food-candidate']"
+                    hx-swap="delete"
+                    "#,
+                    Route::SaveFoodCandidate
+                ),
+                "".into(),
+                format!(
+                    r#"
+                    <form
+                        hx-post="{candidate_route}"
+                        hx-target="closest div[data-name='meal-card']"
+                    >
+                        <input type="hidden" value="{food_name}" name="food_name" />
+                        <input type="hidden" value="{calories}" name="calories" />
+                        <input type="hidden" value="{protein}" name="protein_grams" />
+                        <input type="hidden" value="{carbs}" name="carbohydrates_grams" />
+                        <input type="hidden" value="{fat}" name="fat_grams" />
+                        <input type="hidden" value="{original_eaten_at}" name="eaten_at" />
+                        <button
+                            class="block p-2 m-2 bg-red-100 hover:bg-red-200 rounded shadow hover:shadow-none"
+                        >
+                            Cancel
+                        </button>
+                    </form>
+                    "#
+                ),
+            )
+        } else {
+            (
+                format!(
+                    r#"
+                    hx-post="{}"
+                    hx-target="closest div[data-name='meal-card']"
+                    "#,
+                    Route::SaveFood
+                ),
+                format!(
+                    r##"
+                    <button
+                        class="block p-2 m-2 bg-red-100 hover:bg-red-200 rounded shadow hover:shadow-none"
+                        hx-get="{default}"
+                        hx-target="#cal-chat-container"
+                    >
+                        Cancel
+                    </button>
+                    "##
+                ),
+                "".into(),
+            )
+        };
         format!(
             r##"
             <form
-                hx-post="{save_meal}"
-                hx-target="closest div[data-name='meal-card']"
+                {save_form_attrs}
                 class="flex flex-col">
                 <label for="created_date">
                     Date
@@ -119,7 +183,7 @@ impl Component for PrevDayFormActions<'_> {
                 />
                 <!-- This field gets populated by JS when the buttons below are
                 clicked -->
-                <input type="hidden" name="eaten_at" id="eaten_at" />
+                <input type="hidden" name="eaten_at" />
                 <input type="hidden" value="{food_name}" name="food_name" />
                 <input type="hidden" value="{calories}" name="calories" />
                 <input type="hidden" value="{protein}" name="protein_grams" />
@@ -128,37 +192,32 @@ impl Component for PrevDayFormActions<'_> {
                 <p class="text-sm">Approximately what time of day was this meal?</p>
                 <button
                     class="block p-2 m-2 bg-blue-100 hover:bg-blue-200 rounded shadow hover:shadow-none"
-                    id="breakfast"
+                    data-time="breakfast"
                 >
                     Breakfast
                 </button>
                 <button
                     class="block p-2 m-2 bg-blue-100 hover:bg-blue-200 rounded shadow hover:shadow-none"
-                    id="lunch"
+                    data-time="lunch"
                 >
                     Lunch
                 </button>
                 <button
                     class="block p-2 m-2 bg-blue-100 hover:bg-blue-200 rounded shadow hover:shadow-none"
-                    id="dinner"
+                    data-time="dinner"
                 >
                     Dinner
                 </button>
                 <button
                     class="block p-2 m-2 bg-blue-100 hover:bg-blue-200 rounded shadow hover:shadow-none"
-                    id="evening"
+                    data-time="evening"
                 >
                     Evening
                 </button>
-                <button
-                    class="block p-2 m-2 bg-red-100 hover:bg-red-200 rounded shadow hover:shadow-none"
-                    hx-get="{default}"
-                    hx-target="#cal-chat-container"
-                >
-                    Cancel
-                </button>
+                {cancel_in_form}
                 <script>(() => {{{script}}})();</script>
             </form>
+            {cancel_after_form}
             "##
         )
     }
@@ -0,0 +1,157 @@
+//! Per-user OpenAI token budgets. Usage is recorded in `openai_usage` with
+//! every chat, and we check it before sending another message to the LLM.
+//! Days and months are in the user's timezone.
+
+use crate::{
+    chrono_utils::{start_of_month, start_of_today},
+    config::{DAILY_TOKEN_QUOTA, MONTHLY_TOKEN_QUOTA},
+    prelude::*,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum QuotaStatus {
+    Available,
+    DailyExceeded,
+    MonthlyExceeded,
+}
+
+struct TokenUsage {
+    daily_tokens: i64,
+    monthly_tokens: i64,
+    daily_limit: Option<i32>,
+    monthly_limit: Option<i32>,
+}
+
+impl TokenUsage {
+    fn status(&self) -> QuotaStatus {
+        let daily_limit = self.daily_limit.unwrap_or(DAILY_TOKEN_QUOTA);
+        let monthly_limit = self.monthly_limit.unwrap_or(MONTHLY_TOKEN_QUOTA);
+        if self.monthly_tokens >= monthly_limit.into() {
+            QuotaStatus::MonthlyExceeded
+        } else if self.daily_tokens >= daily_limit.into() {
+            QuotaStatus::DailyExceeded
+        } else {
+            QuotaStatus::Available
+        }
+    }
+}
+
+pub async fn check_quota(
+    db: impl PgExecutor<'_>,
+    user_id: i32,
+    preferences: &UserPreference,
+) -> Aresult<QuotaStatus> {
+    let usage = query_as!(
+        TokenUsage,
+        r#"select
+            coalesce(
+                sum(u.total_tokens) filter (where u.created_at >= $2),
+                0
+            ) "daily_tokens!",
+            coalesce(sum(u.total_tokens), 0) "monthly_tokens!",
+            (
+                select daily_token_limit from openai_usage_quota
+                where user_id = $1
+            ) daily_limit,
+            (
+                select monthly_token_limit from openai_usage_quota
+                where user_id = $1
+            ) monthly_limit
+        from openai_usage u
+        join openai_usage_user uu on uu.usage_id = u.id
+        where
+            uu.user_id = $1
+            and u.created_at >= $3"#,
+        user_id,
+        start_of_today(preferences.timezone),
+        start_of_month(preferences.timezone)
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(usage.status())
+}
+
+pub struct OverQuota {
+    pub status: QuotaStatus,
+}
+impl Component for OverQuota {
+    fn render(&self) -> String {
+        let retry_route = Route::ChatForm;
+        let period = match self.status {
+            QuotaStatus::MonthlyExceeded => "this month",
+            _ => "today",
+        };
+        let resets = match self.status {
+            QuotaStatus::MonthlyExceeded => "at the start of next month",
+            _ => "tomorrow",
+        };
+        format!(
+            r##"
+            <div class="prose max-w-[400px] dark:text-slate-200">
+                <p>
+                    You've reached your limit for AI estimates {period}. Your
+                    limit resets {resets}.
+                </p>
+                <p>
+                    In the meantime, you can still count food that you've
+                    eaten before; just describe it like you did last time, or
+                    use "Duplicate" on a previous entry.
+                </p>
+                <button
+                    hx-get="{retry_route}"
+                    hx-target="#cal-chat-container"
+                    class="
+                        bg-red-100
+                        dark:bg-red-800
+                        dark:hover:bg-red-700
+                        p-1
+                        rounded
+                        shadow
+                        hover:bg-red-200
+                ">
+                    Back
+                </button>
+            </div>
+            "##
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn usage(daily_tokens: i64, monthly_tokens: i64) -> TokenUsage {
+        TokenUsage {
+            daily_tokens,
+            monthly_tokens,
+            daily_limit: None,
+            monthly_limit: None,
+        }
+    }
+
+    #[test]
+    fn test_default_quota() {
+        assert_eq!(usage(0, 0).status(), QuotaStatus::Available);
+        assert_eq!(
+            usage(DAILY_TOKEN_QUOTA.into(), DAILY_TOKEN_QUOTA.into()).status(),
+            QuotaStatus::DailyExceeded
+        );
+        assert_eq!(
+            usage(0, MONTHLY_TOKEN_QUOTA.into()).status(),
+            QuotaStatus::MonthlyExceeded
+        );
+    }
+
+    #[test]
+    fn test_quota_override() {
+        let mut usage = usage(100, 100);
+        usage.daily_limit = Some(100);
+        assert_eq!(usage.status(), QuotaStatus::DailyExceeded);
+        usage.daily_limit = Some(DAILY_TOKEN_QUOTA * 10);
+        usage.daily_tokens = DAILY_TOKEN_QUOTA.into();
+        assert_eq!(usage.status(), QuotaStatus::Available);
+        usage.monthly_limit = Some(50);
+        assert_eq!(usage.status(), QuotaStatus::MonthlyExceeded);
+    }
+}
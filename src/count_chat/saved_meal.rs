@@ -0,0 +1,838 @@
+//! Saved meals are named bundles of food which can be logged to the current
+//! day in one click. Meals point back at existing rows in `food`, so logging
+//! a meal inserts one `food_eaten_event` per food item instead of cloning
+//! anything, which is how natural deduping emerges over time.
+
+use crate::{
+    client_events, components::Saved, config::FOOD_PAGE_SIZE, htmx, prelude::*,
+};
+
+/// Meal names are shown on cards, so they're kept short.
+const MAX_NAME_LEN: usize = 100;
+
+pub struct MealFood {
+    pub id: i32,
+    pub name: String,
+    pub calories: i32,
+    pub protein_grams: i32,
+    pub carbohydrates_grams: i32,
+    pub fat_grams: i32,
+}
+
+pub struct SavedMeal {
+    pub id: i32,
+    pub name: String,
+    pub food: Vec<MealFood>,
+}
+impl SavedMeal {
+    fn total_calories(&self) -> i32 {
+        self.food.iter().map(|f| f.calories).sum()
+    }
+}
+
+/// Trims the meal name from the form, and rejects names which are empty or
+/// too long.
+fn validate_name(name: &str) -> Result<&str, ServerError> {
+    let name = name.trim();
+    if name.is_empty() {
+        Err(ServerError::bad_request(
+            "saved meal name is empty",
+            Some("Meal name is required".into()),
+        ))
+    } else if name.chars().count() > MAX_NAME_LEN {
+        Err(ServerError::bad_request(
+            "saved meal name is too long",
+            Some(format!(
+                "Meal name must be {MAX_NAME_LEN} characters or less"
+            )),
+        ))
+    } else {
+        Ok(name)
+    }
+}
+
+enum MealFoodAction {
+    /// Remove this food from the meal with the given ID.
+    Remove(i32),
+    /// Add this food to the meal with the given ID.
+    Add(i32),
+}
+
+struct MealFoodRow<'a> {
+    food: &'a MealFood,
+    hide_calories: bool,
+    action: MealFoodAction,
+}
+impl Component for MealFoodRow<'_> {
+    fn render(&self) -> String {
+        let food_id = self.food.id;
+        let name = clean(&self.food.name);
+        let protein = self.food.protein_grams;
+        let carbs = self.food.carbohydrates_grams;
+        let fat = self.food.fat_grams;
+        let calories = if self.hide_calories {
+            "".to_string()
+        } else {
+            format!("{} kcal, ", self.food.calories)
+        };
+        let action = match self.action {
+            MealFoodAction::Remove(meal_id) => {
+                let href = Route::SavedMealFood(Some(meal_id));
+                format!(
+                    r##"
+                    <button
+                        hx-delete="{href}?food_id={food_id}"
+                        hx-target="#meal-editor"
+                        class="bg-red-100 hover:bg-red-200 rounded p-1
+                        text-sm dark:text-black"
+                    >
+                        Remove
+                    </button>
+                    "##
+                )
+            }
+            MealFoodAction::Add(meal_id) => {
+                let href = Route::SavedMealFood(Some(meal_id));
+                format!(
+                    r##"
+                    <form hx-post="{href}" hx-target="#meal-editor">
+                        <input type="hidden" name="food_id" value="{food_id}" />
+                        <button
+                            class="bg-emerald-100 hover:bg-emerald-200 rounded
+                            p-1 text-sm dark:text-black"
+                        >
+                            Add
+                        </button>
+                    </form>
+                    "##
+                )
+            }
+        };
+        format!(
+            r#"
+            <div class="flex items-center justify-between gap-2 py-1">
+                <div>
+                    <p class="font-semibold">{name}</p>
+                    <p class="text-xs">
+                        {calories}{protein}g protein, {carbs}g carbs, {fat}g fat
+                    </p>
+                </div>
+                {action}
+            </div>
+            "#
+        )
+    }
+}
+
+struct SavedMealCard<'a> {
+    meal: &'a SavedMeal,
+    hide_calories: bool,
+}
+impl Component for SavedMealCard<'_> {
+    fn render(&self) -> String {
+        let name = clean(&self.meal.name);
+        let edit = Route::SavedMeal(Some(self.meal.id));
+        let add_to_today = Route::AddMealToToday(Some(self.meal.id));
+        let calories = if self.hide_calories {
+            "".to_string()
+        } else {
+            format!(
+                r#"<p class="text-sm">{} calories total</p>"#,
+                self.meal.total_calories()
+            )
+        };
+        let food = if self.meal.food.is_empty() {
+            r#"<p class="text-sm italic">This meal is empty.</p>"#.to_string()
+        } else {
+            let names = self
+                .meal
+                .food
+                .iter()
+                .map(|f| clean(&f.name))
+                .collect::<Vec<String>>()
+                .join(", ");
+            format!(r#"<p class="text-sm">{names}</p>"#)
+        };
+        let add_to_today_button = if self.meal.food.is_empty() {
+            "".to_string()
+        } else {
+            format!(
+                r##"
+                <button
+                    hx-post="{add_to_today}"
+                    hx-target="#saved-meal-toast"
+                    hx-swap="innerHTML"
+                    class="bg-emerald-100 hover:bg-emerald-200 rounded p-1
+                    dark:text-black"
+                >
+                    Add to Today
+                </button>
+                "##
+            )
+        };
+        format!(
+            r#"
+            <div
+                class="rounded p-2 shadow sm:w-[20rem] bg-gradient-to-br
+                from-blue-100 via-sky-100 to-indigo-200 dark:text-black"
+                data-name="saved-meal-card"
+            >
+                <h1 class="text-2xl bold serif">{name}</h1>
+                {calories}
+                {food}
+                <div class="flex justify-end gap-2 mt-2">
+                    {add_to_today_button}
+                    <a href="{edit}">
+                        <button
+                            class="bg-indigo-100 hover:bg-indigo-200 rounded p-1"
+                        >
+                            Edit
+                        </button>
+                    </a>
+                    <button
+                        hx-delete="{edit}"
+                        hx-target="closest div[data-name='saved-meal-card']"
+                        hx-confirm="Delete this saved meal?"
+                        class="bg-red-100 hover:bg-red-200 rounded p-1"
+                    >
+                        Delete
+                    </button>
+                </div>
+            </div>
+            "#
+        )
+    }
+}
+
+struct SavedMealList<'a> {
+    meals: &'a [SavedMeal],
+    hide_calories: bool,
+}
+impl Component for SavedMealList<'_> {
+    fn render(&self) -> String {
+        let create = Route::SavedMeals;
+        let home = Route::UserHome;
+        let meals = if self.meals.is_empty() {
+            r#"<p class="italic">No saved meals yet!</p>"#.to_string()
+        } else {
+            self.meals.iter().fold(String::new(), |mut acc, meal| {
+                acc.push_str(
+                    &SavedMealCard {
+                        meal,
+                        hide_calories: self.hide_calories,
+                    }
+                    .render(),
+                );
+                acc
+            })
+        };
+        format!(
+            r#"
+            <div class="flex flex-col gap-2">
+                <a href="{home}">
+                    <button
+                        class="dark:bg-emerald-700 dark:hover:bg-emerald-800
+                        bg-emerald-100 hover:bg-emerald-200 p-1 m-1 rounded"
+                    >
+                        Home
+                    </button>
+                </a>
+                <h1 class="text-2xl font-extrabold">Saved Meals</h1>
+                <p class="text-sm max-w-prose">
+                    Group food you've already counted into a meal, then add
+                    the whole meal to today with one click.
+                </p>
+                <form
+                    hx-post="{create}"
+                    class="flex flex-col gap-2 max-w-md p-2 rounded
+                    bg-blue-200 dark:bg-blue-950"
+                >
+                    <label for="name">New meal name</label>
+                    <input
+                        required
+                        type="text"
+                        id="name"
+                        name="name"
+                        maxlength="{MAX_NAME_LEN}"
+                        placeholder="Weekday breakfast"
+                    />
+                    <button
+                        class="self-start bg-emerald-100 hover:bg-emerald-200
+                        dark:bg-emerald-700 dark:hover:bg-emerald-600 rounded
+                        p-1"
+                    >
+                        Create Meal
+                    </button>
+                </form>
+                <div id="saved-meal-toast"></div>
+                <div class="flex flex-wrap gap-2">
+                    {meals}
+                </div>
+            </div>
+            "#
+        )
+    }
+}
+
+struct MealEditor {
+    meal: SavedMeal,
+    candidates: Vec<MealFood>,
+    hide_calories: bool,
+}
+impl Component for MealEditor {
+    fn render(&self) -> String {
+        let name = clean(&self.meal.name);
+        let meals = Route::SavedMeals;
+        let rename = Route::SavedMeal(Some(self.meal.id));
+        let contents = if self.meal.food.is_empty() {
+            r#"<p class="italic">Add some food below.</p>"#.to_string()
+        } else {
+            self.meal.food.iter().fold(String::new(), |mut acc, food| {
+                acc.push_str(
+                    &MealFoodRow {
+                        food,
+                        hide_calories: self.hide_calories,
+                        action: MealFoodAction::Remove(self.meal.id),
+                    }
+                    .render(),
+                );
+                acc
+            })
+        };
+        let candidates = if self.candidates.is_empty() {
+            r#"
+            <p class="italic">
+                Once you've counted some food, it will show up here.
+            </p>
+            "#
+            .to_string()
+        } else {
+            self.candidates.iter().fold(String::new(), |mut acc, food| {
+                acc.push_str(
+                    &MealFoodRow {
+                        food,
+                        hide_calories: self.hide_calories,
+                        action: MealFoodAction::Add(self.meal.id),
+                    }
+                    .render(),
+                );
+                acc
+            })
+        };
+        let total = if self.hide_calories {
+            "".to_string()
+        } else {
+            format!(
+                r#"<p class="text-sm">{} calories total</p>"#,
+                self.meal.total_calories()
+            )
+        };
+        format!(
+            r##"
+            <div id="meal-editor" class="flex flex-col gap-2 max-w-prose">
+                <a href="{meals}">
+                    <button
+                        class="dark:bg-emerald-700 dark:hover:bg-emerald-800
+                        bg-emerald-100 hover:bg-emerald-200 p-1 m-1 rounded"
+                    >
+                        Back to Saved Meals
+                    </button>
+                </a>
+                <h1 class="text-2xl font-extrabold">{name}</h1>
+                <form
+                    hx-post="{rename}"
+                    hx-target="#meal-editor"
+                    class="flex items-end gap-2"
+                >
+                    <div class="flex flex-col">
+                        <label for="name" class="text-sm">Meal name</label>
+                        <input
+                            required
+                            type="text"
+                            id="name"
+                            name="name"
+                            maxlength="{MAX_NAME_LEN}"
+                            value="{name}"
+                        />
+                    </div>
+                    <button
+                        class="bg-indigo-100 hover:bg-indigo-200 rounded p-1
+                        dark:text-black"
+                    >
+                        Rename
+                    </button>
+                </form>
+                {total}
+                <div class="rounded p-2 bg-blue-200 dark:bg-blue-950">
+                    <h2 class="text-lg font-semibold">In this meal</h2>
+                    {contents}
+                </div>
+                <div class="rounded p-2 bg-emerald-200 dark:bg-indigo-900">
+                    <h2 class="text-lg font-semibold">Recently counted food</h2>
+                    {candidates}
+                </div>
+            </div>
+            "##
+        )
+    }
+}
+
+pub async fn list_saved_meals(
+    db: impl PgExecutor<'_> + Copy,
+    user_id: i32,
+) -> Aresult<Vec<SavedMeal>> {
+    struct MealRow {
+        id: i32,
+        name: String,
+    }
+    struct FoodRow {
+        meal_id: i32,
+        id: i32,
+        name: String,
+        calories: i32,
+        protein_grams: i32,
+        carbohydrates_grams: i32,
+        fat_grams: i32,
+    }
+    let meals = query_as!(
+        MealRow,
+        "select id, name from meal
+        where user_id = $1
+        order by created_at desc",
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+    let food = query_as!(
+        FoodRow,
+        "select
+            mf.meal_id,
+            f.id,
+            f.name,
+            f.calories,
+            f.protein protein_grams,
+            f.carbohydrates carbohydrates_grams,
+            f.fat fat_grams
+        from meal_food mf
+        join meal m on m.id = mf.meal_id
+        join food f on f.id = mf.food_id
+        where m.user_id = $1 and f.user_id = $1
+        order by f.name",
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(meals
+        .into_iter()
+        .map(|meal| SavedMeal {
+            food: food
+                .iter()
+                .filter(|f| f.meal_id == meal.id)
+                .map(|f| MealFood {
+                    id: f.id,
+                    name: f.name.clone(),
+                    calories: f.calories,
+                    protein_grams: f.protein_grams,
+                    carbohydrates_grams: f.carbohydrates_grams,
+                    fat_grams: f.fat_grams,
+                })
+                .collect(),
+            id: meal.id,
+            name: meal.name,
+        })
+        .collect())
+}
+
+async fn get_saved_meal(
+    db: impl PgExecutor<'_> + Copy,
+    user_id: i32,
+    meal_id: i32,
+) -> Aresult<Option<SavedMeal>> {
+    struct MealRow {
+        id: i32,
+        name: String,
+    }
+    let meal = query_as!(
+        MealRow,
+        "select id, name from meal where id = $1 and user_id = $2",
+        meal_id,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+    let Some(meal) = meal else {
+        return Ok(None);
+    };
+    let food = query_as!(
+        MealFood,
+        "select
+            f.id,
+            f.name,
+            f.calories,
+            f.protein protein_grams,
+            f.carbohydrates carbohydrates_grams,
+            f.fat fat_grams
+        from meal_food mf
+        join food f on f.id = mf.food_id
+        where mf.meal_id = $1 and f.user_id = $2
+        order by f.name",
+        meal_id,
+        user_id
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(Some(SavedMeal {
+        id: meal.id,
+        name: meal.name,
+        food,
+    }))
+}
+
+/// Food which the user has counted before, but which is not yet part of the
+/// meal. Most recently eaten food comes first.
+async fn list_meal_candidates(
+    db: impl PgExecutor<'_>,
+    user_id: i32,
+    meal_id: i32,
+) -> Aresult<Vec<MealFood>> {
+    let limit: i64 = FOOD_PAGE_SIZE.into();
+    Ok(query_as!(
+        MealFood,
+        "select
+            f.id,
+            f.name,
+            f.calories,
+            f.protein protein_grams,
+            f.carbohydrates carbohydrates_grams,
+            f.fat fat_grams
+        from food f
+        where
+            f.user_id = $1
+            and not exists (
+                select 1 from meal_food mf
+                where mf.meal_id = $2 and mf.food_id = f.id
+            )
+        order by (
+            select max(fee.eaten_at) from food_eaten_event fee
+            where fee.food_id = f.id
+        ) desc nulls last
+        limit $3",
+        user_id,
+        meal_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+async fn get_meal_editor(
+    db: &PgPool,
+    session: &Session,
+    meal_id: i32,
+) -> Result<MealEditor, ServerError> {
+    let preferences = session.get_preferences(db).await?;
+    let meal = get_saved_meal(db, session.user_id, meal_id)
+        .await?
+        .ok_or_else(|| {
+            ServerError::bad_request("saved meal does not exist", None)
+        })?;
+    let candidates = list_meal_candidates(db, session.user_id, meal_id).await?;
+    Ok(MealEditor {
+        meal,
+        candidates,
+        hide_calories: preferences.hide_calories,
+    })
+}
+
+pub async fn saved_meal_list(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "saved meal list")?;
+    let preferences = session.get_preferences(&db).await?;
+    let meals = list_saved_meals(&db, session.user_id).await?;
+    Ok(Page {
+        title: "Saved Meals",
+        children: &PageContainer {
+            children: &SavedMealList {
+                meals: &meals,
+                hide_calories: preferences.hide_calories,
+            },
+        },
+    }
+    .render())
+}
+
+#[derive(Deserialize)]
+pub struct NewSavedMeal {
+    name: String,
+}
+
+pub async fn create_saved_meal(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(NewSavedMeal { name }): Form<NewSavedMeal>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "create saved meal")?;
+    let name = validate_name(&name)?;
+    let Id { id } = query_as!(
+        Id,
+        "insert into meal (user_id, name) values ($1, $2) returning id",
+        session.user_id,
+        name
+    )
+    .fetch_one(&db)
+    .await?;
+    let headers = htmx::redirect(
+        HeaderMap::new(),
+        &Route::SavedMeal(Some(id)).as_string(),
+    );
+    Ok((headers, ""))
+}
+
+pub async fn saved_meal_editor(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "saved meal editor")?;
+    let editor = get_meal_editor(&db, &session, id).await?;
+    Ok(Page {
+        title: "Edit Saved Meal",
+        children: &PageContainer { children: &editor },
+    }
+    .render())
+}
+
+pub async fn rename_saved_meal(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+    Form(NewSavedMeal { name }): Form<NewSavedMeal>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "rename saved meal")?;
+    let name = validate_name(&name)?;
+    query!(
+        "update meal set name = $1 where id = $2 and user_id = $3",
+        name,
+        id,
+        session.user_id
+    )
+    .execute(&db)
+    .await?;
+    Ok(get_meal_editor(&db, &session, id).await?.render())
+}
+
+pub async fn delete_saved_meal(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "delete saved meal")?;
+    query!(
+        "delete from meal where id = $1 and user_id = $2",
+        id,
+        session.user_id
+    )
+    .execute(&db)
+    .await?;
+    Ok("")
+}
+
+#[derive(Deserialize)]
+pub struct MealFoodPayload {
+    food_id: i32,
+}
+
+pub async fn add_food_to_saved_meal(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+    Form(MealFoodPayload { food_id }): Form<MealFoodPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session =
+        Session::from_headers_err(&headers, "add food to saved meal")?;
+    query!(
+        "insert into meal_food (meal_id, food_id)
+        select m.id, f.id
+        from meal m
+        join food f on f.user_id = m.user_id
+        where
+            m.id = $1
+            and f.id = $2
+            and m.user_id = $3
+        on conflict do nothing",
+        id,
+        food_id,
+        session.user_id
+    )
+    .execute(&db)
+    .await?;
+    Ok(get_meal_editor(&db, &session, id).await?.render())
+}
+
+pub async fn remove_food_from_saved_meal(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+    Query(MealFoodPayload { food_id }): Query<MealFoodPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session =
+        Session::from_headers_err(&headers, "remove food from saved meal")?;
+    query!(
+        "delete from meal_food mf
+        using meal m
+        where
+            mf.meal_id = m.id
+            and m.id = $1
+            and mf.food_id = $2
+            and m.user_id = $3",
+        id,
+        food_id,
+        session.user_id
+    )
+    .execute(&db)
+    .await?;
+    Ok(get_meal_editor(&db, &session, id).await?.render())
+}
+
+pub async fn add_saved_meal_to_today(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session =
+        Session::from_headers_err(&headers, "add saved meal to today")?;
+    let result = query!(
+        "insert into food_eaten_event (user_id, food_id)
+        select m.user_id, mf.food_id
+        from meal_food mf
+        join meal m on m.id = mf.meal_id
+        where m.id = $1 and m.user_id = $2",
+        id,
+        session.user_id
+    )
+    .execute(&db)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(ServerError::bad_request(
+            "attempt to add empty or non-existent saved meal to today",
+            None,
+        ));
+    }
+    let headers = client_events::reload_food(HeaderMap::new());
+    let headers = client_events::reload_macros(headers);
+    Ok((
+        headers,
+        Saved {
+            message: "Meal added to today",
+        }
+        .render(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn food(id: i32, name: &str, calories: i32) -> MealFood {
+        MealFood {
+            id,
+            name: name.into(),
+            calories,
+            protein_grams: 10,
+            carbohydrates_grams: 20,
+            fat_grams: 5,
+        }
+    }
+
+    fn meal() -> SavedMeal {
+        SavedMeal {
+            id: 7,
+            name: "Eggs & <toast>".into(),
+            food: vec![food(1, "Eggs", 150), food(2, "Toast", 80)],
+        }
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert_eq!(validate_name("  Breakfast \n").unwrap(), "Breakfast");
+        assert!(validate_name("").is_err());
+        assert!(validate_name("   ").is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN)).is_ok());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+        // Length is counted in characters, not bytes.
+        assert!(validate_name(&"é".repeat(MAX_NAME_LEN)).is_ok());
+    }
+
+    #[test]
+    fn test_total_calories() {
+        assert_eq!(meal().total_calories(), 230);
+        let empty = SavedMeal {
+            id: 1,
+            name: "Empty".into(),
+            food: vec![],
+        };
+        assert_eq!(empty.total_calories(), 0);
+    }
+
+    #[test]
+    fn test_card_escapes_name() {
+        let meal = meal();
+        let card = SavedMealCard {
+            meal: &meal,
+            hide_calories: false,
+        }
+        .render();
+        assert!(!card.contains("<toast>"));
+        assert!(card.contains("230 calories total"));
+        assert!(card.contains("Eggs, Toast"));
+        assert!(card.contains("Add to Today"));
+    }
+
+    #[test]
+    fn test_card_hides_calories() {
+        let meal = meal();
+        let card = SavedMealCard {
+            meal: &meal,
+            hide_calories: true,
+        }
+        .render();
+        assert!(!card.contains("calories total"));
+    }
+
+    #[test]
+    fn test_empty_card_cannot_be_added_to_today() {
+        let meal = SavedMeal {
+            id: 1,
+            name: "Empty".into(),
+            food: vec![],
+        };
+        let card = SavedMealCard {
+            meal: &meal,
+            hide_calories: false,
+        }
+        .render();
+        assert!(card.contains("This meal is empty."));
+        assert!(!card.contains("Add to Today"));
+    }
+
+    #[test]
+    fn test_editor_renders_rename_form_and_actions() {
+        let editor = MealEditor {
+            meal: meal(),
+            candidates: vec![food(3, "Banana", 100)],
+            hide_calories: false,
+        }
+        .render();
+        let meal_route = Route::SavedMeal(Some(7)).as_string();
+        let food_route = Route::SavedMealFood(Some(7)).as_string();
+        assert!(editor.contains(&format!(r#"hx-post="{meal_route}""#)));
+        assert!(!editor.contains("<toast>"));
+        assert!(
+            editor.contains(&format!(r#"hx-delete="{food_route}?food_id=1""#))
+        );
+        assert!(editor.contains(r#"name="food_id" value="3""#));
+    }
+}
@@ -47,6 +47,7 @@ struct Qres {
     id: i32,
     username: String,
     email: String,
+    email_verified: bool,
     stripe_customer_id: String,
     subscription_type_id: i32,
     created_at: DateTime<Utc>,
@@ -59,6 +60,7 @@ fn map_into_user(row: Qres) -> models::User {
         id: row.id,
         username: row.username,
         email: row.email,
+        email_verified: row.email_verified,
         stripe_customer_id: row.stripe_customer_id,
         created_at: row.created_at,
     }
@@ -74,6 +76,7 @@ impl<'a> GetModel<GetUserQuery<'a>> for models::User {
                         id,
                         username,
                         email,
+                        email_verified,
                         stripe_customer_id,
                         subscription_type_id,
                         created_at
@@ -92,6 +95,7 @@ impl<'a> GetModel<GetUserQuery<'a>> for models::User {
                         id,
                         username,
                         email,
+                        email_verified,
                         stripe_customer_id,
                         subscription_type_id,
                         created_at
@@ -0,0 +1,339 @@
+//! Users can delete their account from the preferences page. Everything
+//! which belongs to them is deleted along with the `users` row; see
+//! `migrations/45_account_deletion.sql`. Their Stripe subscription is
+//! cancelled first, so that nobody is billed for an account which doesn't
+//! exist anymore.
+//!
+//! Deletion asks for the same credentials as logging in: the password, and
+//! a two-factor code if the user has turned that on.
+
+use crate::{
+    auth::{check_password, is_anon, rate_limit, session_store, totp},
+    email::{Email, EmailTemplate},
+    htmx,
+    models::User,
+    prelude::*,
+    smtp::send_email,
+    stripe,
+};
+use axum::http::HeaderValue;
+
+/// Users need to type this to confirm that they want to delete their
+/// account.
+const CONFIRMATION_PHRASE: &str = "delete my account";
+
+const CONTAINER_STYLE: &str = "p-4 mt-4 bg-red-100 dark:bg-red-950
+    dark:text-slate-200 text-black rounded w-prose flex flex-col gap-2";
+
+const BUTTON_STYLE: &str = "self-start bg-red-200 hover:bg-red-300
+    dark:bg-red-800 dark:hover:bg-red-700 rounded p-1";
+
+pub struct DeleteAccount;
+impl Component for DeleteAccount {
+    fn render(&self) -> String {
+        let delete = Route::DeleteAccount;
+        format!(
+            r##"
+            <div id="delete-account" class="{CONTAINER_STYLE}">
+                <h2 class="text-lg">Delete Account</h2>
+                <p class="text-xs">
+                    Permanently delete your account and all of your data,
+                    including your food, weigh-ins, balancing checkpoints, and
+                    comments. If you have a subscription, it will be
+                    cancelled.
+                </p>
+                <button
+                    hx-get="{delete}"
+                    hx-target="#delete-account"
+                    class="{BUTTON_STYLE}"
+                >
+                    Delete my account
+                </button>
+            </div>
+            "##
+        )
+    }
+}
+
+struct ConfirmDeleteAccount<'a> {
+    /// Anonymous users don't know their password.
+    needs_password: bool,
+    needs_code: bool,
+    error: Option<&'a str>,
+}
+impl Component for ConfirmDeleteAccount<'_> {
+    fn render(&self) -> String {
+        let delete = Route::DeleteAccount;
+        let preferences = Route::UserPreference;
+        let password = if self.needs_password {
+            r#"
+            <label for="password">Password</label>
+            <input
+                type="password"
+                id="password"
+                name="password"
+                autocomplete="current-password"
+                required
+            />
+            "#
+        } else {
+            ""
+        };
+        let code = if self.needs_code {
+            r#"
+            <label for="code">
+                Code from your authenticator app, or a recovery code
+            </label>
+            <input
+                type="text"
+                id="code"
+                name="code"
+                autocomplete="one-time-code"
+                required
+            />
+            "#
+        } else {
+            ""
+        };
+        let error = self.error.map_or("".into(), |e| {
+            format!(r#"<p class="text-sm italic">{e}</p>"#)
+        });
+        format!(
+            r##"
+            <div id="delete-account" class="{CONTAINER_STYLE}">
+                <h2 class="text-lg">Are you sure?</h2>
+                <p class="text-xs">
+                    Your account and all of your data will be deleted right
+                    away, and can't be recovered. You may want to download
+                    your data first.
+                </p>
+                <form
+                    hx-post="{delete}"
+                    hx-target="#delete-account"
+                    class="flex flex-col gap-2"
+                >
+                    <label for="confirmation">
+                        Type
+                        <span class="font-mono">{CONFIRMATION_PHRASE}</span>
+                        to confirm
+                    </label>
+                    <input
+                        type="text"
+                        id="confirmation"
+                        name="confirmation"
+                        autocomplete="off"
+                        required
+                    />
+                    {password}
+                    {code}
+                    {error}
+                    <button class="{BUTTON_STYLE}">
+                        Permanently delete my account
+                    </button>
+                </form>
+                <a class="link text-sm" href="{preferences}">Cancel</a>
+            </div>
+            "##
+        )
+    }
+}
+
+struct AccountDeletedEmail;
+impl Component for AccountDeletedEmail {
+    fn render(&self) -> String {
+        r#"
+        <p>
+            Your beancount.bot account has been deleted, along with all of
+            your data. If you had a subscription, it was cancelled, and you
+            won't be billed again.
+        </p>
+        <p>
+            If you didn't delete your account, please contact
+            jdevries3133@gmail.com right away.
+        </p>
+        "#
+        .into()
+    }
+}
+impl EmailTemplate for AccountDeletedEmail {
+    fn subject(&self) -> String {
+        "Your beancount.bot account has been deleted".into()
+    }
+    fn plaintext(&self) -> String {
+        "Your beancount.bot account has been deleted, along with all of your data. If you had a subscription, it was cancelled, and you won't be billed again.\n\nIf you didn't delete your account, please contact jdevries3133@gmail.com right away.".into()
+    }
+}
+
+/// Matches the confirmation phrase, ignoring case and surrounding
+/// whitespace.
+fn is_confirmed(confirmation: &str) -> bool {
+    confirmation.trim().to_lowercase() == CONFIRMATION_PHRASE
+}
+
+/// Nearly everything is removed by cascading deletes, except for OpenAI
+/// usage, which is only linked to users through `openai_usage_user`, and
+/// failed logins, which are only linked to users by their username or email
+/// in the `rate_limit` key.
+async fn delete_user(db: &PgPool, user: &User) -> Aresult<()> {
+    let mut tx = db.begin().await?;
+    query!(
+        "delete from openai_usage where id in (
+            select usage_id from openai_usage_user where user_id = $1
+        )",
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!(
+        "delete from rate_limit where key = any($1)",
+        &[
+            rate_limit::identifier_key(&user.username),
+            rate_limit::identifier_key(&user.email)
+        ]
+    )
+    .execute(&mut *tx)
+    .await?;
+    query!("delete from users where id = $1", user.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_delete_account_form(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "delete account form")?;
+    Ok(ConfirmDeleteAccount {
+        needs_password: !is_anon(&session.username),
+        needs_code: totp::is_enabled(&db, session.user_id).await?,
+        error: None,
+    }
+    .render())
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountPayload {
+    confirmation: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    code: String,
+}
+
+pub async fn handle_delete_account(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<DeleteAccountPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "delete account")?;
+    let user = session.get_user(&db).await?;
+    let needs_password = !is_anon(&user.username);
+    let needs_code = totp::is_enabled(&db, user.id).await?;
+    // The code is checked last, because checking it uses it up.
+    let error = if !is_confirmed(&form.confirmation) {
+        Some("Please type the confirmation exactly as shown.")
+    } else if needs_password
+        && check_password(&db, &user.username, &form.password)
+            .await
+            .is_err()
+    {
+        Some("That password is incorrect.")
+    } else if needs_code && !totp::verify(&db, user.id, &form.code).await? {
+        Some("That code didn't work. Please try again.")
+    } else {
+        None
+    };
+    if error.is_some() {
+        return Ok((
+            HeaderMap::new(),
+            ConfirmDeleteAccount {
+                needs_password,
+                needs_code,
+                error,
+            }
+            .render(),
+        ));
+    }
+
+    stripe::cancel_subscriptions(&user.stripe_customer_id).await?;
+    session_store::revoke_all(&db, user.id).await?;
+    delete_user(&db, &user).await?;
+    println!("Deleted user {} ({})", user.id, user.username);
+    if !is_anon(&user.username) {
+        if let Err(e) =
+            send_email(&Email::new(&user.email, &AccountDeletedEmail)).await
+        {
+            eprintln!(
+                "Error: failed to send account deletion email to {} :: {e}",
+                user.email
+            );
+        }
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Set-Cookie",
+        HeaderValue::from_static("session=null; Path=/; HttpOnly"),
+    );
+    let headers = htmx::redirect(headers, &Route::AccountDeleted.as_string());
+    Ok((headers, "OK".into()))
+}
+
+struct AccountDeleted;
+impl Component for AccountDeleted {
+    fn render(&self) -> String {
+        let root = Route::Root;
+        format!(
+            r#"
+            <div class="flex flex-col gap-2 max-w-prose">
+                <h1 class="text-xl font-extrabold">Account Deleted</h1>
+                <p>
+                    Your account and all of your data have been deleted.
+                    Thanks for using Bean Count!
+                </p>
+                <a class="link" href="{root}">Back to the home page</a>
+            </div>
+            "#
+        )
+    }
+}
+
+pub async fn account_deleted() -> impl IntoResponse {
+    Page {
+        title: "Account Deleted",
+        children: &PageContainer {
+            children: &AccountDeleted {},
+        },
+    }
+    .render()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_confirmed() {
+        assert!(is_confirmed("delete my account"));
+        assert!(is_confirmed("  Delete My Account\n"));
+        assert!(!is_confirmed("delete account"));
+        assert!(!is_confirmed(""));
+    }
+
+    #[test]
+    fn test_confirmation_asks_for_code_with_two_factor() {
+        let form = |needs_code| {
+            ConfirmDeleteAccount {
+                needs_password: true,
+                needs_code,
+                error: None,
+            }
+            .render()
+        };
+        assert!(form(true).contains(r#"name="code""#));
+        assert!(!form(false).contains(r#"name="code""#));
+        assert!(form(false).contains(r#"name="password""#));
+    }
+}
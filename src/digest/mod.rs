@@ -0,0 +1,143 @@
+//! Opt-in email digests and reminders. A background task, started in
+//! [crate::main], checks for emails which are due every
+//! [config::DIGEST_POLL_INTERVAL].
+
+mod reminder;
+mod report;
+mod unsubscribe;
+
+use crate::{
+    auth::is_anon, config, email::Email, preferences::get_user_preference,
+    prelude::*, smtp::send_email,
+};
+use report::{build_digest, due_digests, Digest, DigestKind};
+use tokio::{task::JoinHandle, time};
+pub use unsubscribe::{get_unsubscribe_page, handle_unsubscribe};
+
+pub fn spawn_scheduler(db: PgPool) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = time::interval(config::DIGEST_POLL_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = send_due_digests(&db).await {
+                eprintln!("Error: failed to send email digests :: {e}");
+            }
+            if let Err(e) = reminder::send_due_reminders(&db).await {
+                eprintln!("Error: failed to send reminders :: {e}");
+            }
+        }
+    })
+}
+
+struct Recipient {
+    user_id: i32,
+    username: String,
+    email: String,
+}
+
+async fn send_due_digests(db: &PgPool) -> Aresult<()> {
+    let recipients = query_as!(
+        Recipient,
+        "select u.id user_id, u.username, u.email
+        from users u
+        join user_preference p on p.user_id = u.id
+        where
+            (p.daily_digest_enabled or p.weekly_digest_enabled)
+            and u.email_verified"
+    )
+    .fetch_all(db)
+    .await?;
+    let now = utc_now();
+    for recipient in recipients {
+        if is_anon(&recipient.username) {
+            continue;
+        }
+        let Some(preferences) =
+            get_user_preference(db, recipient.user_id).await?
+        else {
+            continue;
+        };
+        for (kind, first_day) in
+            due_digests(now, preferences.timezone, &preferences.email)
+        {
+            if let Err(e) =
+                send_digest(db, &recipient, &preferences, kind, first_day).await
+            {
+                eprintln!(
+                    "Error: failed to send {} digest to user {} :: {e}",
+                    kind.as_str(),
+                    recipient.user_id
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The digest is claimed before it's sent, so that it goes out at most once.
+/// If sending fails, the claim is released so that we'll try again on the
+/// next tick.
+async fn send_digest(
+    db: &PgPool,
+    recipient: &Recipient,
+    preferences: &UserPreference,
+    kind: DigestKind,
+    first_day: NaiveDate,
+) -> Aresult<()> {
+    let claim = query!(
+        "insert into email_digest_sent (user_id, kind, period_start)
+        values ($1, $2, $3)
+        on conflict do nothing",
+        recipient.user_id,
+        kind.as_str(),
+        first_day
+    )
+    .execute(db)
+    .await?;
+    if claim.rows_affected() == 0 {
+        return Ok(());
+    }
+    let result = async {
+        let digest =
+            build_digest(db, recipient.user_id, preferences, kind, first_day)
+                .await?;
+        deliver(&recipient.email, &digest).await
+    }
+    .await;
+    if result.is_err() {
+        query!(
+            "delete from email_digest_sent
+            where user_id = $1 and kind = $2 and period_start = $3",
+            recipient.user_id,
+            kind.as_str(),
+            first_day
+        )
+        .execute(db)
+        .await?;
+    }
+    result
+}
+
+async fn deliver(to: &str, digest: &Digest) -> Aresult<()> {
+    send_email(&Email::new(to, digest)).await
+}
+
+#[cfg(all(test, not(feature = "enable_smtp_email")))]
+mod test {
+    use super::*;
+    use crate::smtp::outbox;
+
+    #[tokio::test]
+    async fn test_deliver_sends_through_stub() {
+        let to = "digest-test@example.com";
+        let digest = report::test::daily_digest(&UserPreference::default());
+        deliver(to, &digest).await.unwrap();
+        let sent = outbox::take(to);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].subject, "Your summary for Jun 28");
+        assert!(sent[0].text.contains("Top foods:"));
+        assert!(sent[0].html.contains("<li>Chicken (1000 calories"));
+        assert!(outbox::take(to).is_empty());
+    }
+}
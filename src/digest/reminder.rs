@@ -0,0 +1,214 @@
+//! "Remind me if I haven't logged anything by HH:MM." Reminders are sent at
+//! most once per day, and only if nothing has been logged yet that day.
+
+use super::unsubscribe::{self, EmailFooter};
+use crate::{
+    auth::is_anon,
+    chrono_utils::start_of_date,
+    config,
+    email::{Email, EmailButton, EmailTemplate},
+    prelude::*,
+    smtp::send_email,
+};
+
+/// The date that a reminder is due for, if the user's reminder time has
+/// already passed today in their timezone.
+fn reminder_due(
+    now: DateTime<Utc>,
+    timezone: Tz,
+    reminder_time: NaiveTime,
+) -> Option<NaiveDate> {
+    let local = now.with_timezone(&timezone);
+    (local.time() >= reminder_time).then_some(local.date_naive())
+}
+
+struct ReminderEmail {
+    user_id: i32,
+    reminder_time: NaiveTime,
+}
+impl ReminderEmail {
+    fn time(&self) -> String {
+        self.reminder_time
+            .format("%l:%M %p")
+            .to_string()
+            .trim()
+            .into()
+    }
+}
+impl Component for ReminderEmail {
+    fn render(&self) -> String {
+        let button = EmailButton {
+            href: &Route::UserHome,
+            text: "Log your meals",
+        }
+        .render();
+        let time = self.time();
+        let footer = EmailFooter {
+            user_id: self.user_id,
+        }
+        .render();
+        format!(
+            r#"
+            <p>You haven't logged anything today.</p>
+            {button}
+            <p>We send this reminder when nothing is logged by {time}.</p>
+            {footer}
+            "#
+        )
+    }
+}
+impl EmailTemplate for ReminderEmail {
+    fn subject(&self) -> String {
+        "Don't forget to log your food".into()
+    }
+    fn plaintext(&self) -> String {
+        let home = format!("{}{}", config::BASE_URL, Route::UserHome);
+        let time = self.time();
+        let footer = unsubscribe::footer(self.user_id);
+        format!(
+            "You haven't logged anything today. Log your meals at {home}\n\n\
+            We send this reminder when nothing is logged by {time}.\n\n{footer}"
+        )
+    }
+}
+
+struct Recipient {
+    user_id: i32,
+    username: String,
+    email: String,
+    timezone: String,
+    reminder_time: NaiveTime,
+}
+
+pub async fn send_due_reminders(db: &PgPool) -> Aresult<()> {
+    let recipients = query_as!(
+        Recipient,
+        r#"select
+            u.id user_id,
+            u.username,
+            u.email,
+            p.timezone,
+            p.reminder_time "reminder_time!"
+        from users u
+        join user_preference p on p.user_id = u.id
+        where p.reminder_time is not null and u.email_verified"#
+    )
+    .fetch_all(db)
+    .await?;
+    let now = utc_now();
+    for recipient in recipients {
+        if is_anon(&recipient.username) {
+            continue;
+        }
+        let Ok(timezone) = recipient.timezone.parse::<Tz>() else {
+            continue;
+        };
+        let Some(date) = reminder_due(now, timezone, recipient.reminder_time)
+        else {
+            continue;
+        };
+        if let Err(e) = send_reminder(db, &recipient, timezone, date).await {
+            eprintln!(
+                "Error: failed to send reminder to user {} :: {e}",
+                recipient.user_id
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn send_reminder(
+    db: &PgPool,
+    recipient: &Recipient,
+    timezone: Tz,
+    date: NaiveDate,
+) -> Aresult<()> {
+    let logged_today = query!(
+        r#"select exists(
+            select 1 from food_eaten_event
+            where user_id = $1 and eaten_at >= $2
+        ) "exists!""#,
+        recipient.user_id,
+        start_of_date(date, timezone)
+    )
+    .fetch_one(db)
+    .await?
+    .exists;
+    if logged_today {
+        return Ok(());
+    }
+    let claim = query!(
+        "insert into food_reminder_sent (user_id, reminder_date)
+        values ($1, $2)
+        on conflict do nothing",
+        recipient.user_id,
+        date
+    )
+    .execute(db)
+    .await?;
+    if claim.rows_affected() == 0 {
+        return Ok(());
+    }
+    let result = send_email(&Email::new(
+        &recipient.email,
+        &ReminderEmail {
+            user_id: recipient.user_id,
+            reminder_time: recipient.reminder_time,
+        },
+    ))
+    .await;
+    if result.is_err() {
+        query!(
+            "delete from food_reminder_sent
+            where user_id = $1 and reminder_date = $2",
+            recipient.user_id,
+            date
+        )
+        .execute(db)
+        .await?;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_reminder_due_in_user_timezone() {
+        // utc_now is 19:47 in New York, and 08:47 tomorrow in Tokyo.
+        let now = utc_now();
+        assert_eq!(
+            reminder_due(now, Tz::America__New_York, time("13:00")),
+            Some(NaiveDate::from_ymd_opt(2024, 6, 29).unwrap())
+        );
+        assert_eq!(
+            reminder_due(now, Tz::America__New_York, time("20:00")),
+            None
+        );
+        assert_eq!(
+            reminder_due(now, Tz::Asia__Tokyo, time("08:30")),
+            Some(NaiveDate::from_ymd_opt(2024, 6, 30).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_reminder_email() {
+        std::env::set_var("SESSION_SECRET", "foo");
+        let email = Email::new(
+            "reminder-test@example.com",
+            &ReminderEmail {
+                user_id: 7,
+                reminder_time: time("12:30"),
+            },
+        );
+        assert!(email.text.contains("nothing is logged by 12:30 PM."));
+        assert!(email.text.contains("/unsubscribe/7-"));
+        assert!(email.html.contains("nothing is logged by 12:30 PM."));
+        assert!(email.html.contains("/unsubscribe/7-"));
+    }
+}
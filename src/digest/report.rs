@@ -0,0 +1,611 @@
+//! The contents of the daily summary and weekly report emails.
+
+use super::unsubscribe::{self, EmailFooter};
+use crate::{
+    balancing,
+    chrono_utils::start_of_date,
+    config,
+    email::{EmailButton, EmailTemplate},
+    preferences::{EmailPreferences, MacroGrams},
+    prelude::*,
+};
+use chrono::Days;
+use std::collections::BTreeMap;
+
+/// How many of the top foods are listed in each digest.
+const TOP_FOODS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DigestKind {
+    Daily,
+    Weekly,
+}
+impl DigestKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+        }
+    }
+    fn days(self) -> u64 {
+        match self {
+            Self::Daily => 1,
+            Self::Weekly => 7,
+        }
+    }
+}
+
+/// Digests which are due for a user at `now`, each with the first day of the
+/// period that it covers. Digests go out after
+/// [config::DIGEST_SEND_HOUR] in the user's timezone; the daily summary
+/// covers yesterday, and the weekly report covers the 7 days before the most
+/// recent Monday. The weekly report stays due all week, so that a missed
+/// Monday is caught up on a later tick; `email_digest_sent` makes sure it
+/// only goes out once.
+pub fn due_digests(
+    now: DateTime<Utc>,
+    timezone: Tz,
+    email: &EmailPreferences,
+) -> Vec<(DigestKind, NaiveDate)> {
+    let local = now.with_timezone(&timezone);
+    let today = local.date_naive();
+    let past_send_hour = local.hour() >= config::DIGEST_SEND_HOUR;
+    let mut due = vec![];
+    if email.daily_digest && past_send_hour {
+        if let Some(yesterday) =
+            today.checked_sub_days(Days::new(DigestKind::Daily.days()))
+        {
+            due.push((DigestKind::Daily, yesterday));
+        }
+    }
+    if email.weekly_digest {
+        let last_send_day = if past_send_hour {
+            Some(today)
+        } else {
+            today.checked_sub_days(Days::new(1))
+        };
+        let first_day = last_send_day.and_then(|day| {
+            let since_monday = day.weekday().num_days_from_monday();
+            day.checked_sub_days(Days::new(u64::from(since_monday)))?
+                .checked_sub_days(Days::new(DigestKind::Weekly.days()))
+        });
+        if let Some(first_day) = first_day {
+            due.push((DigestKind::Weekly, first_day));
+        }
+    }
+    due
+}
+
+struct FoodRow {
+    name: String,
+    calories: i32,
+    protein: i32,
+    carbohydrates: i32,
+    fat: i32,
+    eaten_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Totals {
+    calories: i32,
+    protein: i32,
+    carbohydrates: i32,
+    fat: i32,
+}
+
+#[derive(Debug, PartialEq)]
+struct TopFood {
+    name: String,
+    times_eaten: i32,
+    calories: i32,
+}
+
+/// Foods are grouped by name, ignoring case, and ranked by the calories that
+/// they contributed over the whole period.
+fn top_foods(food: &[FoodRow]) -> Vec<TopFood> {
+    let mut foods: Vec<TopFood> = food
+        .iter()
+        .fold(BTreeMap::new(), |mut acc, row| {
+            let top =
+                acc.entry(row.name.trim().to_lowercase())
+                    .or_insert(TopFood {
+                        name: row.name.trim().to_string(),
+                        times_eaten: 0,
+                        calories: 0,
+                    });
+            top.times_eaten += 1;
+            top.calories += row.calories;
+            acc
+        })
+        .into_values()
+        .collect();
+    foods.sort_by(|a, b| {
+        b.calories
+            .cmp(&a.calories)
+            .then(b.times_eaten.cmp(&a.times_eaten))
+    });
+    foods.truncate(TOP_FOODS);
+    foods
+}
+
+struct Summary {
+    intro: String,
+    stats: Vec<String>,
+    balancing: Option<String>,
+    top_foods: Vec<String>,
+}
+
+pub struct Digest {
+    user_id: i32,
+    kind: DigestKind,
+    first_day: NaiveDate,
+    /// Totals for each day that had food logged.
+    daily_totals: Vec<Totals>,
+    top_foods: Vec<TopFood>,
+    calorie_goal: Option<i32>,
+    macro_goal: MacroGrams,
+    /// When calorie balancing is enabled, this is the goal for the day that
+    /// the digest is sent, after balancing in the period we're reporting on.
+    balanced_goal: Option<i32>,
+    hide_calories: bool,
+}
+
+impl Digest {
+    fn new(
+        user_id: i32,
+        kind: DigestKind,
+        first_day: NaiveDate,
+        timezone: Tz,
+        food: &[FoodRow],
+        preferences: &UserPreference,
+        balanced_goal: Option<i32>,
+    ) -> Self {
+        let daily_totals = food
+            .iter()
+            .fold(BTreeMap::new(), |mut acc, row| {
+                let date = row.eaten_at.with_timezone(&timezone).date_naive();
+                let totals: &mut Totals = acc.entry(date).or_default();
+                totals.calories += row.calories;
+                totals.protein += row.protein;
+                totals.carbohydrates += row.carbohydrates;
+                totals.fat += row.fat;
+                acc
+            })
+            .into_values()
+            .collect();
+        Self {
+            user_id,
+            kind,
+            first_day,
+            daily_totals,
+            top_foods: top_foods(food),
+            calorie_goal: preferences.caloric_intake_goal,
+            macro_goal: preferences
+                .macro_targets
+                .to_grams(preferences.caloric_intake_goal),
+            balanced_goal,
+            hide_calories: preferences.hide_calories,
+        }
+    }
+    /// The average over logged days, or the only day's totals for a daily
+    /// summary.
+    fn average(&self) -> Totals {
+        let days = self.daily_totals.len() as i32;
+        if days == 0 {
+            return Totals::default();
+        }
+        let sum =
+            self.daily_totals
+                .iter()
+                .fold(Totals::default(), |acc, t| Totals {
+                    calories: acc.calories + t.calories,
+                    protein: acc.protein + t.protein,
+                    carbohydrates: acc.carbohydrates + t.carbohydrates,
+                    fat: acc.fat + t.fat,
+                });
+        Totals {
+            calories: sum.calories / days,
+            protein: sum.protein / days,
+            carbohydrates: sum.carbohydrates / days,
+            fat: sum.fat / days,
+        }
+    }
+    fn empty_message(&self) -> String {
+        let period = match self.kind {
+            DigestKind::Daily => "yesterday",
+            DigestKind::Weekly => "last week",
+        };
+        format!("You didn't log any food {period}.")
+    }
+    /// The contents of the digest, shared by the HTML and plaintext bodies.
+    /// This is `None` if nothing was logged.
+    fn summary(&self) -> Option<Summary> {
+        let days_logged = self.daily_totals.len();
+        if days_logged == 0 {
+            return None;
+        }
+        let average = self.average();
+        let intro = match self.kind {
+            DigestKind::Daily => format!(
+                "Here's your summary for {}.",
+                self.first_day.format("%A, %B %-d")
+            ),
+            DigestKind::Weekly => format!(
+                "Here's your week starting {}. You logged food on \
+                {days_logged} of 7 days, and these are your daily averages.",
+                self.first_day.format("%A, %B %-d")
+            ),
+        };
+        let mut stats = Vec::new();
+        if let Some(goal) = self.calorie_goal {
+            stats.push(self.calorie_line(average.calories, goal));
+            if self.kind == DigestKind::Weekly {
+                let within = self
+                    .daily_totals
+                    .iter()
+                    .filter(|t| t.calories <= goal)
+                    .count();
+                stats.push(format!(
+                    "You were at or under your goal on {within} of \
+                    {days_logged} days."
+                ));
+            }
+        } else if !self.hide_calories {
+            stats.push(format!("Calories: {}", average.calories));
+        }
+        stats.push(macro_line(
+            "Protein",
+            average.protein,
+            self.macro_goal.protein,
+        ));
+        stats.push(macro_line(
+            "Carbs",
+            average.carbohydrates,
+            self.macro_goal.carbohydrates,
+        ));
+        stats.push(macro_line("Fat", average.fat, self.macro_goal.fat));
+        let balancing = match (self.balanced_goal, self.calorie_goal) {
+            (Some(_), Some(_)) if self.hide_calories => Some(
+                "Calorie balancing has adjusted your goal for today.".into(),
+            ),
+            (Some(balanced), Some(goal)) => Some(format!(
+                "With calorie balancing, your goal for today is {balanced} \
+                calories ({:+} from your usual goal).",
+                balanced - goal
+            )),
+            _ => None,
+        };
+        let top_foods = self
+            .top_foods
+            .iter()
+            .map(|food| {
+                let times = if food.times_eaten > 1 {
+                    format!(", {} times", food.times_eaten)
+                } else {
+                    "".into()
+                };
+                if self.hide_calories {
+                    format!("{}{times}", food.name)
+                } else {
+                    format!("{} ({} calories{times})", food.name, food.calories)
+                }
+            })
+            .collect();
+        Some(Summary {
+            intro,
+            stats,
+            balancing,
+            top_foods,
+        })
+    }
+    fn calorie_line(&self, calories: i32, goal: i32) -> String {
+        let under = calories <= goal;
+        if self.hide_calories {
+            let met = if under { "at or under" } else { "over" };
+            format!("Calories: {met} your goal")
+        } else {
+            let diff = (goal - calories).abs();
+            let status = if under { "under" } else { "over" };
+            format!("Calories: {calories} / {goal} ({diff} {status})")
+        }
+    }
+}
+
+impl Component for Digest {
+    fn render(&self) -> String {
+        let footer = EmailFooter {
+            user_id: self.user_id,
+        }
+        .render();
+        let Some(summary) = self.summary() else {
+            let message = self.empty_message();
+            let button = EmailButton {
+                href: &Route::UserHome,
+                text: "Pick back up",
+            }
+            .render();
+            return format!("<p>{message}</p>{button}{footer}");
+        };
+        let intro = summary.intro;
+        let stats = summary.stats.join("<br />");
+        let balancing = summary
+            .balancing
+            .map_or("".into(), |b| format!("<p>{b}</p>"));
+        let top_foods = if summary.top_foods.is_empty() {
+            "".into()
+        } else {
+            let items =
+                summary.top_foods.iter().fold(String::new(), |mut acc, f| {
+                    acc.push_str(&format!("<li>{}</li>", clean(f)));
+                    acc
+                });
+            format!("<p>Top foods:</p><ul>{items}</ul>")
+        };
+        format!(
+            r#"
+            <p>{intro}</p>
+            <p>{stats}</p>
+            {balancing}
+            {top_foods}
+            {footer}
+            "#
+        )
+    }
+}
+
+impl EmailTemplate for Digest {
+    fn subject(&self) -> String {
+        let date = self.first_day.format("%b %-d");
+        match self.kind {
+            DigestKind::Daily => format!("Your summary for {date}"),
+            DigestKind::Weekly => format!("Your week starting {date}"),
+        }
+    }
+    fn plaintext(&self) -> String {
+        let footer = unsubscribe::footer(self.user_id);
+        let Some(summary) = self.summary() else {
+            let home = format!("{}{}", config::BASE_URL, Route::UserHome);
+            return format!(
+                "{} Pick back up at {home}\n\n{footer}",
+                self.empty_message()
+            );
+        };
+        let mut lines = vec![summary.intro, String::new()];
+        lines.extend(summary.stats);
+        if let Some(balancing) = summary.balancing {
+            lines.push(String::new());
+            lines.push(balancing);
+        }
+        if !summary.top_foods.is_empty() {
+            lines.push(String::new());
+            lines.push("Top foods:".into());
+            lines.extend(summary.top_foods.iter().map(|f| format!("- {f}")));
+        }
+        lines.push(String::new());
+        lines.push(footer);
+        lines.join("\n")
+    }
+}
+
+fn macro_line(label: &str, grams: i32, goal: Option<i32>) -> String {
+    match goal {
+        Some(goal) => format!("{label}: {grams}g / {goal}g"),
+        None => format!("{label}: {grams}g"),
+    }
+}
+
+pub async fn build_digest(
+    db: &PgPool,
+    user_id: i32,
+    preferences: &UserPreference,
+    kind: DigestKind,
+    first_day: NaiveDate,
+) -> Aresult<Digest> {
+    let timezone = preferences.timezone;
+    let end = first_day
+        .checked_add_days(Days::new(kind.days()))
+        .ok_or(Error::msg("digest ends after the end of time"))?;
+    let food = query_as!(
+        FoodRow,
+        "select f.name, f.calories, f.protein, f.carbohydrates, f.fat,
+            fee.eaten_at
+        from food_eaten_event fee
+        join food f on fee.food_id = f.id
+        where
+            fee.user_id = $1
+            and f.user_id = $1
+            and fee.eaten_at >= $2
+            and fee.eaten_at < $3",
+        user_id,
+        start_of_date(first_day, timezone),
+        start_of_date(end, timezone)
+    )
+    .fetch_all(db)
+    .await?;
+    let balanced_goal = if preferences.calorie_balancing_enabled {
+        balancing::get_current_goal(db, user_id, preferences)
+            .await?
+            .calories
+    } else {
+        None
+    };
+    Ok(Digest::new(
+        user_id,
+        kind,
+        first_day,
+        timezone,
+        &food,
+        preferences,
+        balanced_goal,
+    ))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn food(name: &str, calories: i32, eaten_at: &str) -> FoodRow {
+        FoodRow {
+            name: name.into(),
+            calories,
+            protein: 10,
+            carbohydrates: 20,
+            fat: 5,
+            eaten_at: at(eaten_at),
+        }
+    }
+
+    pub fn daily_digest(preferences: &UserPreference) -> Digest {
+        std::env::set_var("SESSION_SECRET", "foo");
+        Digest::new(
+            1,
+            DigestKind::Daily,
+            date("2024-06-28"),
+            Tz::UTC,
+            &[
+                food("Oatmeal", 300, "2024-06-28T08:00:00Z"),
+                food("Chicken", 500, "2024-06-28T12:00:00Z"),
+                food("chicken ", 500, "2024-06-28T18:00:00Z"),
+            ],
+            preferences,
+            Some(2100),
+        )
+    }
+
+    const BOTH: EmailPreferences = EmailPreferences {
+        daily_digest: true,
+        weekly_digest: true,
+        reminder_time: None,
+    };
+
+    const DAILY: EmailPreferences = EmailPreferences {
+        daily_digest: true,
+        weekly_digest: false,
+        reminder_time: None,
+    };
+
+    const WEEKLY: EmailPreferences = EmailPreferences {
+        daily_digest: false,
+        weekly_digest: true,
+        reminder_time: None,
+    };
+
+    #[test]
+    fn test_nothing_due_before_send_hour() {
+        // It's Saturday, 19:47 in New York, but already Sunday 08:47 in
+        // Tokyo.
+        assert_eq!(
+            due_digests(
+                at("2024-06-29T04:00:00Z"),
+                Tz::America__New_York,
+                &DAILY
+            ),
+            vec![]
+        );
+        assert_eq!(
+            due_digests(utc_now(), Tz::Asia__Tokyo, &DAILY),
+            vec![(DigestKind::Daily, date("2024-06-29"))]
+        );
+    }
+
+    #[test]
+    fn test_weekly_digest_on_mondays() {
+        // 07:00 on Monday, July 1st in New York.
+        assert_eq!(
+            due_digests(
+                at("2024-07-01T11:00:00Z"),
+                Tz::America__New_York,
+                &BOTH
+            ),
+            vec![
+                (DigestKind::Daily, date("2024-06-30")),
+                (DigestKind::Weekly, date("2024-06-24"))
+            ]
+        );
+        assert_eq!(
+            due_digests(
+                at("2024-07-01T11:00:00Z"),
+                Tz::America__New_York,
+                &EmailPreferences::default()
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_weekly_digest_stays_due_until_next_monday() {
+        // 05:00 on Monday, July 1st in New York; this week's report isn't
+        // due yet, but last week's still is.
+        assert_eq!(
+            due_digests(
+                at("2024-07-01T09:00:00Z"),
+                Tz::America__New_York,
+                &WEEKLY
+            ),
+            vec![(DigestKind::Weekly, date("2024-06-17"))]
+        );
+        // Wednesday, July 3rd, and Sunday, July 7th.
+        for now in ["2024-07-03T16:00:00Z", "2024-07-08T03:00:00Z"] {
+            assert_eq!(
+                due_digests(at(now), Tz::America__New_York, &WEEKLY),
+                vec![(DigestKind::Weekly, date("2024-06-24"))]
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_foods_are_grouped_by_name() {
+        let digest = daily_digest(&UserPreference::default());
+        assert_eq!(
+            digest.top_foods,
+            vec![
+                TopFood {
+                    name: "Chicken".into(),
+                    times_eaten: 2,
+                    calories: 1000
+                },
+                TopFood {
+                    name: "Oatmeal".into(),
+                    times_eaten: 1,
+                    calories: 300
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_daily_body() {
+        let preferences = UserPreference {
+            caloric_intake_goal: Some(2000),
+            calorie_balancing_enabled: true,
+            ..Default::default()
+        };
+        let body = daily_digest(&preferences).plaintext();
+        assert!(body.contains("Here's your summary for Friday, June 28."));
+        assert!(body.contains("Calories: 1300 / 2000 (700 under)"));
+        assert!(body.contains("Protein: 30g\n"));
+        assert!(body.contains("your goal for today is 2100 calories (+100"));
+        assert!(body.contains("- Chicken (1000 calories, 2 times)"));
+    }
+
+    #[test]
+    fn test_hidden_calories_body() {
+        let preferences = UserPreference {
+            caloric_intake_goal: Some(2000),
+            hide_calories: true,
+            ..Default::default()
+        };
+        let body = daily_digest(&preferences).plaintext();
+        assert!(body.contains("Calories: at or under your goal"));
+        assert!(body.contains("- Chicken, 2 times"));
+        assert!(!body.contains("1300"));
+    }
+}
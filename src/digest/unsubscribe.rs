@@ -0,0 +1,164 @@
+//! Every email includes an unsubscribe link which works without logging in.
+//! The link carries the user's ID, signed with the session secret.
+
+use crate::{auth::crypto, config, prelude::*};
+
+fn sign(user_id: i32) -> String {
+    hex::encode(crypto::get_digest(
+        format!("unsubscribe {user_id}").as_bytes(),
+    ))
+}
+
+pub fn unsubscribe_token(user_id: i32) -> String {
+    format!("{user_id}-{}", sign(user_id))
+}
+
+/// Returns the user ID from a valid token.
+fn verify_token(token: &str) -> Option<i32> {
+    let (user_id, signature) = token.split_once('-')?;
+    let user_id: i32 = user_id.parse().ok()?;
+    let signature = hex::decode(signature).ok()?;
+    crypto::is_valid(format!("unsubscribe {user_id}").as_bytes(), &signature)
+        .then_some(user_id)
+}
+
+/// Appended to the end of every opt-in email.
+pub fn footer(user_id: i32) -> String {
+    let base = config::BASE_URL;
+    let preferences = Route::UserPreference;
+    let unsubscribe = Route::Unsubscribe(Some(unsubscribe_token(user_id)));
+    format!(
+        "You're receiving this because you opted in to emails. Change your \
+        email preferences at {base}{preferences}, or unsubscribe from all \
+        emails at {base}{unsubscribe}"
+    )
+}
+
+/// HTML version of [footer].
+pub struct EmailFooter {
+    pub user_id: i32,
+}
+impl Component for EmailFooter {
+    fn render(&self) -> String {
+        let base = config::BASE_URL;
+        let preferences = Route::UserPreference;
+        let unsubscribe =
+            Route::Unsubscribe(Some(unsubscribe_token(self.user_id)));
+        format!(
+            r#"
+            <p style="margin-top: 24px; font-size: 12px; color: #64748b;">
+                You're receiving this because you opted in to emails. Change
+                your <a href="{base}{preferences}">email preferences</a>, or
+                <a href="{base}{unsubscribe}">unsubscribe</a> from all emails.
+            </p>
+            "#
+        )
+    }
+}
+
+struct UnsubscribePage<'a> {
+    token: &'a str,
+}
+impl Component for UnsubscribePage<'_> {
+    fn render(&self) -> String {
+        let unsubscribe = Route::Unsubscribe(Some(self.token.to_string()));
+        format!(
+            r#"
+            <form
+                hx-post="{unsubscribe}"
+                class="flex flex-col gap-2 items-start max-w-prose"
+            >
+                <h1 class="text-2xl font-extrabold">Unsubscribe</h1>
+                <p>
+                    Stop all daily summaries, weekly reports, and reminders
+                    from beancount.bot?
+                </p>
+                <button
+                    class="bg-red-100 hover:bg-red-200 rounded p-1 text-black"
+                >
+                    Unsubscribe
+                </button>
+            </form>
+            "#
+        )
+    }
+}
+
+struct Unsubscribed;
+impl Component for Unsubscribed {
+    fn render(&self) -> String {
+        let preferences = Route::UserPreference;
+        format!(
+            r#"
+            <div class="flex flex-col gap-2 max-w-prose">
+                <h1 class="text-2xl font-extrabold">Unsubscribed</h1>
+                <p>
+                    You won't receive any more emails from us, other than
+                    password resets. You can turn emails back on from your
+                    <a class="link" href="{preferences}">preferences</a>.
+                </p>
+            </div>
+            "#
+        )
+    }
+}
+
+/// Email clients and link scanners sometimes follow links on their own, so
+/// visiting the link only asks for confirmation.
+pub async fn get_unsubscribe_page(
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, ServerError> {
+    verify_token(&token)
+        .ok_or_else(|| ServerError::forbidden("unsubscribe page"))?;
+    Ok(Page {
+        title: "Unsubscribe",
+        children: &PageContainer {
+            children: &UnsubscribePage { token: &token },
+        },
+    }
+    .render())
+}
+
+pub async fn handle_unsubscribe(
+    State(AppState { db, .. }): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, ServerError> {
+    let user_id = verify_token(&token)
+        .ok_or_else(|| ServerError::forbidden("unsubscribe"))?;
+    query!(
+        "update user_preference set
+            daily_digest_enabled = false,
+            weekly_digest_enabled = false,
+            reminder_time = null
+        where user_id = $1",
+        user_id
+    )
+    .execute(&db)
+    .await?;
+    Ok(Unsubscribed.render())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn init() {
+        std::env::set_var("SESSION_SECRET", "foo");
+    }
+
+    #[test]
+    fn test_token_round_trip() {
+        init();
+        assert_eq!(verify_token(&unsubscribe_token(42)), Some(42));
+    }
+
+    #[test]
+    fn test_token_is_bound_to_user() {
+        init();
+        let token = unsubscribe_token(42);
+        let (_, signature) = token.split_once('-').unwrap();
+        assert_eq!(verify_token(&format!("43-{signature}")), None);
+        assert_eq!(verify_token("42-not-hex"), None);
+        assert_eq!(verify_token("garbage"), None);
+    }
+}
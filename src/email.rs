@@ -0,0 +1,126 @@
+//! Templates for outgoing emails. Every email is sent as a multipart message,
+//! with an HTML part rendered from [Component]s, and a plaintext alternative
+//! for email clients which don't display HTML. Delivery happens in
+//! [crate::smtp].
+//!
+//! Email clients don't load our stylesheet, so email components use inline
+//! styles instead of Tailwind classes.
+
+use crate::{config, prelude::*};
+
+/// An email which can be rendered into an [Email]. [Component::render]
+/// provides the HTML body, which is wrapped in [EmailLayout].
+pub trait EmailTemplate: Component {
+    fn subject(&self) -> String;
+    fn plaintext(&self) -> String;
+}
+
+/// A fully rendered email, ready to be sent.
+#[derive(Clone, Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl Email {
+    pub fn new(to: &str, template: &impl EmailTemplate) -> Self {
+        let subject = template.subject();
+        let html = EmailLayout {
+            title: &subject,
+            children: template,
+        }
+        .render();
+        Self {
+            to: to.into(),
+            subject,
+            html,
+            text: template.plaintext(),
+        }
+    }
+}
+
+pub struct EmailLayout<'a> {
+    pub title: &'a str,
+    pub children: &'a dyn Component,
+}
+impl Component for EmailLayout<'_> {
+    fn render(&self) -> String {
+        let title = clean(self.title);
+        let children = self.children.render();
+        let base = config::BASE_URL;
+        format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta charset="UTF-8">
+                    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+                    <title>{title}</title>
+                </head>
+                <body style="margin: 0; padding: 16px; background: #f0fdf4; font-family: sans-serif; color: #0f172a;">
+                    <div style="max-width: 600px; margin: 0 auto; padding: 16px; background: #ffffff; border-radius: 8px;">
+                        <a href="{base}" style="font-size: 20px; font-weight: bold; color: #0f172a; text-decoration: none;">
+                            beancount.bot
+                        </a>
+                        {children}
+                    </div>
+                </body>
+            </html>
+            "#
+        )
+    }
+}
+
+/// A link which looks like a button. `href` is relative to
+/// [config::BASE_URL].
+pub struct EmailButton<'a> {
+    pub href: &'a Route,
+    pub text: &'a str,
+}
+impl Component for EmailButton<'_> {
+    fn render(&self) -> String {
+        let base = config::BASE_URL;
+        let href = self.href;
+        let text = clean(self.text);
+        format!(
+            r#"
+            <p>
+                <a
+                    href="{base}{href}"
+                    style="display: inline-block; padding: 8px 12px; background: #bbf7d0; color: #0f172a; border-radius: 4px; text-decoration: none;"
+                >{text}</a>
+            </p>
+            "#
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Hello;
+    impl Component for Hello {
+        fn render(&self) -> String {
+            "<p>Hello, world</p>".into()
+        }
+    }
+    impl EmailTemplate for Hello {
+        fn subject(&self) -> String {
+            "Greetings & salutations".into()
+        }
+        fn plaintext(&self) -> String {
+            "Hello, world".into()
+        }
+    }
+
+    #[test]
+    fn test_email_has_both_parts() {
+        let email = Email::new("a@example.com", &Hello);
+        assert_eq!(email.subject, "Greetings & salutations");
+        assert_eq!(email.text, "Hello, world");
+        assert!(email.html.contains("<p>Hello, world</p>"));
+        assert!(email.html.contains("<title>Greetings &amp; salutations"));
+    }
+}
@@ -43,6 +43,17 @@ impl ServerError {
             response_body: "Method is not allowed".into(),
         }
     }
+    /// `response_body` should explain when the user can try again.
+    pub fn too_many_requests(
+        log_msg: &'static str,
+        response_body: String,
+    ) -> Self {
+        ServerError {
+            err: Some(Error::msg(log_msg)),
+            status: StatusCode::TOO_MANY_REQUESTS,
+            response_body,
+        }
+    }
     pub fn bad_request(
         log_msg: &'static str,
         response_body: Option<String>,
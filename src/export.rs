@@ -0,0 +1,299 @@
+//! Users can download all of their data. The food log is available as CSV
+//! for spreadsheets, and everything (food, balancing checkpoints, and
+//! preferences) is available as JSON. Timestamps are in the user's timezone.
+//!
+//! Food is streamed straight from the database to the client, so that large
+//! exports don't need to be buffered in memory.
+
+use crate::prelude::*;
+use axum::{
+    body::Body,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+};
+use futures::{
+    channel::mpsc,
+    future::ready,
+    stream::{self, StreamExt},
+    SinkExt,
+};
+use serde::Serialize;
+
+/// Number of rows which can be read from the database ahead of the client.
+const EXPORT_BUFFER_SIZE: usize = 64;
+
+const CSV_HEADER: &str =
+    "eaten_at,food_name,calories,protein_grams,carbohydrates_grams,fat_grams\n";
+
+pub struct ExportData;
+impl Component for ExportData {
+    fn render(&self) -> String {
+        let csv = Route::ExportCsv;
+        let json = Route::ExportJson;
+        let import = Route::ImportFood;
+        format!(
+            r#"
+            <div class="p-4 mt-4 bg-blue-200 dark:bg-blue-950 dark:text-slate-200
+                text-black rounded w-prose flex flex-col gap-2"
+            >
+                <h2 class="text-lg">Your Data</h2>
+                <p class="text-xs">
+                    The CSV export includes all of the food you've counted. The
+                    JSON export also includes your balancing checkpoints and
+                    preferences. You can also import food from a CSV file, or
+                    from another calorie tracker.
+                </p>
+                <!-- Note: hx-boost is disabled so that the browser will
+                     download the exports -->
+                <div class="flex gap-2">
+                    <a
+                        href="{import}"
+                        class="bg-blue-100 hover:bg-blue-200 dark:bg-blue-800
+                        dark:hover:bg-blue-700 rounded p-1"
+                    >Import</a>
+                    <a
+                        href="{csv}"
+                        hx-boost="false"
+                        download
+                        class="bg-emerald-100 hover:bg-emerald-200
+                        dark:bg-emerald-700 dark:hover:bg-emerald-600 rounded
+                        p-1"
+                    >Download CSV</a>
+                    <a
+                        href="{json}"
+                        hx-boost="false"
+                        download
+                        class="bg-emerald-100 hover:bg-emerald-200
+                        dark:bg-emerald-700 dark:hover:bg-emerald-600 rounded
+                        p-1"
+                    >Download JSON</a>
+                </div>
+            </div>
+            "#
+        )
+    }
+}
+
+struct FoodRow {
+    food_name: String,
+    calories: i32,
+    protein_grams: i32,
+    carbohydrates_grams: i32,
+    fat_grams: i32,
+    eaten_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct ExportedFood {
+    eaten_at: String,
+    food_name: String,
+    calories: i32,
+    protein_grams: i32,
+    carbohydrates_grams: i32,
+    fat_grams: i32,
+}
+
+impl ExportedFood {
+    fn new(row: FoodRow, timezone: Tz) -> Self {
+        Self {
+            eaten_at: row.eaten_at.with_timezone(&timezone).to_rfc3339(),
+            food_name: row.food_name,
+            calories: row.calories,
+            protein_grams: row.protein_grams,
+            carbohydrates_grams: row.carbohydrates_grams,
+            fat_grams: row.fat_grams,
+        }
+    }
+    fn to_csv_line(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}\n",
+            self.eaten_at,
+            csv_field(&self.food_name),
+            self.calories,
+            self.protein_grams,
+            self.carbohydrates_grams,
+            self.fat_grams
+        )
+    }
+}
+
+/// Quote a field if it contains anything that would break the CSV structure,
+/// per RFC 4180.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The query needs to borrow the pool for as long as the response body is
+/// being streamed, so rows are fed to the body through a channel from a
+/// separate task.
+fn stream_food(
+    db: PgPool,
+    user_id: i32,
+    timezone: Tz,
+) -> mpsc::Receiver<Aresult<ExportedFood>> {
+    let (mut tx, rx) = mpsc::channel(EXPORT_BUFFER_SIZE);
+    tokio::spawn(async move {
+        let mut rows = query_as!(
+            FoodRow,
+            "select
+                f.name food_name,
+                f.calories,
+                f.protein protein_grams,
+                f.carbohydrates carbohydrates_grams,
+                f.fat fat_grams,
+                fee.eaten_at
+            from food_eaten_event fee
+            join food f on f.id = fee.food_id
+            where fee.user_id = $1
+            order by fee.eaten_at",
+            user_id
+        )
+        .fetch(&db);
+        while let Some(row) = rows.next().await {
+            let row = row
+                .map(|row| ExportedFood::new(row, timezone))
+                .map_err(Error::from);
+            if tx.send(row).await.is_err() {
+                // The client went away.
+                break;
+            }
+        }
+    });
+    rx
+}
+
+async fn list_checkpoints(
+    db: impl PgExecutor<'_>,
+    user_id: i32,
+) -> Aresult<Vec<NaiveDate>> {
+    struct Qres {
+        ignore_before: NaiveDate,
+    }
+    Ok(query_as!(
+        Qres,
+        "select ignore_before from balancing_checkpoint
+        where user_id = $1
+        order by ignore_before",
+        user_id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| row.ignore_before)
+    .collect())
+}
+
+fn attachment(filename: &str) -> String {
+    format!(r#"attachment; filename="{filename}""#)
+}
+
+pub async fn export_csv(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "export csv")?;
+    let preferences = session.get_preferences(&db).await?;
+    let lines = stream_food(db, session.user_id, preferences.timezone)
+        .map(|food| food.map(|food| food.to_csv_line()));
+    let body = stream::once(ready(Ok(CSV_HEADER.to_string()))).chain(lines);
+    Ok((
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (CONTENT_DISPOSITION, attachment("calcount-food.csv")),
+        ],
+        Body::from_stream(body),
+    ))
+}
+
+#[derive(Serialize)]
+struct ExportHeader<'a> {
+    exported_at: String,
+    preferences: &'a UserPreference,
+    balancing_checkpoints: &'a [NaiveDate],
+}
+
+/// Splits the serialized header before its closing brace, so that the food
+/// array can be streamed into the same object.
+fn json_prefix(header: &ExportHeader) -> Aresult<String> {
+    let header = serde_json::to_string(header)?;
+    let object_body = header
+        .strip_suffix('}')
+        .ok_or(Error::msg("export header is not a JSON object"))?;
+    Ok(format!(r#"{object_body},"food":["#))
+}
+
+pub async fn export_json(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "export json")?;
+    let preferences = session.get_preferences(&db).await?;
+    let checkpoints = list_checkpoints(&db, session.user_id).await?;
+    let prefix = json_prefix(&ExportHeader {
+        exported_at: utc_now()
+            .with_timezone(&preferences.timezone)
+            .to_rfc3339(),
+        preferences: &preferences,
+        balancing_checkpoints: &checkpoints,
+    })?;
+    let food = stream_food(db, session.user_id, preferences.timezone)
+        .enumerate()
+        .map(|(i, food)| {
+            let food = serde_json::to_string(&food?)?;
+            Ok::<_, Error>(if i == 0 { food } else { format!(",{food}") })
+        });
+    let body = stream::once(ready(Ok(prefix)))
+        .chain(food)
+        .chain(stream::once(ready(Ok("]}".to_string()))));
+    Ok((
+        [
+            (CONTENT_TYPE, "application/json".to_string()),
+            (CONTENT_DISPOSITION, attachment("calcount-data.json")),
+        ],
+        Body::from_stream(body),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_csv_line() {
+        let food = ExportedFood::new(
+            FoodRow {
+                food_name: r#"Sandwich, "extra" cheese"#.into(),
+                calories: 500,
+                protein_grams: 20,
+                carbohydrates_grams: 50,
+                fat_grams: 25,
+                eaten_at: utc_now(),
+            },
+            Tz::America__New_York,
+        );
+        assert_eq!(
+            food.to_csv_line(),
+            "2024-06-29T19:47:33-04:00,\"Sandwich, \"\"extra\"\" cheese\",500,20,50,25\n"
+        );
+    }
+
+    #[test]
+    fn test_json_prefix() {
+        let preferences = UserPreference::default();
+        let checkpoints = [NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()];
+        let prefix = json_prefix(&ExportHeader {
+            exported_at: "now".into(),
+            preferences: &preferences,
+            balancing_checkpoints: &checkpoints,
+        })
+        .unwrap();
+        let export: serde_json::Value =
+            serde_json::from_str(&format!("{prefix}]}}")).unwrap();
+        assert_eq!(export["balancing_checkpoints"][0], "2024-01-01");
+        assert_eq!(export["preferences"]["timezone"], "UTC");
+        assert!(export["food"].as_array().unwrap().is_empty());
+    }
+}
@@ -82,7 +82,7 @@ pub const fn get_client_script() -> &'static str {
         });
 
         htmx.on('htmx:beforeSwap', (e) => {
-            if (e.detail.xhr.status === 400) {
+            if ([400, 429].includes(e.detail.xhr.status)) {
                 e.detail.shouldSwap = true;
                 e.detail.isError = false;
             }
@@ -13,12 +13,15 @@ impl Component for PrivacyPolicy {
                 a 3rd party under and circumstances, which includes our email
                 list as well as meal data you've entered into the app.
             </p>
+            <p>
+                You can download all of your Bean Count data, or delete your
+                account along with all of your data, at any time from the
+                preferences page. Deletion happens immediately, and cancels
+                your subscription.
+            </p>
             <p>
                 Reach out to <a href="mailto:jdevries3133@gmail.com">jdevries3133@gmail.com</a>
-                for data management concerns, including requesting deletion of
-                your Bean Count account or requesting an export of your Bean
-                Count data. Export or total deletion will be completed within
-                2-4 weeks.
+                for any other data management concerns.
             </p>
         </div>
         "#.into()
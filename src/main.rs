@@ -4,6 +4,7 @@ use anyhow::Result;
 use dotenvy::dotenv;
 use std::net::SocketAddr;
 
+mod admin;
 mod auth;
 mod balancing;
 mod blog;
@@ -14,7 +15,11 @@ mod config;
 mod controllers;
 mod count_chat;
 mod db_ops;
+mod delete_account;
+mod digest;
+mod email;
 mod errors;
+mod export;
 mod html_sanitize;
 mod htmx;
 mod legal;
@@ -26,6 +31,7 @@ mod prelude;
 mod routes;
 mod smtp;
 mod stripe;
+mod weight;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -33,7 +39,9 @@ async fn main() -> Result<()> {
 
     let db = db_ops::create_pg_pool().await?;
     sqlx::migrate!().run(&db).await?;
-    let state = models::AppState { db };
+    let llm = count_chat::llm_backend_from_env()?;
+    digest::spawn_scheduler(db.clone());
+    let state = models::AppState { db, llm };
 
     let app = routes::get_routes(state.clone()).with_state(state);
 
@@ -46,7 +54,7 @@ async fn main() -> Result<()> {
                 println!("listening on {addr}");
             })
             .unwrap_or_else(|e| panic!("Can bind to address {addr} ({e})")),
-        app.into_make_service(),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
     )
     .await
     .unwrap();
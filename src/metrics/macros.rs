@@ -1,5 +1,8 @@
+use super::progress::{CalorieProgress, MacroProgress};
 use crate::{
-    balancing, chrono_utils::is_before_today, count_chat::FoodItemDetails,
+    balancing::{self, CurrentGoal},
+    chrono_utils::is_before_today,
+    count_chat::FoodItemDetails,
     prelude::*,
 };
 
@@ -17,10 +20,10 @@ impl Macros {
     pub fn is_empty(&self) -> bool {
         self.calories == 0
     }
-    pub fn render_status(&self, caloric_intake_goal: Option<i32>) -> String {
+    pub fn render_status(&self, goal: &CurrentGoal) -> String {
         MacroStatus {
             macros: self,
-            caloric_intake_goal,
+            goal,
             user_preferences: &self.user_preferences,
         }
         .render()
@@ -29,7 +32,7 @@ impl Macros {
 
 pub struct MacroStatus<'a> {
     macros: &'a Macros,
-    caloric_intake_goal: Option<i32>,
+    goal: &'a CurrentGoal,
     user_preferences: &'a UserPreference,
 }
 impl Component for MacroStatus<'_> {
@@ -39,7 +42,7 @@ impl Component for MacroStatus<'_> {
         let fat = self.macros.fat_grams;
         let carbs = self.macros.carbohydrates_grams;
         let macros = Route::DisplayMacros;
-        let calories_remaining = match self.caloric_intake_goal {
+        let calorie_status = match self.goal.calories {
             Some(goal) => {
                 let computed_goal =
                     if self.user_preferences.calorie_balancing_enabled {
@@ -67,35 +70,32 @@ impl Component for MacroStatus<'_> {
                     } else {
                         "".into()
                     };
-                let diff = goal - calories;
-                let goal_statement = if self.user_preferences.hide_calories {
-                    if diff <= 0 {
-                        "<p>You have met your calorie goal!</p>".to_string()
-                    } else {
-                        "<p>You have not met your calorie goal yet.</p>"
-                            .to_string()
-                    }
-                } else {
-                    format!(
-                        "<p>You have {diff} calories left to eat today.</p>"
-                    )
-                };
-                format!("{goal_statement}{computed_goal}")
+                let progress = CalorieProgress {
+                    calories,
+                    goal,
+                    hide_calories: self.user_preferences.hide_calories,
+                }
+                .render();
+                format!("{progress}{computed_goal}")
             }
-            None => "".into(),
-        };
-        let total_calories = if self.user_preferences.hide_calories {
-            "".into()
-        } else {
-            format!("{calories} calories,")
+            None if self.user_preferences.hide_calories => "".into(),
+            None => format!("<p>You've eaten {calories} calories today.</p>"),
         };
+        let macro_progress = MacroProgress {
+            protein_grams: protein,
+            carbohydrates_grams: carbs,
+            fat_grams: fat,
+            targets: &self.goal.macros,
+        }
+        .render();
         format!(
-            r#"<div hx-get="{macros}" hx-trigger="reload-macros from:body">
-                {calories_remaining}
-                <p>
-                    In total, you've eaten {total_calories} {protein} grams
-                    of protein, {fat} grams of fat, and {carbs} grams of carbs today.
-                </p>
+            r#"<div
+                hx-get="{macros}"
+                hx-trigger="reload-macros from:body"
+                class="flex flex-col gap-1"
+            >
+                {calorie_status}
+                {macro_progress}
             </div>"#
         )
     }
@@ -185,23 +185,20 @@ pub async fn get_macros(
 }
 
 pub async fn display_macros(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers_err(&headers, "display macros")?;
     let preferences = session.get_preferences(&db).await?;
     let macros = get_macros(&db, session.user_id, &preferences).await?;
-    let caloric_intake_goal = if preferences.calorie_balancing_enabled {
-        balancing::get_current_goal(&db, session.user_id, &preferences).await?
-    } else {
-        preferences.caloric_intake_goal
-    };
+    let goal =
+        balancing::get_current_goal(&db, session.user_id, &preferences).await?;
     if macros.is_empty() && !preferences.calorie_balancing_enabled {
         Ok(MacroPlaceholder {}.render())
     } else {
         Ok(MacroStatus {
             macros: &macros,
-            caloric_intake_goal,
+            goal: &goal,
             user_preferences: &preferences,
         }
         .render())
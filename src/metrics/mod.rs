@@ -1,3 +1,6 @@
 mod macros;
+mod progress;
+mod trends;
 
 pub use macros::{display_macros, get_macros, MacroPlaceholder, Macros};
+pub use trends::trends;
@@ -0,0 +1,249 @@
+//! Progress bars for today's calories and macros. These are rendered inside
+//! of [super::MacroStatus], so they're refreshed by the `reload-macros`
+//! event along with everything else.
+
+use crate::{
+    preferences::{
+        MacroGrams, CALORIES_PER_GRAM_CARBOHYDRATES, CALORIES_PER_GRAM_FAT,
+        CALORIES_PER_GRAM_PROTEIN,
+    },
+    prelude::*,
+};
+
+/// How full a bar should be, as a whole percentage in `0..=100`. A goal of
+/// zero or less is immediately met.
+fn fill_percent(value: i32, goal: i32) -> i32 {
+    if goal <= 0 {
+        return if value > 0 { 100 } else { 0 };
+    }
+    let percent = (i64::from(value) * 100 / i64::from(goal)).clamp(0, 100);
+    percent as i32
+}
+
+struct ProgressBar<'a> {
+    label: &'a str,
+    percent: i32,
+    /// Shown after the label, like "1200 / 2000 calories." Left empty when
+    /// numbers are hidden.
+    detail: &'a str,
+    bar_color: &'a str,
+}
+impl Component for ProgressBar<'_> {
+    fn render(&self) -> String {
+        let label = self.label;
+        let percent = self.percent;
+        let detail = self.detail;
+        let bar_color = self.bar_color;
+        format!(
+            r#"
+            <div>
+                <p class="text-sm flex justify-between gap-2">
+                    <span>{label}</span>
+                    <span>{detail}</span>
+                </p>
+                <div
+                    class="w-full h-3 rounded-full bg-slate-200 dark:bg-slate-700"
+                    role="progressbar"
+                    aria-label="{label}"
+                    aria-valuemin="0"
+                    aria-valuemax="100"
+                    aria-valuenow="{percent}"
+                >
+                    <div
+                        class="h-3 rounded-full {bar_color}"
+                        style="width: {percent}%"
+                    ></div>
+                </div>
+            </div>
+            "#
+        )
+    }
+}
+
+/// Today's calories against the user's goal (the balanced goal, if calorie
+/// balancing is enabled). When `hide_calories` is set, only the fill of the
+/// bar is shown.
+pub struct CalorieProgress {
+    pub calories: i32,
+    pub goal: i32,
+    pub hide_calories: bool,
+}
+impl Component for CalorieProgress {
+    fn render(&self) -> String {
+        let diff = (self.goal - self.calories).abs();
+        let (status, bar_color) = if self.hide_calories {
+            if self.calories >= self.goal {
+                (
+                    "You have met your calorie goal!".to_string(),
+                    "bg-emerald-500",
+                )
+            } else {
+                (
+                    "You have not met your calorie goal yet.".to_string(),
+                    "bg-emerald-500",
+                )
+            }
+        } else if self.calories > self.goal {
+            (format!("You are {diff} calories over."), "bg-red-500")
+        } else {
+            (
+                format!("You have {diff} calories left to eat today."),
+                "bg-emerald-500",
+            )
+        };
+        let detail = if self.hide_calories {
+            "".to_string()
+        } else {
+            format!("{} / {} calories", self.calories, self.goal)
+        };
+        let bar = ProgressBar {
+            label: "Calories",
+            percent: fill_percent(self.calories, self.goal),
+            detail: &detail,
+            bar_color,
+        }
+        .render();
+        format!(
+            r#"
+            <div class="flex flex-col gap-1">
+                {bar}
+                <p>{status}</p>
+            </div>
+            "#
+        )
+    }
+}
+
+/// One bar per macro. Macros with a target fill towards it, and show the
+/// grams remaining. Otherwise, bars show how much of today's calories came
+/// from each macro.
+pub struct MacroProgress<'a> {
+    pub protein_grams: i32,
+    pub carbohydrates_grams: i32,
+    pub fat_grams: i32,
+    pub targets: &'a MacroGrams,
+}
+impl Component for MacroProgress<'_> {
+    fn render(&self) -> String {
+        let protein_calories = self.protein_grams * CALORIES_PER_GRAM_PROTEIN;
+        let carb_calories =
+            self.carbohydrates_grams * CALORIES_PER_GRAM_CARBOHYDRATES;
+        let fat_calories = self.fat_grams * CALORIES_PER_GRAM_FAT;
+        let total = protein_calories + carb_calories + fat_calories;
+        [
+            (
+                "Protein",
+                self.protein_grams,
+                protein_calories,
+                self.targets.protein,
+                "bg-sky-500",
+            ),
+            (
+                "Carbs",
+                self.carbohydrates_grams,
+                carb_calories,
+                self.targets.carbohydrates,
+                "bg-amber-500",
+            ),
+            (
+                "Fat",
+                self.fat_grams,
+                fat_calories,
+                self.targets.fat,
+                "bg-fuchsia-500",
+            ),
+        ]
+        .iter()
+        .fold(
+            String::new(),
+            |mut acc, (label, grams, calories, target, color)| {
+                let (percent, detail) = match target {
+                    Some(target) => {
+                        let remaining = target - grams;
+                        let detail = if remaining >= 0 {
+                            format!("{grams} / {target}g, {remaining}g left")
+                        } else {
+                            format!("{grams} / {target}g, {}g over", -remaining)
+                        };
+                        (fill_percent(*grams, *target), detail)
+                    }
+                    None => {
+                        let percent = fill_percent(*calories, total);
+                        (percent, format!("{grams}g ({percent}% of calories)"))
+                    }
+                };
+                acc.push_str(
+                    &ProgressBar {
+                        label,
+                        percent,
+                        detail: &detail,
+                        bar_color: color,
+                    }
+                    .render(),
+                );
+                acc
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fill_percent() {
+        assert_eq!(fill_percent(0, 2000), 0);
+        assert_eq!(fill_percent(500, 2000), 25);
+        assert_eq!(fill_percent(2000, 2000), 100);
+        assert_eq!(fill_percent(3000, 2000), 100);
+        assert_eq!(fill_percent(-100, 2000), 0);
+        assert_eq!(fill_percent(0, 0), 0);
+        assert_eq!(fill_percent(100, 0), 100);
+        assert_eq!(fill_percent(100, -50), 100);
+    }
+
+    #[test]
+    fn test_hidden_calories_are_not_rendered() {
+        let progress = CalorieProgress {
+            calories: 1234,
+            goal: 5678,
+            hide_calories: true,
+        }
+        .render();
+        assert!(!progress.contains("1234"));
+        assert!(!progress.contains("5678"));
+        assert!(progress.contains("width: 21%"));
+    }
+
+    #[test]
+    fn test_over_goal() {
+        let progress = CalorieProgress {
+            calories: 2500,
+            goal: 2000,
+            hide_calories: false,
+        }
+        .render();
+        assert!(progress.contains("You are 500 calories over."));
+        assert!(progress.contains("width: 100%"));
+        assert!(progress.contains("bg-red-500"));
+    }
+
+    #[test]
+    fn test_macro_targets() {
+        let progress = MacroProgress {
+            protein_grams: 100,
+            carbohydrates_grams: 250,
+            fat_grams: 50,
+            targets: &MacroGrams {
+                protein: Some(150),
+                carbohydrates: Some(200),
+                fat: None,
+            },
+        }
+        .render();
+        assert!(progress.contains("100 / 150g, 50g left"));
+        assert!(progress.contains("250 / 200g, 50g over"));
+        assert!(progress.contains("50g (24% of calories)"));
+    }
+}
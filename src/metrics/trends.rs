@@ -0,0 +1,681 @@
+//! Daily totals over the last week, month, or quarter, along with some
+//! summary statistics and charts. Days are bucketed in the user's timezone,
+//! and today is left out, since it's not over yet. The smoothed weight trend
+//! is shown over the same dates, so that intake and weight can be compared.
+
+use crate::{
+    chrono_utils::start_of_date,
+    preferences::MacroGrams,
+    prelude::*,
+    weight::{get_weight_days, get_weight_unit, WeightChart, WeightDay},
+};
+use chrono::Days;
+use futures::join;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrendRange {
+    #[default]
+    Week,
+    Month,
+    Quarter,
+}
+impl TrendRange {
+    const ALL: [Self; 3] = [Self::Week, Self::Month, Self::Quarter];
+    fn days(self) -> u64 {
+        match self {
+            Self::Week => 7,
+            Self::Month => 30,
+            Self::Quarter => 90,
+        }
+    }
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Quarter => "quarter",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct DailyTotal {
+    calories: i32,
+    protein_grams: i32,
+    carbohydrates_grams: i32,
+    fat_grams: i32,
+}
+
+/// One day in the range. `total` is [None] if nothing was logged, which we
+/// treat differently from a day where the user ate nothing at all; these
+/// days are not counted in averages.
+#[derive(Debug, PartialEq)]
+struct Day {
+    date: NaiveDate,
+    total: Option<DailyTotal>,
+}
+
+struct EatenFood {
+    calories: i32,
+    protein_grams: i32,
+    carbohydrates_grams: i32,
+    fat_grams: i32,
+    eaten_at: DateTime<Utc>,
+}
+
+/// Every date in `first_day..first_day + days`, with food totals in the
+/// user's timezone. Food outside of the range is ignored.
+fn daily_totals(
+    food: &[EatenFood],
+    timezone: Tz,
+    first_day: NaiveDate,
+    days: u64,
+) -> Vec<Day> {
+    let totals = food.iter().fold(BTreeMap::new(), |mut acc, item| {
+        let date = item.eaten_at.with_timezone(&timezone).date_naive();
+        let total: &mut DailyTotal = acc.entry(date).or_default();
+        total.calories += item.calories;
+        total.protein_grams += item.protein_grams;
+        total.carbohydrates_grams += item.carbohydrates_grams;
+        total.fat_grams += item.fat_grams;
+        acc
+    });
+    first_day
+        .iter_days()
+        .take(days as usize)
+        .map(|date| Day {
+            date,
+            total: totals.get(&date).copied(),
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+struct TrendSummary {
+    days_logged: i32,
+    average: DailyTotal,
+    /// The logged days with the fewest and the most calories.
+    lowest: Option<(NaiveDate, i32)>,
+    highest: Option<(NaiveDate, i32)>,
+    /// Logged days at or under the user's caloric intake goal, if they have
+    /// one.
+    days_within_goal: Option<i32>,
+}
+
+fn summarize(days: &[Day], calorie_goal: Option<i32>) -> TrendSummary {
+    let logged: Vec<(NaiveDate, DailyTotal)> = days
+        .iter()
+        .filter_map(|day| Some((day.date, day.total?)))
+        .collect();
+    let days_logged = logged.len() as i32;
+    let sum = logged
+        .iter()
+        .fold(DailyTotal::default(), |mut acc, (_, t)| {
+            acc.calories += t.calories;
+            acc.protein_grams += t.protein_grams;
+            acc.carbohydrates_grams += t.carbohydrates_grams;
+            acc.fat_grams += t.fat_grams;
+            acc
+        });
+    let average = if days_logged == 0 {
+        DailyTotal::default()
+    } else {
+        DailyTotal {
+            calories: sum.calories / days_logged,
+            protein_grams: sum.protein_grams / days_logged,
+            carbohydrates_grams: sum.carbohydrates_grams / days_logged,
+            fat_grams: sum.fat_grams / days_logged,
+        }
+    };
+    let calories = logged.iter().map(|(date, t)| (*date, t.calories));
+    TrendSummary {
+        days_logged,
+        average,
+        lowest: calories.clone().min_by_key(|(_, calories)| *calories),
+        highest: calories.clone().max_by_key(|(_, calories)| *calories),
+        days_within_goal: calorie_goal.map(|goal| {
+            calories.filter(|(_, calories)| *calories <= goal).count() as i32
+        }),
+    }
+}
+
+/// Height of the chart's coordinate space. The SVG stretches to fill its
+/// container, so this is only meaningful relative to the bar width.
+const CHART_HEIGHT: i32 = 100;
+const BAR_WIDTH: i32 = 10;
+
+/// A bar chart with one bar per day, and an optional dashed line for the
+/// user's goal. Labels live in HTML around the SVG so that they don't get
+/// stretched along with it.
+struct BarChart<'a> {
+    title: &'a str,
+    unit: &'a str,
+    days: &'a [Day],
+    value: fn(&DailyTotal) -> i32,
+    goal: Option<i32>,
+    bar_color: &'a str,
+}
+impl Component for BarChart<'_> {
+    fn render(&self) -> String {
+        let title = self.title;
+        let unit = self.unit;
+        let bar_color = self.bar_color;
+        let max_value = self
+            .days
+            .iter()
+            .filter_map(|day| day.total.as_ref().map(self.value))
+            .chain(self.goal)
+            .max();
+        let max_label =
+            max_value.map_or(String::new(), |max| format!("{max} {unit}"));
+        let scale = |value: i32| {
+            (i64::from(value.max(0)) * i64::from(CHART_HEIGHT)
+                / i64::from(max_value.unwrap_or_default().max(1)))
+                as i32
+        };
+        let bars = self.days.iter().enumerate().fold(
+            String::new(),
+            |mut acc, (i, day)| {
+                if let Some(total) = &day.total {
+                    let value = (self.value)(total);
+                    let height = scale(value);
+                    let x = i as i32 * BAR_WIDTH + 1;
+                    let y = CHART_HEIGHT - height;
+                    let width = BAR_WIDTH - 2;
+                    let date = day.date.format("%b %e");
+                    acc.push_str(&format!(
+                        r#"<rect x="{x}" y="{y}" width="{width}" height="{height}" class="{bar_color}"><title>{date}: {value} {unit}</title></rect>"#
+                    ));
+                };
+                acc
+            },
+        );
+        let width = self.days.len() as i32 * BAR_WIDTH;
+        let goal_line = self.goal.map_or(String::new(), |goal| {
+            let y = CHART_HEIGHT - scale(goal);
+            format!(
+                r#"<line x1="0" x2="{width}" y1="{y}" y2="{y}" class="stroke-slate-500 dark:stroke-slate-300" stroke-width="1" stroke-dasharray="4 2" vector-effect="non-scaling-stroke"><title>Goal: {goal} {unit}</title></line>"#
+            )
+        });
+        let goal_legend = self.goal.map_or(String::new(), |goal| {
+            format!(r#"<span>Dashed line: goal of {goal} {unit}</span>"#)
+        });
+        let first = self
+            .days
+            .first()
+            .map_or(String::new(), |d| d.date.format("%b %e").to_string());
+        let last = self
+            .days
+            .last()
+            .map_or(String::new(), |d| d.date.format("%b %e").to_string());
+        format!(
+            r#"
+            <div class="flex flex-col gap-1">
+                <p class="flex justify-between text-sm">
+                    <span class="font-semibold">{title}</span>
+                    <span>{max_label}</span>
+                </p>
+                <svg
+                    viewBox="0 0 {width} {CHART_HEIGHT}"
+                    preserveAspectRatio="none"
+                    class="w-full h-32 bg-slate-100 dark:bg-slate-800 rounded"
+                    role="img"
+                    aria-label="{title} per day"
+                >
+                    {bars}
+                    {goal_line}
+                </svg>
+                <p class="flex justify-between text-xs">
+                    <span>{first}</span>
+                    {goal_legend}
+                    <span>{last}</span>
+                </p>
+            </div>
+            "#
+        )
+    }
+}
+
+struct RangePicker {
+    selected: TrendRange,
+}
+impl Component for RangePicker {
+    fn render(&self) -> String {
+        let trends = Route::Trends;
+        let links = TrendRange::ALL.iter().fold(String::new(), |mut acc, r| {
+            let range = r.as_str();
+            let days = r.days();
+            let color = if *r == self.selected {
+                "bg-emerald-300 dark:bg-emerald-600"
+            } else {
+                "bg-emerald-100 hover:bg-emerald-200 dark:bg-emerald-800
+                dark:hover:bg-emerald-700"
+            };
+            acc.push_str(&format!(
+                r#"
+                <a href="{trends}?range={range}">
+                    <button class="{color} rounded p-1">{days} days</button>
+                </a>
+                "#
+            ));
+            acc
+        });
+        format!(r#"<div class="flex gap-2">{links}</div>"#)
+    }
+}
+
+struct SummaryTable<'a> {
+    summary: &'a TrendSummary,
+    calorie_goal: Option<i32>,
+    hide_calories: bool,
+    range_days: u64,
+}
+impl Component for SummaryTable<'_> {
+    fn render(&self) -> String {
+        let s = self.summary;
+        let days_logged = s.days_logged;
+        let range_days = self.range_days;
+        let fmt_day = |day: Option<(NaiveDate, i32)>| {
+            day.map_or("-".to_string(), |(date, calories)| {
+                format!("{calories} calories on {}", date.format("%b %e"))
+            })
+        };
+        let calories = if self.hide_calories {
+            String::new()
+        } else {
+            let average = s.average.calories;
+            let lowest = fmt_day(s.lowest);
+            let highest = fmt_day(s.highest);
+            format!(
+                r#"
+                <p>Average: {average} calories</p>
+                <p>Lowest day: {lowest}</p>
+                <p>Highest day: {highest}</p>
+                "#
+            )
+        };
+        let adherence = match (s.days_within_goal, self.calorie_goal) {
+            (Some(within), Some(goal)) => {
+                let goal = if self.hide_calories {
+                    "your calorie goal".to_string()
+                } else {
+                    format!("your goal of {goal} calories")
+                };
+                format!(
+                    "<p>You were at or under {goal} on {within} of
+                    {days_logged} logged days.</p>"
+                )
+            }
+            _ => String::new(),
+        };
+        let protein = s.average.protein_grams;
+        let carbs = s.average.carbohydrates_grams;
+        let fat = s.average.fat_grams;
+        format!(
+            r#"
+            <div class="flex flex-col gap-1">
+                <p>You logged food on {days_logged} of the last {range_days}
+                days.</p>
+                {calories}
+                <p>Average macros: {protein}g protein, {carbs}g carbs,
+                {fat}g fat</p>
+                {adherence}
+            </div>
+            "#
+        )
+    }
+}
+
+/// The trend weight on the first day of the range which has one, and on the
+/// last day of the range.
+fn weight_change(days: &[WeightDay]) -> Option<(f64, f64)> {
+    let start = days.iter().find_map(|day| day.trend)?;
+    let end = days.last()?.trend?;
+    Some((start, end))
+}
+
+struct WeightSummary<'a> {
+    days: &'a [WeightDay],
+}
+impl Component for WeightSummary<'_> {
+    fn render(&self) -> String {
+        let weight = Route::Weight;
+        match weight_change(self.days) {
+            Some((start, end)) => {
+                let change = end - start;
+                format!(
+                    r#"
+                    <p>
+                        Your <a class="link" href="{weight}">trend weight</a>
+                        went from {start:.1} to {end:.1} ({change:+.1}).
+                    </p>
+                    "#
+                )
+            }
+            None => format!(
+                r#"
+                <p>
+                    <a class="link" href="{weight}">Log your weight</a> to
+                    compare it with what you've eaten.
+                </p>
+                "#
+            ),
+        }
+    }
+}
+
+struct TrendsPage<'a> {
+    range: TrendRange,
+    days: &'a [Day],
+    weight_days: &'a [WeightDay],
+    preferences: &'a UserPreference,
+    macro_targets: &'a MacroGrams,
+}
+impl Component for TrendsPage<'_> {
+    fn render(&self) -> String {
+        let home = Route::UserHome;
+        let picker = RangePicker {
+            selected: self.range,
+        }
+        .render();
+        let calorie_goal = self.preferences.caloric_intake_goal;
+        let summary = SummaryTable {
+            summary: &summarize(self.days, calorie_goal),
+            calorie_goal,
+            hide_calories: self.preferences.hide_calories,
+            range_days: self.range.days(),
+        }
+        .render();
+        let calorie_chart = if self.preferences.hide_calories {
+            String::new()
+        } else {
+            BarChart {
+                title: "Calories",
+                unit: "calories",
+                days: self.days,
+                value: |t| t.calories,
+                goal: calorie_goal,
+                bar_color: "fill-emerald-500",
+            }
+            .render()
+        };
+        let protein_chart = BarChart {
+            title: "Protein",
+            unit: "grams",
+            days: self.days,
+            value: |t| t.protein_grams,
+            goal: self.macro_targets.protein,
+            bar_color: "fill-sky-500",
+        }
+        .render();
+        let carb_chart = BarChart {
+            title: "Carbohydrates",
+            unit: "grams",
+            days: self.days,
+            value: |t| t.carbohydrates_grams,
+            goal: self.macro_targets.carbohydrates,
+            bar_color: "fill-amber-500",
+        }
+        .render();
+        let weight_summary = WeightSummary {
+            days: self.weight_days,
+        }
+        .render();
+        let weight_chart = WeightChart {
+            days: self.weight_days,
+        }
+        .render();
+        let fat_chart = BarChart {
+            title: "Fat",
+            unit: "grams",
+            days: self.days,
+            value: |t| t.fat_grams,
+            goal: self.macro_targets.fat,
+            bar_color: "fill-rose-500",
+        }
+        .render();
+        format!(
+            r#"
+            <div class="flex flex-col gap-4 md:w-[40rem]">
+                <a href="{home}">
+                    <button
+                        class="dark:bg-emerald-700 dark:hover:bg-emerald-800
+                        bg-emerald-100 hover:bg-emerald-200 p-1 rounded"
+                    >
+                        Back
+                    </button>
+                </a>
+                <h1 class="text-2xl font-extrabold">Trends</h1>
+                <p class="text-sm">
+                    Daily totals in your timezone, not including today.
+                </p>
+                {picker}
+                {summary}
+                {calorie_chart}
+                {protein_chart}
+                {carb_chart}
+                {fat_chart}
+                {weight_summary}
+                {weight_chart}
+            </div>
+            "#
+        )
+    }
+}
+
+async fn get_food_between(
+    db: &PgPool,
+    user_id: i32,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Aresult<Vec<EatenFood>> {
+    Ok(query_as!(
+        EatenFood,
+        "select
+            f.calories,
+            f.protein protein_grams,
+            f.carbohydrates carbohydrates_grams,
+            f.fat fat_grams,
+            fee.eaten_at
+        from food_eaten_event fee
+        join food f on fee.food_id = f.id
+        where
+            fee.user_id = $1
+            and f.user_id = $1
+            and fee.eaten_at >= $2
+            and fee.eaten_at < $3",
+        user_id,
+        start,
+        end
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+#[derive(Deserialize)]
+pub struct TrendsParams {
+    range: Option<TrendRange>,
+}
+
+pub async fn trends(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Query(TrendsParams { range }): Query<TrendsParams>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "trends")?;
+    let preferences = session.get_preferences(&db).await?;
+    let range = range.unwrap_or_default();
+    let timezone = preferences.timezone;
+    let today = utc_now().with_timezone(&timezone).date_naive();
+    let first_day = today
+        .checked_sub_days(Days::new(range.days()))
+        .ok_or(Error::msg("trend range starts before the dawn of time"))?;
+    let unit = get_weight_unit(&db, session.user_id).await?;
+    let (food, weight_days) = join![
+        get_food_between(
+            &db,
+            session.user_id,
+            start_of_date(first_day, timezone),
+            start_of_date(today, timezone),
+        ),
+        get_weight_days(
+            &db,
+            session.user_id,
+            unit,
+            timezone,
+            first_day,
+            range.days()
+        )
+    ];
+    let food = food?;
+    let weight_days = weight_days?;
+    let days = daily_totals(&food, timezone, first_day, range.days());
+    let macro_targets = preferences
+        .macro_targets
+        .to_grams(preferences.caloric_intake_goal);
+
+    Ok(Page {
+        title: "Trends",
+        children: &PageContainer {
+            children: &TrendsPage {
+                range,
+                days: &days,
+                weight_days: &weight_days,
+                preferences: &preferences,
+                macro_targets: &macro_targets,
+            },
+        },
+    }
+    .render())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn food(eaten_at: &str, calories: i32, protein_grams: i32) -> EatenFood {
+        EatenFood {
+            calories,
+            protein_grams,
+            carbohydrates_grams: 10,
+            fat_grams: 5,
+            eaten_at: DateTime::parse_from_rfc3339(eaten_at).unwrap().to_utc(),
+        }
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_daily_totals_use_user_timezone() {
+        let items = [
+            food("2024-06-27T12:00:00Z", 500, 20),
+            // 8PM on the 27th in New York.
+            food("2024-06-28T00:00:00Z", 700, 30),
+            food("2024-06-28T12:00:00Z", 1000, 50),
+        ];
+        let days =
+            daily_totals(&items, Tz::America__New_York, date("2024-06-26"), 3);
+        assert_eq!(
+            days,
+            vec![
+                Day {
+                    date: date("2024-06-26"),
+                    total: None
+                },
+                Day {
+                    date: date("2024-06-27"),
+                    total: Some(DailyTotal {
+                        calories: 1200,
+                        protein_grams: 50,
+                        carbohydrates_grams: 20,
+                        fat_grams: 10
+                    })
+                },
+                Day {
+                    date: date("2024-06-28"),
+                    total: Some(DailyTotal {
+                        calories: 1000,
+                        protein_grams: 50,
+                        carbohydrates_grams: 10,
+                        fat_grams: 5
+                    })
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summarize_skips_days_without_food() {
+        let items = [
+            food("2024-06-26T12:00:00Z", 1800, 100),
+            food("2024-06-27T12:00:00Z", 2400, 80),
+            food("2024-06-28T12:00:00Z", 1500, 120),
+        ];
+        let days = daily_totals(&items, Tz::UTC, date("2024-06-22"), 7);
+        let summary = summarize(&days, Some(2000));
+        assert_eq!(
+            summary,
+            TrendSummary {
+                days_logged: 3,
+                average: DailyTotal {
+                    calories: 1900,
+                    protein_grams: 100,
+                    carbohydrates_grams: 10,
+                    fat_grams: 5
+                },
+                lowest: Some((date("2024-06-28"), 1500)),
+                highest: Some((date("2024-06-27"), 2400)),
+                days_within_goal: Some(2)
+            }
+        );
+        assert_eq!(summarize(&days, None).days_within_goal, None);
+    }
+
+    #[test]
+    fn test_summarize_empty_range() {
+        let days = daily_totals(&[], Tz::UTC, date("2024-06-22"), 7);
+        let summary = summarize(&days, Some(2000));
+        assert_eq!(summary.days_logged, 0);
+        assert_eq!(summary.average, DailyTotal::default());
+        assert_eq!(summary.lowest, None);
+        assert_eq!(summary.days_within_goal, Some(0));
+    }
+
+    #[test]
+    fn test_bar_chart_scales_to_goal() {
+        let items = [food("2024-06-27T12:00:00Z", 1000, 50)];
+        let days = daily_totals(&items, Tz::UTC, date("2024-06-26"), 2);
+        let chart = BarChart {
+            title: "Calories",
+            unit: "calories",
+            days: &days,
+            value: |t| t.calories,
+            goal: Some(2000),
+            bar_color: "fill-emerald-500",
+        }
+        .render();
+        assert_eq!(chart.matches("<rect").count(), 1);
+        assert!(chart.contains(r#"x="11" y="50" width="8" height="50""#));
+        assert!(chart.contains(r#"y1="0" y2="0""#));
+        assert!(chart.contains("Jun 27: 1000 calories"));
+    }
+
+    #[test]
+    fn test_weight_change_starts_at_first_trend() {
+        let day = |d: &str, trend: Option<f64>| WeightDay {
+            date: date(d),
+            weight: None,
+            trend,
+        };
+        let days = [
+            day("2024-06-26", None),
+            day("2024-06-27", Some(180.0)),
+            day("2024-06-28", Some(179.5)),
+        ];
+        assert_eq!(weight_change(&days), Some((180.0, 179.5)));
+        assert_eq!(weight_change(&days[..1]), None);
+    }
+}
@@ -1,15 +1,22 @@
 //! Axum middlewares, modeled as async functions.
 
 use super::{
-    auth::Session, chrono_utils::utc_now, config, htmx, models::AppState,
+    auth::{
+        rate_limit::{self as attempts, LockoutNotice},
+        session_store::{self, SessionStatus},
+        Session,
+    },
+    chrono_utils::utc_now,
+    components::Component,
+    errors::ServerError,
+    htmx,
+    models::AppState,
     routes::Route,
 };
 #[cfg(feature = "stripe")]
-use super::{
-    errors::ServerError, models::IdCreatedAt, stripe::SubscriptionTypes,
-};
+use super::{models::IdCreatedAt, stripe::SubscriptionTypes};
 use axum::{
-    body::Body,
+    body::{to_bytes, Body},
     extract::State,
     http::{HeaderMap, HeaderValue, Request},
     middleware::Next,
@@ -64,14 +71,124 @@ pub async fn html_headers(request: Request<Body>, next: Next) -> Response {
     response
 }
 
+/// Checks the session cookie against the `session` table before any handler
+/// runs, so that handlers can keep using [Session::from_headers].
+///
+/// Revoked or expired sessions, along with legacy session cookies, are
+/// removed from the request, and the cookie is cleared.
+pub async fn sessions(
+    State(AppState { db, .. }): State<AppState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let user_agent = request
+        .headers()
+        .get("User-Agent")
+        .and_then(|ua| ua.to_str().ok())
+        .map(|ua| ua.to_owned());
+    let status =
+        session_store::check(&db, request.headers(), user_agent.as_deref())
+            .await;
+    match status {
+        Ok(SessionStatus::Missing | SessionStatus::Active) => {
+            next.run(request).await
+        }
+        Ok(SessionStatus::Revoked) => {
+            request.headers_mut().remove("Cookie");
+            let mut response = next.run(request).await;
+            let headers = response.headers_mut();
+            if !headers.contains_key("Set-Cookie") {
+                headers.insert(
+                    "Set-Cookie",
+                    HeaderValue::from_static("session=null; Path=/; HttpOnly"),
+                );
+            }
+            response
+        }
+        Err(e) => {
+            eprintln!("Error: could not check session :: {e}");
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Login and password reset forms are tiny; anything bigger is not a real
+/// submission.
+const FORM_BODY_LIMIT: usize = 16 * 1024;
+
+/// Locks out clients which keep failing at a form; see
+/// [crate::auth::rate_limit]. A request which doesn't end with a new session
+/// cookie or [attempts::AttemptSucceeded] counts as a failure, so every
+/// password reset request counts towards the limit, as does every failed
+/// login.
+pub async fn rate_limit(
+    State(AppState { db, .. }): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, FORM_BODY_LIMIT).await {
+        Ok(body) => body,
+        Err(_) => {
+            return ServerError::bad_request("form is too large", None)
+                .into_response()
+        }
+    };
+    // Forms for users who are already logged in don't ask who they are, but
+    // attempts still count against their account.
+    let identifier = attempts::form_identifier(&body).or_else(|| {
+        Session::from_headers(&parts.headers).map(|session| session.username)
+    });
+    let keys = attempts::keys(
+        attempts::client_ip(&parts).as_deref(),
+        identifier.as_deref(),
+    );
+    let result = async {
+        let Some(reservations) = attempts::reserve(&db, &keys).await? else {
+            let locked_until = attempts::locked_until(&db, &keys)
+                .await?
+                .unwrap_or_else(utc_now);
+            return Err(ServerError::too_many_requests(
+                "client is locked out",
+                LockoutNotice { locked_until }.render(),
+            ));
+        };
+        let response =
+            next.run(Request::from_parts(parts, Body::from(body))).await;
+        if response.headers().contains_key("Set-Cookie")
+            || response
+                .extensions()
+                .get::<attempts::AttemptSucceeded>()
+                .is_some()
+        {
+            attempts::refund(&db, &reservations).await?;
+            if let Some(identifier) = identifier {
+                attempts::clear(&db, &attempts::identifier_key(&identifier))
+                    .await?;
+            }
+        } else if response
+            .extensions()
+            .get::<attempts::PasswordAccepted>()
+            .is_some()
+        {
+            attempts::refund(&db, &reservations).await?;
+        }
+        Ok(response)
+    };
+    result.await.unwrap_or_else(|e| e.into_response())
+}
+
 /// This will validate the session from the request headers and redirect any
 /// unauthenticated users to the login route, allowing the creation of a
 /// router with protected routes for users only. Unfortunately, this work
 /// is not passed along to request handlers because I don't know how, so the
-/// session parsing work will be repeated, but these are JWT-style tokens, so
-/// validating the session at least does not require a database round trip. This
-/// middleware also logs the method, path, and username for authenticated
-/// requests.
+/// session parsing work will be repeated. Revocation was already checked by
+/// [sessions], so this doesn't need a database round trip. This middleware
+/// also logs the method, path, and username for authenticated requests.
 pub async fn auth(request: Request<Body>, next: Next) -> Response {
     let headers = request.headers();
     let session = Session::from_headers(headers);
@@ -88,24 +205,18 @@ pub async fn auth(request: Request<Body>, next: Next) -> Response {
     };
 
     if let Some(session) = session {
-        let token_age_days = utc_now()
-            .signed_duration_since(session.created_at)
-            .num_days();
-        if token_age_days < config::SESSION_EXPIRY_TIME_DAYS {
-            let start = utc_now().with_timezone(&Tz::US__Eastern);
-            let method = request.method().as_str().to_owned();
-            let uri = request.uri().path().to_owned();
-            let username = session.username;
-            let response = next.run(request).await;
-            let end = utc_now().with_timezone(&Tz::US__Eastern);
-            let duration = (end - start).num_milliseconds();
-            let stat = response.status();
-            println!("[{end}] {method} {uri} => {stat} in {duration}ms from {username}");
-            response
-        } else {
-            (response_headers(), Redirect::to(&Route::Login.to_string()))
-                .into_response()
-        }
+        let start = utc_now().with_timezone(&Tz::US__Eastern);
+        let method = request.method().as_str().to_owned();
+        let uri = request.uri().path().to_owned();
+        let username = session.username;
+        let response = next.run(request).await;
+        let end = utc_now().with_timezone(&Tz::US__Eastern);
+        let duration = (end - start).num_milliseconds();
+        let stat = response.status();
+        println!(
+            "[{end}] {method} {uri} => {stat} in {duration}ms from {username}"
+        );
+        response
     } else {
         (response_headers(), Redirect::to(&Route::Login.to_string()))
             .into_response()
@@ -127,7 +238,7 @@ pub async fn log(request: Request<Body>, next: Next) -> Response {
 
 #[cfg(feature = "stripe")]
 pub async fn narc_on_subscriptions(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     request: Request<Body>,
     next: Next,
 ) -> Response {
@@ -1,13 +1,15 @@
 //! Core data-models for the application.
 
-use super::stripe;
+use super::{count_chat::LlmBackend, stripe};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub struct AppState {
     pub db: PgPool,
+    pub llm: Arc<dyn LlmBackend>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,6 +17,8 @@ pub struct User {
     pub id: i32,
     pub username: String,
     pub email: String,
+    /// Set once the user follows the link in their verification email.
+    pub email_verified: bool,
     pub created_at: DateTime<Utc>,
     pub stripe_customer_id: String,
     pub stripe_subscription_type: stripe::SubscriptionTypes,
@@ -0,0 +1,94 @@
+//! Opt-in emails. These are never sent to anonymous users, since they don't
+//! have a real email address.
+
+use crate::prelude::*;
+use serde::Serialize;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct EmailPreferences {
+    /// Yesterday's totals, sent every morning.
+    pub daily_digest: bool,
+    /// The last week's totals, sent on Monday mornings.
+    pub weekly_digest: bool,
+    /// Send a reminder if nothing has been logged by this time of day.
+    pub reminder_time: Option<NaiveTime>,
+}
+
+/// Reminder times come from `<input type="time">`, which is an empty string
+/// if the reminder is turned off.
+pub fn parse_reminder_time(
+    value: &str,
+) -> Result<Option<NaiveTime>, &'static str> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map(Some)
+        .map_err(|_| "Reminder time cannot be parsed into a time of day.")
+}
+
+pub struct EmailPreferenceFields<'a> {
+    pub preferences: &'a EmailPreferences,
+    pub error: Option<&'a str>,
+}
+impl Component for EmailPreferenceFields<'_> {
+    fn render(&self) -> String {
+        let checked = |value: bool| if value { "checked" } else { "" };
+        let daily = checked(self.preferences.daily_digest);
+        let weekly = checked(self.preferences.weekly_digest);
+        let reminder_time = self
+            .preferences
+            .reminder_time
+            .map_or("".to_string(), |t| t.format("%H:%M").to_string());
+        let error = self.error.map_or("".to_string(), |err| {
+            format!(r#"<p class="text-red-500 italic text-sm">{err}</p>"#)
+        });
+        format!(
+            r#"
+            <div class="rounded my-3 p-3 border-2 border-black">
+                <h2 class="text-lg">Emails</h2>
+                <details class="text-xs">
+                    <summary>Learn more</summary>
+                    <p>
+                        (Optional settings) the daily summary arrives each
+                        morning with yesterday's totals, and your goal for
+                        the day. The weekly report arrives on Monday mornings,
+                        and covers the last 7 days. If you set a reminder
+                        time, we'll send you a reminder on days where you
+                        haven't logged anything by then. Clear the reminder
+                        time to stop reminders. Emails are only sent to
+                        registered accounts.
+                    </p>
+                </details>
+                <label class="flex items-center gap-2">
+                    <input
+                        class="w-6 h-6"
+                        type="checkbox"
+                        name="daily_digest"
+                        {daily}
+                    />
+                    Daily summary
+                </label>
+                <label class="flex items-center gap-2">
+                    <input
+                        class="w-6 h-6"
+                        type="checkbox"
+                        name="weekly_digest"
+                        {weekly}
+                    />
+                    Weekly report
+                </label>
+                <label class="flex items-center gap-2">
+                    Remind me if I haven't logged anything by
+                    <input
+                        type="time"
+                        name="reminder_time"
+                        value="{reminder_time}"
+                    />
+                </label>
+                {error}
+            </div>
+            "#
+        )
+    }
+}
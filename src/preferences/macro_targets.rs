@@ -0,0 +1,152 @@
+//! Optional daily targets for protein, carbohydrates, and fat. Targets can be
+//! set in grams, or as a percentage of the user's calorie goal.
+
+use crate::prelude::*;
+use serde::Serialize;
+
+pub const CALORIES_PER_GRAM_PROTEIN: i32 = 4;
+pub const CALORIES_PER_GRAM_CARBOHYDRATES: i32 = 4;
+pub const CALORIES_PER_GRAM_FAT: i32 = 9;
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MacroTargetUnit {
+    #[default]
+    Grams,
+    Percent,
+}
+
+impl MacroTargetUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Grams => "grams",
+            Self::Percent => "percent",
+        }
+    }
+    pub fn parse(value: &str) -> Aresult<Self> {
+        match value {
+            "grams" => Ok(Self::Grams),
+            "percent" => Ok(Self::Percent),
+            other => Err(Error::msg(format!("unknown macro unit {other}"))),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MacroTargets {
+    pub unit: MacroTargetUnit,
+    pub protein: Option<i32>,
+    pub carbohydrates: Option<i32>,
+    pub fat: Option<i32>,
+}
+
+/// Daily macro targets, in grams.
+#[derive(Debug, PartialEq)]
+pub struct MacroGrams {
+    pub protein: Option<i32>,
+    pub carbohydrates: Option<i32>,
+    pub fat: Option<i32>,
+}
+
+impl MacroTargets {
+    pub fn is_empty(&self) -> bool {
+        self.protein.is_none()
+            && self.carbohydrates.is_none()
+            && self.fat.is_none()
+    }
+    /// Percentage targets are converted to grams using `calorie_goal`, which
+    /// should be the user's current (balanced) goal. Without a calorie goal,
+    /// percentage targets are meaningless, and will be [None].
+    pub fn to_grams(self, calorie_goal: Option<i32>) -> MacroGrams {
+        match self.unit {
+            MacroTargetUnit::Grams => MacroGrams {
+                protein: self.protein,
+                carbohydrates: self.carbohydrates,
+                fat: self.fat,
+            },
+            MacroTargetUnit::Percent => {
+                let convert = |percent: Option<i32>, calories_per_gram: i32| {
+                    let grams = i64::from(calorie_goal?) * i64::from(percent?)
+                        / 100
+                        / i64::from(calories_per_gram);
+                    i32::try_from(grams).ok()
+                };
+                MacroGrams {
+                    protein: convert(self.protein, CALORIES_PER_GRAM_PROTEIN),
+                    carbohydrates: convert(
+                        self.carbohydrates,
+                        CALORIES_PER_GRAM_CARBOHYDRATES,
+                    ),
+                    fat: convert(self.fat, CALORIES_PER_GRAM_FAT),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gram_targets() {
+        let targets = MacroTargets {
+            unit: MacroTargetUnit::Grams,
+            protein: Some(150),
+            carbohydrates: None,
+            fat: Some(60),
+        };
+        assert_eq!(
+            targets.to_grams(None),
+            MacroGrams {
+                protein: Some(150),
+                carbohydrates: None,
+                fat: Some(60)
+            }
+        );
+    }
+
+    #[test]
+    fn test_percent_targets() {
+        let targets = MacroTargets {
+            unit: MacroTargetUnit::Percent,
+            protein: Some(30),
+            carbohydrates: Some(40),
+            fat: Some(30),
+        };
+        assert_eq!(
+            targets.to_grams(Some(2000)),
+            MacroGrams {
+                protein: Some(150),
+                carbohydrates: Some(200),
+                fat: Some(66)
+            }
+        );
+        assert_eq!(
+            targets.to_grams(None),
+            MacroGrams {
+                protein: None,
+                carbohydrates: None,
+                fat: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_percent_targets_do_not_overflow() {
+        let targets = MacroTargets {
+            unit: MacroTargetUnit::Percent,
+            protein: Some(i32::MAX),
+            carbohydrates: Some(100),
+            fat: None,
+        };
+        assert_eq!(
+            targets.to_grams(Some(i32::MAX)),
+            MacroGrams {
+                protein: None,
+                carbohydrates: Some(i32::MAX / 4),
+                fat: None
+            }
+        );
+    }
+}
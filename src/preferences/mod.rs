@@ -1,4 +1,11 @@
+mod email;
+mod macro_targets;
 mod user;
+pub use email::EmailPreferences;
+pub use macro_targets::{
+    MacroGrams, CALORIES_PER_GRAM_CARBOHYDRATES, CALORIES_PER_GRAM_FAT,
+    CALORIES_PER_GRAM_PROTEIN,
+};
 pub use user::{
     get_user_preference, save_user_preference, user_preference_controller,
     UserPreference,
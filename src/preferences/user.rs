@@ -1,9 +1,17 @@
 //! User preferences
 
-use crate::{components::Saved, prelude::*};
+use super::{
+    email::{parse_reminder_time, EmailPreferenceFields, EmailPreferences},
+    macro_targets::{MacroTargetUnit, MacroTargets},
+};
+use crate::{
+    components::Saved, config, delete_account::DeleteAccount,
+    export::ExportData, prelude::*, weight::TdeeEstimatePlaceholder,
+};
 use axum::http::Method;
 use chrono_tz::TZ_VARIANTS;
 use serde::Serialize;
+use std::sync::LazyLock;
 
 #[derive(Copy, Clone, Serialize, Debug)]
 pub struct UserPreference {
@@ -19,6 +27,8 @@ pub struct UserPreference {
     /// blank form field on the preferences page.
     pub calorie_balancing_min_calories: Option<i32>,
     pub hide_calories: bool,
+    pub macro_targets: MacroTargets,
+    pub email: EmailPreferences,
 }
 
 impl Default for UserPreference {
@@ -30,6 +40,8 @@ impl Default for UserPreference {
             calorie_balancing_max_calories: None,
             calorie_balancing_min_calories: None,
             hide_calories: false,
+            macro_targets: MacroTargets::default(),
+            email: EmailPreferences::default(),
         }
     }
 }
@@ -125,6 +137,16 @@ impl Component for UserPreferenceForm<'_> {
             .calorie_balancing_max_calories
             .map(|v| v.to_string())
             .unwrap_or("".into());
+        let macro_targets = MacroTargetFields {
+            targets: &self.preferences.macro_targets,
+            error: self.get_field_validation_err("macro_targets"),
+        }
+        .render();
+        let email = EmailPreferenceFields {
+            preferences: &self.preferences.email,
+            error: self.get_field_validation_err("reminder_time"),
+        }
+        .render();
         let script = include_str!("./interactive_checkbox.js");
         let intake_goal_err = if let Some(err) =
             self.get_field_validation_err("caloric_intake_goal")
@@ -273,6 +295,8 @@ impl Component for UserPreferenceForm<'_> {
                             value="{max_calories}"
                         />
                     </div>
+                    {macro_targets}
+                    {email}
                     <button class="
                         bg-emerald-100 
                         hover:bg-emerald-200 
@@ -299,6 +323,124 @@ impl Component for UserPreferenceForm<'_> {
     }
 }
 
+struct MacroTargetFields<'a> {
+    targets: &'a MacroTargets,
+    error: Option<&'a str>,
+}
+impl Component for MacroTargetFields<'_> {
+    fn render(&self) -> String {
+        let value = |target: Option<i32>| {
+            target.map_or("".to_string(), |t| t.to_string())
+        };
+        let protein = value(self.targets.protein);
+        let carbs = value(self.targets.carbohydrates);
+        let fat = value(self.targets.fat);
+        let (grams_checked, percent_checked) = match self.targets.unit {
+            MacroTargetUnit::Grams => ("checked", ""),
+            MacroTargetUnit::Percent => ("", "checked"),
+        };
+        let grams = MacroTargetUnit::Grams.as_str();
+        let percent = MacroTargetUnit::Percent.as_str();
+        let error = self.error.map_or("".to_string(), |err| {
+            format!(r#"<p class="text-red-500 italic text-sm">{err}</p>"#)
+        });
+        format!(
+            r#"
+            <div class="rounded my-3 p-3 border-2 border-black">
+                <h2 class="text-lg">Macro Targets</h2>
+                <details class="text-xs">
+                    <summary>Learn more</summary>
+                    <p>
+                        (Optional settings) set a daily target for any of your
+                        macros, either in grams, or as a percentage of your
+                        calorie goal. Percentages require a caloric intake
+                        goal, and will follow your balanced goal if calorie
+                        balancing is enabled. With calorie balancing, any
+                        protein you fall short on is also carried forward to
+                        the next day.
+                    </p>
+                </details>
+                {error}
+                <div class="flex gap-3">
+                    <label>
+                        <input
+                            type="radio"
+                            name="macro_target_unit"
+                            value="{grams}"
+                            {grams_checked}
+                        />
+                        Grams
+                    </label>
+                    <label>
+                        <input
+                            type="radio"
+                            name="macro_target_unit"
+                            value="{percent}"
+                            {percent_checked}
+                        />
+                        Percent
+                    </label>
+                </div>
+                <label class="block" for="protein_target">Protein</label>
+                <input
+                    type="number"
+                    id="protein_target"
+                    name="protein_target"
+                    min="0"
+                    value="{protein}"
+                />
+                <label class="block" for="carbohydrates_target">
+                    Carbohydrates
+                </label>
+                <input
+                    type="number"
+                    id="carbohydrates_target"
+                    name="carbohydrates_target"
+                    min="0"
+                    value="{carbs}"
+                />
+                <label class="block" for="fat_target">Fat</label>
+                <input
+                    type="number"
+                    id="fat_target"
+                    name="fat_target"
+                    min="0"
+                    value="{fat}"
+                />
+            </div>
+            "#
+        )
+    }
+}
+
+/// The form is swapped out when it's submitted, so anything else on the
+/// preferences page lives outside of it.
+struct UserPreferencePage {
+    preferences: UserPreference,
+}
+impl Component for UserPreferencePage {
+    fn render(&self) -> String {
+        let form = UserPreferenceForm {
+            preferences: self.preferences,
+            field_validation_error: None,
+        }
+        .render();
+        let tdee = TdeeEstimatePlaceholder.render();
+        let export = ExportData.render();
+        let delete_account = DeleteAccount.render();
+        format!(
+            r#"
+            <div class="flex flex-col items-center">
+                {form}
+                {tdee}
+                {export}
+                {delete_account}
+            </div>
+            "#
+        )
+    }
+}
+
 struct SavedPreference {
     preferences: UserPreference,
 }
@@ -333,6 +475,13 @@ pub async fn get_user_preference(
         calorie_balancing_max_calories: Option<i32>,
         calorie_balancing_min_calories: Option<i32>,
         hide_calories: bool,
+        macro_target_unit: String,
+        protein_target: Option<i32>,
+        carbohydrates_target: Option<i32>,
+        fat_target: Option<i32>,
+        daily_digest_enabled: bool,
+        weekly_digest_enabled: bool,
+        reminder_time: Option<NaiveTime>,
     }
     let pref = query_as!(
         Qres,
@@ -342,7 +491,14 @@ pub async fn get_user_preference(
             calorie_balancing_max_calories,
             calorie_balancing_min_calories,
             timezone,
-            hide_calories
+            hide_calories,
+            macro_target_unit,
+            protein_target,
+            carbohydrates_target,
+            fat_target,
+            daily_digest_enabled,
+            weekly_digest_enabled,
+            reminder_time
         from user_preference
         where user_id = $1",
         user_id
@@ -361,6 +517,17 @@ pub async fn get_user_preference(
             })?,
             caloric_intake_goal: pref.caloric_intake_goal,
             hide_calories: pref.hide_calories,
+            macro_targets: MacroTargets {
+                unit: MacroTargetUnit::parse(&pref.macro_target_unit)?,
+                protein: pref.protein_target,
+                carbohydrates: pref.carbohydrates_target,
+                fat: pref.fat_target,
+            },
+            email: EmailPreferences {
+                daily_digest: pref.daily_digest_enabled,
+                weekly_digest: pref.weekly_digest_enabled,
+                reminder_time: pref.reminder_time,
+            },
         })),
         None => Ok(None),
     }
@@ -380,8 +547,17 @@ pub async fn save_user_preference(
             calorie_balancing_enabled,
             calorie_balancing_min_calories,
             calorie_balancing_max_calories,
-            hide_calories
-        ) values ($1, $2, $3, $4, $5, $6, $7)
+            hide_calories,
+            macro_target_unit,
+            protein_target,
+            carbohydrates_target,
+            fat_target,
+            daily_digest_enabled,
+            weekly_digest_enabled,
+            reminder_time
+        ) values (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14
+        )
         on conflict (user_id)
         do update set
             timezone = $2,
@@ -389,7 +565,14 @@ pub async fn save_user_preference(
             calorie_balancing_enabled = $4,
             calorie_balancing_min_calories = $5,
             calorie_balancing_max_calories = $6,
-            hide_calories = $7
+            hide_calories = $7,
+            macro_target_unit = $8,
+            protein_target = $9,
+            carbohydrates_target = $10,
+            fat_target = $11,
+            daily_digest_enabled = $12,
+            weekly_digest_enabled = $13,
+            reminder_time = $14
         ",
         user_id,
         preference.timezone.to_string(),
@@ -397,7 +580,14 @@ pub async fn save_user_preference(
         preference.calorie_balancing_enabled,
         preference.calorie_balancing_min_calories,
         preference.calorie_balancing_max_calories,
-        preference.hide_calories
+        preference.hide_calories,
+        preference.macro_targets.unit.as_str(),
+        preference.macro_targets.protein,
+        preference.macro_targets.carbohydrates,
+        preference.macro_targets.fat,
+        preference.email.daily_digest,
+        preference.email.weekly_digest,
+        preference.email.reminder_time
     )
     .execute(db)
     .await?;
@@ -452,7 +642,60 @@ pub struct UserPreferencePayload {
     hide_calories: Option<String>,
     calorie_balancing_min_calories: Option<String>,
     calorie_balancing_max_calories: Option<String>,
+    #[serde(default)]
+    macro_target_unit: MacroTargetUnit,
+    /// Macro targets are empty strings if unset.
+    #[serde(default)]
+    protein_target: String,
+    #[serde(default)]
+    carbohydrates_target: String,
+    #[serde(default)]
+    fat_target: String,
+    /// Checkboxes, like above.
+    daily_digest: Option<String>,
+    weekly_digest: Option<String>,
+    /// Empty string if the reminder is turned off.
+    #[serde(default)]
+    reminder_time: String,
 }
+
+/// Validation errors are `&'static str`, so messages which mention a limit
+/// from [config] are formatted once.
+static INTAKE_GOAL_TOO_HIGH: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "Caloric intake goal cannot be more than {} calories.",
+        config::MAX_CALORIC_INTAKE_GOAL
+    )
+});
+static MACRO_TARGET_TOO_HIGH: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "Macro targets cannot be more than {} grams.",
+        config::MAX_MACRO_TARGET_GRAMS
+    )
+});
+
+/// Percentage targets are checked together, in
+/// [UserPreferencePayload::check_macro_targets].
+fn parse_macro_target(
+    value: &str,
+    unit: MacroTargetUnit,
+) -> Result<Option<i32>, &'static str> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.parse::<i32>() {
+        Ok(val) if val < 0 => Err("Macro targets cannot be negative."),
+        Ok(val)
+            if unit == MacroTargetUnit::Grams
+                && val > config::MAX_MACRO_TARGET_GRAMS =>
+        {
+            Err(MACRO_TARGET_TOO_HIGH.as_str())
+        }
+        Ok(val) => Ok(Some(val)),
+        Err(_) => Err("Macro targets cannot be parsed into a number."),
+    }
+}
+
 impl UserPreferencePayload {
     fn get_intake_goal(&self) -> Result<Option<i32>, &'static str> {
         if self.caloric_intake_goal.is_empty() {
@@ -464,9 +707,14 @@ impl UserPreferencePayload {
                 Ok(None)
             }
         } else {
-            Ok(Some(self.caloric_intake_goal.parse().map_err(|_| {
+            let goal = self.caloric_intake_goal.parse().map_err(|_| {
                 "Caloric intake cannot be parsed into a number."
-            })?))
+            })?;
+            if goal > config::MAX_CALORIC_INTAKE_GOAL {
+                Err(INTAKE_GOAL_TOO_HIGH.as_str())
+            } else {
+                Ok(Some(goal))
+            }
         }
     }
     fn get_calorie_balancing_enabled(&self) -> bool {
@@ -475,6 +723,13 @@ impl UserPreferencePayload {
     fn get_hide_calories(&self) -> bool {
         self.hide_calories.is_some()
     }
+    fn get_email_preferences(&self) -> Result<EmailPreferences, &'static str> {
+        Ok(EmailPreferences {
+            daily_digest: self.daily_digest.is_some(),
+            weekly_digest: self.weekly_digest.is_some(),
+            reminder_time: parse_reminder_time(&self.reminder_time)?,
+        })
+    }
     fn get_calorie_balancing_min_calories(
         &self,
     ) -> CalorieBalancingLimitResult {
@@ -542,6 +797,36 @@ impl UserPreferencePayload {
             Ok(())
         }
     }
+    fn check_macro_targets(&self) -> Result<MacroTargets, &'static str> {
+        let unit = self.macro_target_unit;
+        let targets = MacroTargets {
+            unit,
+            protein: parse_macro_target(&self.protein_target, unit)?,
+            carbohydrates: parse_macro_target(
+                &self.carbohydrates_target,
+                unit,
+            )?,
+            fat: parse_macro_target(&self.fat_target, unit)?,
+        };
+        if targets.unit == MacroTargetUnit::Percent && !targets.is_empty() {
+            if !matches!(self.get_intake_goal(), Ok(Some(_))) {
+                return Err(
+                    "Set a caloric intake goal if you would like to use percentage macro targets.",
+                );
+            }
+            let total = [targets.protein, targets.carbohydrates, targets.fat]
+                .into_iter()
+                .flatten()
+                .map(i64::from)
+                .sum::<i64>();
+            if total > 100 {
+                return Err(
+                    "Macro percentages cannot add up to more than 100%.",
+                );
+            }
+        }
+        Ok(targets)
+    }
     fn get_errors(&self) -> Vec<(&'static str, &'static str)> {
         let mut errs = Vec::new();
         if let Err(e) = self.get_intake_goal() {
@@ -560,6 +845,12 @@ impl UserPreferencePayload {
         if let Err(e) = self.check_calorie_preferences() {
             errs.push(("calorie_preferences", e))
         };
+        if let Err(e) = self.check_macro_targets() {
+            errs.push(("macro_targets", e))
+        };
+        if let Err(e) = self.get_email_preferences() {
+            errs.push(("reminder_time", e))
+        };
         errs
     }
     /// Produce the best approximation of UserPreference from the unvalidated
@@ -588,12 +879,36 @@ impl UserPreferencePayload {
                 .and_then(|v| v.parse().ok())
                 .or(existing_preferences.calorie_balancing_min_calories),
             hide_calories: self.get_hide_calories(),
+            macro_targets: MacroTargets {
+                unit: self.macro_target_unit,
+                protein: parse_macro_target(
+                    &self.protein_target,
+                    self.macro_target_unit,
+                )
+                .unwrap_or(existing_preferences.macro_targets.protein),
+                carbohydrates: parse_macro_target(
+                    &self.carbohydrates_target,
+                    self.macro_target_unit,
+                )
+                .unwrap_or(existing_preferences.macro_targets.carbohydrates),
+                fat: parse_macro_target(
+                    &self.fat_target,
+                    self.macro_target_unit,
+                )
+                .unwrap_or(existing_preferences.macro_targets.fat),
+            },
+            email: EmailPreferences {
+                daily_digest: self.daily_digest.is_some(),
+                weekly_digest: self.weekly_digest.is_some(),
+                reminder_time: parse_reminder_time(&self.reminder_time)
+                    .unwrap_or(existing_preferences.email.reminder_time),
+            },
         }
     }
 }
 
 pub async fn user_preference_controller(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     method: Method,
     headers: HeaderMap,
     preferences: Option<Form<UserPreferencePayload>>,
@@ -606,10 +921,7 @@ pub async fn user_preference_controller(
             Ok(Page {
                 title: "User Preferences",
                 children: &PageContainer {
-                    children: &UserPreferenceForm {
-                        preferences,
-                        field_validation_error: None,
-                    },
+                    children: &UserPreferencePage { preferences },
                 },
             }
             .render())
@@ -626,8 +938,24 @@ pub async fn user_preference_controller(
                     existing_preferences.calorie_balancing_max_calories,
                 );
                 let calorie_prefs_ok = pref.check_calorie_preferences();
-                match (intake, min_cals, max_cals, calorie_prefs_ok) {
-                    (Ok(intake), Ok(min), Ok(max), Ok(_calorie_prefs)) => {
+                let macro_targets = pref.check_macro_targets();
+                let email = pref.get_email_preferences();
+                match (
+                    intake,
+                    min_cals,
+                    max_cals,
+                    calorie_prefs_ok,
+                    macro_targets,
+                    email,
+                ) {
+                    (
+                        Ok(intake),
+                        Ok(min),
+                        Ok(max),
+                        Ok(_calorie_prefs),
+                        Ok(macro_targets),
+                        Ok(email),
+                    ) => {
                         let pref = UserPreference {
                             timezone: pref.timezone,
                             caloric_intake_goal: intake,
@@ -636,6 +964,8 @@ pub async fn user_preference_controller(
                             calorie_balancing_max_calories: max,
                             calorie_balancing_min_calories: min,
                             hide_calories: pref.get_hide_calories(),
+                            macro_targets,
+                            email,
                         };
                         save_user_preference(&db, session.user_id, &pref)
                             .await?;
@@ -657,3 +987,122 @@ pub async fn user_preference_controller(
         _ => Err(ServerError::method_not_allowed()),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn payload(
+        caloric_intake_goal: &str,
+        macro_target_unit: MacroTargetUnit,
+        [protein, carbohydrates, fat]: [&str; 3],
+    ) -> UserPreferencePayload {
+        UserPreferencePayload {
+            timezone: Tz::UTC,
+            caloric_intake_goal: caloric_intake_goal.into(),
+            calorie_balancing_enabled: None,
+            hide_calories: None,
+            calorie_balancing_min_calories: None,
+            calorie_balancing_max_calories: None,
+            macro_target_unit,
+            protein_target: protein.into(),
+            carbohydrates_target: carbohydrates.into(),
+            fat_target: fat.into(),
+            daily_digest: None,
+            weekly_digest: None,
+            reminder_time: "".into(),
+        }
+    }
+
+    #[test]
+    fn test_gram_targets() {
+        let targets = payload("", MacroTargetUnit::Grams, ["150", "", "60"])
+            .check_macro_targets()
+            .unwrap();
+        assert_eq!(targets.protein, Some(150));
+        assert_eq!(targets.carbohydrates, None);
+        assert_eq!(targets.fat, Some(60));
+        // Gram targets don't depend on the calorie goal, and don't need to
+        // add up to anything.
+        assert!(payload("", MacroTargetUnit::Grams, ["300", "300", "300"])
+            .check_macro_targets()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_targets() {
+        assert!(payload("", MacroTargetUnit::Grams, ["-1", "", ""])
+            .check_macro_targets()
+            .is_err());
+        assert!(payload("", MacroTargetUnit::Grams, ["lots", "", ""])
+            .check_macro_targets()
+            .is_err());
+        assert!(payload("", MacroTargetUnit::Grams, ["99999999999", "", ""])
+            .check_macro_targets()
+            .is_err());
+    }
+
+    #[test]
+    fn test_percent_targets_need_a_calorie_goal() {
+        assert!(payload("", MacroTargetUnit::Percent, ["30", "40", "30"])
+            .check_macro_targets()
+            .is_err());
+        assert!(
+            payload("2000", MacroTargetUnit::Percent, ["30", "40", "30"])
+                .check_macro_targets()
+                .is_ok()
+        );
+        // Empty percentage targets are fine without a goal.
+        assert!(payload("", MacroTargetUnit::Percent, ["", "", ""])
+            .check_macro_targets()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_percent_targets_cannot_exceed_100() {
+        assert!(
+            payload("2000", MacroTargetUnit::Percent, ["40", "40", "21"])
+                .check_macro_targets()
+                .is_err()
+        );
+        assert!(payload(
+            "2000",
+            MacroTargetUnit::Percent,
+            ["2147483647", "2147483647", ""]
+        )
+        .check_macro_targets()
+        .is_err());
+    }
+
+    #[test]
+    fn test_intake_goal_upper_bound() {
+        let goal = |goal: &str| {
+            payload(goal, MacroTargetUnit::Grams, ["", "", ""])
+                .get_intake_goal()
+        };
+        assert_eq!(goal("2000"), Ok(Some(2000)));
+        assert_eq!(goal("20000"), Ok(Some(20_000)));
+        assert!(goal("20001").is_err());
+        assert!(goal("2147483647").is_err());
+        assert_eq!(goal(""), Ok(None));
+        assert_eq!(
+            goal("20001"),
+            Err("Caloric intake goal cannot be more than 20000 calories.")
+        );
+    }
+
+    #[test]
+    fn test_gram_target_upper_bound() {
+        let protein = |target: &str| {
+            payload("", MacroTargetUnit::Grams, [target, "", ""])
+                .check_macro_targets()
+                .map(|targets| targets.protein)
+        };
+        assert_eq!(protein("2000"), Ok(Some(2000)));
+        assert_eq!(
+            protein("2001"),
+            Err("Macro targets cannot be more than 2000 grams.")
+        );
+        assert!(protein("2000000000").is_err());
+    }
+}
@@ -1,8 +1,9 @@
 //! All possible routes with their params are defined in a big enum.
 
 use super::{
-    auth, balancing, blog, controllers, count_chat, legal, metrics, middleware,
-    models, preferences, stripe,
+    admin, auth, balancing, blog, controllers, count_chat, delete_account,
+    digest, export, legal, metrics, middleware, models, preferences, stripe,
+    weight,
 };
 use axum::{
     middleware::{from_fn, from_fn_with_state},
@@ -28,7 +29,18 @@ use axum::{
 #[derive(Debug)]
 pub enum Route {
     About,
+    /// Sets the user's calorie goal to the one suggested by the maintenance
+    /// calorie estimate.
+    AcceptSuggestedGoal,
+    /// Shown after a user deletes their account.
+    AccountDeleted,
     AddFoodToToday(Option<i32>),
+    /// Log every food in a saved meal to the current day.
+    AddMealToToday(Option<i32>),
+    /// OpenAI token usage and estimated costs; administrators only.
+    AdminUsage,
+    /// Overrides the default token quotas for one user; administrators only.
+    AdminUsageQuota,
     BalancingCheckpoints,
     BalancingCreateCheckpoint,
     BalancingDeleteCheckpoint,
@@ -36,11 +48,27 @@ pub enum Route {
     BlogCommentSubmission,
     BlogPostList,
     BlogPost(Option<i32>),
+    /// Receives the user's answer to a follow-up question from the LLM.
+    ChatFollowUp,
     ChatForm,
+    /// `GET` returns the confirmation form, and `POST` deletes the user's
+    /// account and all of their data.
+    DeleteAccount,
     DeleteComment(Option<i32>),
     DeleteFood(Option<i32>),
+    DeleteWeight(Option<i32>),
+    /// Lists the user's active sessions.
+    Devices,
     DisplayMacros,
+    /// `GET` returns a form to edit a food eaten event, and `POST` saves it.
+    EditFood(Option<i32>),
+    /// Downloads the user's food log as CSV.
+    ExportCsv,
+    /// Downloads all of the user's data as JSON.
+    ExportJson,
     Favicon,
+    /// Renders the card for one candidate from a multi-item chat.
+    FoodCandidate,
     /// This is just a route which, when visited, will trigger the backend
     /// to hit the stripe API and create a customer portal session, then
     /// redirect the user to the customer portal URL. This allows us to
@@ -50,10 +78,20 @@ pub enum Route {
     GotoStripePortal,
     HandleChat,
     Htmx,
+    /// `GET` returns the import page, and `POST` saves every valid row of
+    /// the submitted CSV.
+    ImportFood,
+    /// Parses a CSV import and renders a preview of it.
+    ImportFoodPreview,
     InitAnon(auth::InitAnonNextRoute),
     ListFood,
     Login,
+    /// The second step of logging in, for users with two-factor
+    /// authentication.
+    LoginTwoFactor,
     Logout,
+    /// Receives the quick-entry weight form.
+    LogWeight,
     PasswordReset,
     PasswordResetSecret(Option<String>),
     Ping,
@@ -63,9 +101,28 @@ pub enum Route {
     PreviousDayFood,
     PrivacyPolicy,
     Register,
+    /// Logs out of every session, including the current one.
+    RevokeAllSessions,
+    /// Logs out of one session, from the devices page.
+    RevokeSession(Option<String>),
+    /// Sends another verification email, at most once every
+    /// [crate::config::VERIFICATION_RESEND_COOLDOWN_MINUTES].
+    ResendVerificationEmail,
     RobotsTxt,
     Root,
+    /// Saves every remaining candidate from a multi-item chat, in one
+    /// transaction.
+    SaveAllFood,
     SaveFood,
+    /// Saves one of several candidates from a multi-item chat.
+    SaveFoodCandidate,
+    /// View (`GET`), rename (`POST`), or delete (`DELETE`) a saved meal.
+    SavedMeal(Option<i32>),
+    /// Add (`POST`) or remove (`DELETE`) food from a saved meal.
+    SavedMealFood(Option<i32>),
+    SavedMeals,
+    /// Renders the card for a single food eaten event.
+    ShowFood(Option<i32>),
     StaticAppleIcon,
     StaticLargeIcon,
     StaticManifest,
@@ -87,9 +144,33 @@ pub enum Route {
     /// won't have any subscription to manage -- they'll only be able to update
     /// billing info and payment method details.
     SubscriptionTrialEnded,
+    /// `GET` returns the maintenance calorie estimate, and `POST` saves the
+    /// user's target rate of weight change before returning it.
+    TdeeEstimate,
     TermsOfService,
+    /// Daily totals, averages, and charts over the last 7, 30, or 90 days.
+    Trends,
+    /// `GET` returns the two-factor authentication settings, and `POST`
+    /// starts enrollment with a new secret.
+    TwoFactor,
+    /// Turns on two-factor authentication once the user enters a code from
+    /// their authenticator app.
+    TwoFactorConfirm,
+    TwoFactorDisable,
+    /// Signed link included in every opt-in email, which works without
+    /// logging in. `GET` asks for confirmation, and `POST` turns off all
+    /// opt-in emails.
+    Unsubscribe(Option<String>),
     UserHome,
     UserPreference,
+    /// Count a food which the user has entered before, pointing a new eaten
+    /// event back at the same `food` row.
+    UsePreviousFood(Option<i32>),
+    /// The link from the verification email, which marks the user's email
+    /// address as verified.
+    VerifyEmail(Option<String>),
+    /// Weight trend chart, and a list of recent weigh-ins.
+    Weight,
     /// Route which will return an empty string. This is mainly an HTMX utility
     /// to allow a component to easily be swapped with nothing.
     Void,
@@ -103,6 +184,14 @@ impl Route {
                 Some(value) => format!("/add-food-to-today/{value}"),
                 None => "/add-food-to-today/:id".into(),
             },
+            Self::AddMealToToday(slug) => match slug {
+                Some(value) => format!("/add-meal-to-today/{value}"),
+                None => "/add-meal-to-today/:id".into(),
+            },
+            Self::AcceptSuggestedGoal => "/tdee/accept".into(),
+            Self::AccountDeleted => "/account-deleted".into(),
+            Self::AdminUsage => "/admin/usage".into(),
+            Self::AdminUsageQuota => "/admin/usage/quota".into(),
             Self::BalancingCheckpoints => {
                 "/calorie-balancing/checkpoints".into()
             }
@@ -115,7 +204,9 @@ impl Route {
                 Some(id) => format!("/blog/{id}"),
                 None => "/blog/:id".into(),
             },
+            Self::ChatFollowUp => "/chat-follow-up".into(),
             Self::ChatForm => "/chat-form".into(),
+            Self::DeleteAccount => "/delete-account".into(),
             Self::DeleteComment(slug) => match slug {
                 Some(id) => format!("/blog-delete-comment/{id}"),
                 None => "/blog-delete-comment/:id".into(),
@@ -126,15 +217,33 @@ impl Route {
                 }
                 None => "/food-eaten-event/:food_eaten_event_id".into(),
             },
+            Self::DeleteWeight(slug) => match slug {
+                Some(id) => format!("/weight/{id}"),
+                None => "/weight/:id".into(),
+            },
+            Self::Devices => "/devices".into(),
             Self::DisplayMacros => "/metrics/macros".into(),
+            Self::EditFood(slug) => match slug {
+                Some(food_eaten_event_id) => {
+                    format!("/food-eaten-event/{food_eaten_event_id}/edit")
+                }
+                None => "/food-eaten-event/:food_eaten_event_id/edit".into(),
+            },
+            Self::ExportCsv => "/export/food.csv".into(),
+            Self::ExportJson => "/export/data.json".into(),
             Self::Favicon => "/favicon.ico".into(),
+            Self::FoodCandidate => "/food-candidate".into(),
             Self::GotoStripePortal => "/stripe-portal".into(),
             Self::HandleChat => "/chat".into(),
             Self::Htmx => "/generated/htmx-2.0.2".into(),
+            Self::ImportFood => "/import-food".into(),
+            Self::ImportFoodPreview => "/import-food/preview".into(),
             Self::InitAnon(next_route) => next_route.as_string(),
             Self::ListFood => "/list-food".into(),
             Self::Login => "/authentication/login".into(),
+            Self::LoginTwoFactor => "/authentication/login/two-factor".into(),
             Self::Logout => "/authentication/logout".into(),
+            Self::LogWeight => "/weight/log".into(),
             Self::PasswordReset => "/authentication/reset-password".into(),
             Self::PasswordResetSecret(slug) => match slug {
                 Some(slug) => format!("/authentication/reset-password/{slug}"),
@@ -144,9 +253,34 @@ impl Route {
             Self::PreviousDayFood => "/get-food-custom-date-form".into(),
             Self::PrivacyPolicy => "/privacy".into(),
             Self::Register => "/authentication/register".into(),
+            Self::ResendVerificationEmail => {
+                "/authentication/verify-email".into()
+            }
+            Self::RevokeAllSessions => "/devices/revoke-all".into(),
+            Self::RevokeSession(slug) => match slug {
+                Some(id) => format!("/devices/{id}"),
+                None => "/devices/:id".into(),
+            },
             Self::Root => "/".into(),
             Self::RobotsTxt => "/robots.txt".into(),
+            Self::SaveAllFood => "/save-all-food".into(),
             Self::SaveFood => "/save-food".into(),
+            Self::SaveFoodCandidate => "/save-food-candidate".into(),
+            Self::SavedMeal(slug) => match slug {
+                Some(id) => format!("/saved-meals/{id}"),
+                None => "/saved-meals/:id".into(),
+            },
+            Self::SavedMealFood(slug) => match slug {
+                Some(id) => format!("/saved-meals/{id}/food"),
+                None => "/saved-meals/:id/food".into(),
+            },
+            Self::SavedMeals => "/saved-meals".into(),
+            Self::ShowFood(slug) => match slug {
+                Some(food_eaten_event_id) => {
+                    format!("/food-eaten-event/{food_eaten_event_id}/card")
+                }
+                None => "/food-eaten-event/:food_eaten_event_id/card".into(),
+            },
             Self::StaticAppleIcon => "/static/apple_icon".into(),
             Self::StaticLargeIcon => "/static/large-icon".into(),
             Self::StaticManifest => "/static/manifest".into(),
@@ -165,9 +299,27 @@ impl Route {
             Self::StripeWehhook => "/stripe-webhook".into(),
             Self::SubscriptionInactive => "/subscription-inactive".into(),
             Self::SubscriptionTrialEnded => "/trial-ended".into(),
+            Self::TdeeEstimate => "/tdee".into(),
             Self::TermsOfService => "/terms".into(),
+            Self::Trends => "/metrics/trends".into(),
+            Self::TwoFactor => "/two-factor".into(),
+            Self::TwoFactorConfirm => "/two-factor/confirm".into(),
+            Self::TwoFactorDisable => "/two-factor/disable".into(),
+            Self::Unsubscribe(slug) => match slug {
+                Some(token) => format!("/unsubscribe/{token}"),
+                None => "/unsubscribe/:token".into(),
+            },
             Self::UserHome => "/home".into(),
             Self::UserPreference => "/preferences".into(),
+            Self::UsePreviousFood(slug) => match slug {
+                Some(id) => format!("/use-previous-food/{id}"),
+                None => "/use-previous-food/:id".into(),
+            },
+            Self::VerifyEmail(slug) => match slug {
+                Some(slug) => format!("/authentication/verify-email/{slug}"),
+                None => "/authentication/verify-email/:slug".into(),
+            },
+            Self::Weight => "/weight".into(),
             Self::Void => "/void".into(),
         }
     }
@@ -185,14 +337,26 @@ impl std::fmt::Display for Route {
 /// are called.
 fn get_authenticated_routes() -> Router<models::AppState> {
     Router::new()
+        .route(
+            &Route::AcceptSuggestedGoal.as_string(),
+            post(weight::accept_suggested_goal),
+        )
         .route(
             &Route::AddFoodToToday(None).as_string(),
             post(controllers::add_food_to_today),
         )
+        .route(
+            &Route::AddMealToToday(None).as_string(),
+            post(count_chat::add_saved_meal_to_today),
+        )
         .route(
             &Route::BlogCommentSubmission.as_string(),
             post(blog::handle_comment_submission),
         )
+        .route(
+            &Route::ChatFollowUp.as_string(),
+            post(count_chat::handle_follow_up),
+        )
         .route(&Route::ChatForm.as_string(), get(count_chat::chat_form))
         .route(&Route::ChatForm.as_string(), post(count_chat::chat_form))
         .route(
@@ -203,39 +367,131 @@ fn get_authenticated_routes() -> Router<models::AppState> {
             &Route::DeleteFood(None).as_string(),
             delete(controllers::delete_food),
         )
+        .route(
+            &Route::DeleteWeight(None).as_string(),
+            delete(weight::delete_weight),
+        )
         .route(
             &Route::DisplayMacros.as_string(),
             get(metrics::display_macros),
         )
+        .route(
+            &Route::EditFood(None).as_string(),
+            get(count_chat::edit_food_form).post(count_chat::handle_edit_food),
+        )
         .route(
             &Route::HandleChat.as_string(),
             post(count_chat::handle_chat),
         )
         .route(&Route::ListFood.as_string(), get(count_chat::list_food))
+        .route(&Route::LogWeight.as_string(), post(weight::log_weight))
         .route(
             &Route::SaveFood.as_string(),
             post(count_chat::handle_save_food),
         )
+        .route(
+            &Route::SaveFoodCandidate.as_string(),
+            post(count_chat::handle_save_food_candidate),
+        )
+        .route(
+            &Route::FoodCandidate.as_string(),
+            post(count_chat::food_candidate_card),
+        )
+        .route(
+            &Route::ImportFood.as_string(),
+            get(count_chat::import_food_page)
+                .post(count_chat::handle_food_import),
+        )
+        .route(
+            &Route::ImportFoodPreview.as_string(),
+            post(count_chat::preview_food_import),
+        )
+        .route(
+            &Route::SaveAllFood.as_string(),
+            post(count_chat::handle_save_all_food),
+        )
+        .route(
+            &Route::SavedMeals.as_string(),
+            get(count_chat::saved_meal_list)
+                .post(count_chat::create_saved_meal),
+        )
+        .route(
+            &Route::SavedMeal(None).as_string(),
+            get(count_chat::saved_meal_editor)
+                .post(count_chat::rename_saved_meal)
+                .delete(count_chat::delete_saved_meal),
+        )
+        .route(
+            &Route::SavedMealFood(None).as_string(),
+            post(count_chat::add_food_to_saved_meal)
+                .delete(count_chat::remove_food_from_saved_meal),
+        )
         .route(
             &Route::PreviousDayFood.as_string(),
             post(count_chat::prev_day_food_form),
         )
+        .route(
+            &Route::TdeeEstimate.as_string(),
+            get(weight::tdee_estimate).post(weight::save_weight_goal),
+        )
+        .route(&Route::Trends.as_string(), get(metrics::trends))
         .route(&Route::UserHome.as_string(), get(controllers::user_home))
         .route(
             &Route::UserPreference.as_string(),
             any(preferences::user_preference_controller),
         )
+        .route(
+            &Route::ShowFood(None).as_string(),
+            get(count_chat::show_food),
+        )
+        .route(
+            &Route::UsePreviousFood(None).as_string(),
+            post(count_chat::use_previous_food),
+        )
+        .route(&Route::Weight.as_string(), get(weight::weight_page))
 }
 
 /// Routes where authentication is required, but we do not check subscription
 /// status, so they can be visited by users who are not paying or do not
 /// have an active free trial.
-fn get_authenticated_free_routes() -> Router<models::AppState> {
+fn get_authenticated_free_routes(
+    state: &models::AppState,
+) -> Router<models::AppState> {
     Router::new()
+        .route(&Route::AdminUsage.as_string(), get(admin::usage_dashboard))
+        .route(
+            &Route::AdminUsageQuota.as_string(),
+            post(admin::set_usage_quota),
+        )
+        .route(
+            &Route::DeleteAccount.as_string(),
+            get(delete_account::get_delete_account_form),
+        )
+        .route(
+            &Route::DeleteAccount.as_string(),
+            post(delete_account::handle_delete_account).layer(
+                from_fn_with_state(state.clone(), middleware::rate_limit),
+            ),
+        )
+        .route(&Route::Devices.as_string(), get(auth::devices))
         .route(
             &Route::GotoStripePortal.as_string(),
             get(stripe::redirect_to_billing_portal),
         )
+        .route(&Route::ExportCsv.as_string(), get(export::export_csv))
+        .route(&Route::ExportJson.as_string(), get(export::export_json))
+        .route(
+            &Route::ResendVerificationEmail.as_string(),
+            post(auth::resend_verification_email),
+        )
+        .route(
+            &Route::RevokeAllSessions.as_string(),
+            post(auth::revoke_all_sessions),
+        )
+        .route(
+            &Route::RevokeSession(None).as_string(),
+            delete(auth::revoke_session),
+        )
         .route(
             &Route::SubscriptionInactive.as_string(),
             get(stripe::subscription_ended),
@@ -244,13 +500,33 @@ fn get_authenticated_free_routes() -> Router<models::AppState> {
             &Route::SubscriptionTrialEnded.as_string(),
             get(stripe::trial_expired),
         )
+        .route(
+            &Route::TwoFactor.as_string(),
+            get(auth::two_factor_settings)
+                .post(auth::start_two_factor_enrollment),
+        )
+        .route(
+            &Route::TwoFactorConfirm.as_string(),
+            post(auth::confirm_two_factor),
+        )
+        .route(
+            &Route::TwoFactorDisable.as_string(),
+            post(auth::disable_two_factor).layer(from_fn_with_state(
+                state.clone(),
+                middleware::rate_limit,
+            )),
+        )
 }
 
 /// In [crate::main], these routes are not protected by any authentication, so
 /// any requester can access these routes.
-fn get_public_routes() -> Router<models::AppState> {
+fn get_public_routes(state: &models::AppState) -> Router<models::AppState> {
     Router::new()
         .route(&Route::About.as_string(), get(controllers::about))
+        .route(
+            &Route::AccountDeleted.as_string(),
+            get(delete_account::account_deleted),
+        )
         .route(
             &Route::BalancingHistory.as_string(),
             get(balancing::history),
@@ -280,7 +556,20 @@ fn get_public_routes() -> Router<models::AppState> {
             post(auth::init_anon),
         )
         .route(&Route::Login.as_string(), get(auth::get_login_form))
-        .route(&Route::Login.as_string(), post(auth::handle_login))
+        .route(
+            &Route::Login.as_string(),
+            post(auth::handle_login).layer(from_fn_with_state(
+                state.clone(),
+                middleware::rate_limit,
+            )),
+        )
+        .route(
+            &Route::LoginTwoFactor.as_string(),
+            post(auth::handle_login_two_factor).layer(from_fn_with_state(
+                state.clone(),
+                middleware::rate_limit,
+            )),
+        )
         .route(&Route::Logout.as_string(), get(auth::logout))
         .route(
             &Route::PasswordReset.as_string(),
@@ -288,7 +577,10 @@ fn get_public_routes() -> Router<models::AppState> {
         )
         .route(
             &Route::PasswordReset.as_string(),
-            post(auth::handle_pw_reset_request),
+            post(auth::handle_pw_reset_request).layer(from_fn_with_state(
+                state.clone(),
+                middleware::rate_limit,
+            )),
         )
         .route(
             &Route::PasswordResetSecret(None).as_string(),
@@ -353,6 +645,14 @@ fn get_public_routes() -> Router<models::AppState> {
             post(stripe::handle_stripe_webhook),
         )
         .route(&Route::TermsOfService.as_string(), get(legal::get_tos))
+        .route(
+            &Route::Unsubscribe(None).as_string(),
+            get(digest::get_unsubscribe_page).post(digest::handle_unsubscribe),
+        )
+        .route(
+            &Route::VerifyEmail(None).as_string(),
+            get(auth::verify_email),
+        )
         .route(&Route::Void.as_string(), get(controllers::void))
 }
 
@@ -360,13 +660,16 @@ pub fn get_routes(state: models::AppState) -> Router<models::AppState> {
     let protected_routes = get_authenticated_routes()
         .layer(from_fn(middleware::html_headers))
         .layer(from_fn(middleware::auth))
-        .layer(from_fn_with_state(state, middleware::narc_on_subscriptions));
+        .layer(from_fn_with_state(
+            state.clone(),
+            middleware::narc_on_subscriptions,
+        ));
 
-    let protected_free_routes = get_authenticated_free_routes()
+    let protected_free_routes = get_authenticated_free_routes(&state)
         .layer(from_fn(middleware::html_headers))
         .layer(from_fn(middleware::auth));
 
-    let public_routes = get_public_routes()
+    let public_routes = get_public_routes(&state)
         .layer(from_fn(middleware::html_headers))
         .layer(from_fn(middleware::log));
 
@@ -374,4 +677,5 @@ pub fn get_routes(state: models::AppState) -> Router<models::AppState> {
         .nest("/", protected_routes)
         .nest("/", public_routes)
         .nest("/", protected_free_routes)
+        .layer(from_fn_with_state(state, middleware::sessions))
 }
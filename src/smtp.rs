@@ -1,39 +1,146 @@
+//! Email delivery. Build an [Email] from an
+//! [EmailTemplate](crate::email::EmailTemplate), and send it here.
+//!
+//! In production (the `enable_smtp_email` feature), emails are sent through
+//! the SMTP relay at `SMTP_RELAY`, from the address at `EMAIL_FROM`. In
+//! development, emails are printed to stdout, or written to the directory at
+//! `EMAIL_OUTPUT_DIR` if it's set, so that the HTML can be opened in a
+//! browser.
+
+use crate::email::Email;
 use anyhow::Result;
 #[cfg(feature = "enable_smtp_email")]
 use lettre::{
-    message::header::ContentType, transport::smtp::authentication::Credentials,
+    message::MultiPart, transport::smtp::authentication::Credentials,
     AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
 };
-#[cfg(feature = "enable_smtp_email")]
 use std::env;
 
 #[cfg(feature = "enable_smtp_email")]
-pub async fn send_email(to: &str, subject: &str, msg: &str) -> Result<()> {
-    let email = Message::builder()
-        .from("beancount.bot <jdevries3133@gmail.com>".parse()?)
-        .reply_to("beancount.bot <jdevries3133@gmail.com>".parse()?)
-        .to(to.parse()?)
-        .subject(subject)
-        .header(ContentType::TEXT_PLAIN)
-        .body(String::from(msg))?;
+pub async fn send_email(email: &Email) -> Result<()> {
+    let from = env::var("EMAIL_FROM")?;
+    let message = Message::builder()
+        .from(from.parse()?)
+        .reply_to(from.parse()?)
+        .to(email.to.parse()?)
+        .subject(&email.subject)
+        .multipart(MultiPart::alternative_plain_html(
+            email.text.clone(),
+            email.html.clone(),
+        ))?;
 
+    let relay = env::var("SMTP_RELAY")?;
     let username = env::var("SMTP_EMAIL_USERNAME")?;
     let password = env::var("SMTP_EMAIL_PASSWORD")?;
 
     let creds = Credentials::new(username, password);
 
-    let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay("smtp.gmail.com")
-        .unwrap()
+    let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(&relay)?
         .credentials(creds)
         .build();
 
-    mailer.send(email).await?;
+    mailer.send(message).await?;
     Ok(())
 }
 
 #[cfg(not(feature = "enable_smtp_email"))]
-pub async fn send_email(to: &str, subject: &str, msg: &str) -> Result<()> {
-    println!("Would send email:\n\tTo: {to}\n\tSubject: {subject}\n\tBody:\n{msg}\n===\n");
+pub async fn send_email(email: &Email) -> Result<()> {
+    #[cfg(test)]
+    outbox::push(email);
+
+    match env::var("EMAIL_OUTPUT_DIR") {
+        Ok(dir) => write_email(&dir, email).await,
+        Err(_) => {
+            let Email {
+                to, subject, text, ..
+            } = email;
+            println!("Would send email:\n\tTo: {to}\n\tSubject: {subject}\n\tBody:\n{text}\n===\n");
+            Ok(())
+        }
+    }
+}
 
+/// Writes `<timestamp>-<recipient>.html` and `.txt` into `dir`.
+#[cfg(not(feature = "enable_smtp_email"))]
+async fn write_email(dir: &str, email: &Email) -> Result<()> {
+    use crate::chrono_utils::utc_now;
+    use std::path::Path;
+    use tokio::fs;
+
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir).await?;
+    let recipient: String = email
+        .to
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let stem = format!("{}-{recipient}", utc_now().format("%Y%m%dT%H%M%S%.6f"));
+    let html = dir.join(format!("{stem}.html"));
+    fs::write(&html, &email.html).await?;
+    fs::write(
+        dir.join(format!("{stem}.txt")),
+        format!(
+            "To: {}\nSubject: {}\n\n{}",
+            email.to, email.subject, email.text
+        ),
+    )
+    .await?;
+    println!("Wrote email to {}", html.display());
     Ok(())
 }
+
+/// Under test, the stub above also keeps every email that it "sends," so
+/// that tests can check what would have gone out.
+#[cfg(all(test, not(feature = "enable_smtp_email")))]
+pub mod outbox {
+    use crate::email::Email;
+    use std::sync::Mutex;
+
+    static OUTBOX: Mutex<Vec<Email>> = Mutex::new(Vec::new());
+
+    pub fn push(email: &Email) {
+        OUTBOX
+            .lock()
+            .expect("outbox is not poisoned")
+            .push(email.clone());
+    }
+
+    /// Removes and returns every email sent to `to`. Tests run in parallel,
+    /// so each test should send to its own address.
+    pub fn take(to: &str) -> Vec<Email> {
+        let mut outbox = OUTBOX.lock().expect("outbox is not poisoned");
+        let (sent, rest) = outbox.drain(..).partition(|email| email.to == to);
+        *outbox = rest;
+        sent
+    }
+}
+
+#[cfg(all(test, not(feature = "enable_smtp_email")))]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_email() {
+        let dir = env::temp_dir()
+            .join(format!("calcount-email-test-{}", std::process::id()));
+        let email = Email {
+            to: "dev@example.com".into(),
+            subject: "Hello".into(),
+            html: "<p>Hello</p>".into(),
+            text: "Hello".into(),
+        };
+        write_email(dir.to_str().unwrap(), &email).await.unwrap();
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), 2);
+        assert_eq!(std::fs::read_to_string(&files[0]).unwrap(), "<p>Hello</p>");
+        assert_eq!(
+            std::fs::read_to_string(&files[1]).unwrap(),
+            "To: dev@example.com\nSubject: Hello\n\nHello"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+//! Cancels a customer's subscriptions right away, when their account is
+//! deleted.
+
+#[cfg(feature = "stripe")]
+use super::env::get_b64_encoded_token_from_env;
+use crate::prelude::*;
+#[cfg(feature = "stripe")]
+use reqwest::Client;
+
+#[cfg(feature = "stripe")]
+#[derive(Deserialize)]
+struct SubscriptionList {
+    data: Vec<Subscription>,
+}
+
+#[cfg(feature = "stripe")]
+#[derive(Deserialize)]
+struct Subscription {
+    id: String,
+}
+
+#[cfg(feature = "stripe")]
+pub async fn cancel_subscriptions(stripe_customer_id: &str) -> Aresult<()> {
+    // Users who never made it to Stripe don't have a customer ID.
+    if stripe_customer_id.is_empty() {
+        return Ok(());
+    }
+    let secret_key = get_b64_encoded_token_from_env()?;
+    let client = Client::new();
+    // Cancelled subscriptions aren't listed, and customers only ever have
+    // one subscription, so the first page is all that we need.
+    let response = client
+        .get("https://api.stripe.com/v1/subscriptions")
+        .header("Authorization", format!("Basic {secret_key}"))
+        .query(&[("customer", stripe_customer_id)])
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(Error::msg(
+            "request to list subscriptions failed (cancel subscriptions)",
+        ));
+    }
+    let subscriptions = response.json::<SubscriptionList>().await?;
+    for subscription in subscriptions.data {
+        let url = format!(
+            "https://api.stripe.com/v1/subscriptions/{}",
+            subscription.id
+        );
+        let response = client
+            .delete(url)
+            .header("Authorization", format!("Basic {secret_key}"))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::msg(
+                "request to cancel subscription failed (cancel subscriptions)",
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "stripe"))]
+pub async fn cancel_subscriptions(_stripe_customer_id: &str) -> Aresult<()> {
+    Ok(())
+}
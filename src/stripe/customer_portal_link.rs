@@ -23,7 +23,7 @@ struct BillingPortalResponse {
 }
 
 pub async fn redirect_to_billing_portal(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ServerError> {
     let session =
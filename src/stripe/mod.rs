@@ -1,3 +1,4 @@
+mod cancel_subscriptions;
 mod create_new_subscription;
 mod customer_portal_link;
 mod db_ops;
@@ -7,6 +8,7 @@ mod subscription_ended;
 mod trial_expired;
 mod webhook;
 
+pub use cancel_subscriptions::cancel_subscriptions;
 pub use create_new_subscription::create_customer;
 pub use customer_portal_link::redirect_to_billing_portal;
 pub use db_ops::get_subscription_type;
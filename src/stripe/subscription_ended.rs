@@ -71,7 +71,7 @@ impl Component for SubscriptionExpired<'_> {
 }
 
 pub async fn subscription_ended(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers_err(&headers, "subscription ended")?;
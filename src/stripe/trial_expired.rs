@@ -74,7 +74,7 @@ impl Component for AnonSubExpired {
     }
 }
 pub async fn trial_expired(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ServerError> {
     let session = Session::from_headers_err(&headers, "trial expired")?;
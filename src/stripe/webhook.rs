@@ -102,7 +102,7 @@ fn parse_update(stripe_garbage: &str) -> Option<StripeUpdate> {
     }
 }
 pub async fn handle_stripe_webhook(
-    State(AppState { db }): State<AppState>,
+    State(AppState { db, .. }): State<AppState>,
     headers: HeaderMap,
     body: String,
 ) -> Result<impl IntoResponse, ServerError> {
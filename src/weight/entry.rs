@@ -0,0 +1,114 @@
+use super::unit::{unit_options, WeightUnit};
+use crate::prelude::*;
+
+/// Quick-entry form for today's weight. After a weight is saved, the form is
+/// swapped for a fresh one which confirms the save.
+pub struct WeightEntryForm {
+    pub saved: Option<f64>,
+    /// Selected by default; users can still log any one weigh-in in the
+    /// other unit.
+    pub unit: WeightUnit,
+}
+impl Component for WeightEntryForm {
+    fn render(&self) -> String {
+        let log_weight = Route::LogWeight;
+        let weight_page = Route::Weight;
+        let unit = self.unit.as_str();
+        let options = unit_options(self.unit);
+        let saved = self.saved.map_or(String::new(), |weight| {
+            format!(r#"<p class="text-sm">Saved {weight:.1} {unit}!</p>"#)
+        });
+        format!(
+            r#"
+            <form
+                hx-post="{log_weight}"
+                hx-swap="outerHTML"
+                class="self-start flex flex-wrap items-center gap-2 p-2
+                bg-slate-100 dark:bg-slate-800 rounded-2xl"
+            >
+                <label for="weight" class="text-sm">Today's weight</label>
+                <input
+                    id="weight"
+                    name="weight"
+                    type="number"
+                    step="0.1"
+                    min="0"
+                    required
+                    class="w-28 rounded dark:text-black"
+                />
+                <select
+                    name="unit"
+                    aria-label="Unit"
+                    class="rounded dark:text-black"
+                >
+                    {options}
+                </select>
+                <button
+                    class="text-sm p-1 bg-emerald-100 hover:bg-emerald-200
+                    rounded text-black"
+                >
+                    Log Weight
+                </button>
+                <a class="link text-sm" href="{weight_page}">Weight Trend</a>
+                {saved}
+            </form>
+            "#
+        )
+    }
+}
+
+/// This is just a sanity check against typos, in either unit.
+const MAX_WEIGHT: f64 = 2000.0;
+
+#[derive(Deserialize)]
+pub struct WeightPayload {
+    weight: f64,
+    unit: String,
+}
+
+pub async fn log_weight(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(WeightPayload { weight, unit }): Form<WeightPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "log weight")?;
+    let unit = WeightUnit::parse(&unit)?;
+    if !(weight > 0.0 && weight < MAX_WEIGHT) {
+        return Err(ServerError::bad_request(
+            "weight out of range",
+            Some(format!(
+                "Weight must be more than 0 and less than {MAX_WEIGHT}."
+            )),
+        ));
+    }
+    query!(
+        "insert into weight_entry (user_id, weight, unit) values ($1, $2, $3)",
+        session.user_id,
+        weight,
+        unit.as_str()
+    )
+    .execute(&db)
+    .await?;
+
+    Ok(WeightEntryForm {
+        saved: Some(weight),
+        unit,
+    }
+    .render())
+}
+
+pub async fn delete_weight(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "delete weight")?;
+    query!(
+        "delete from weight_entry where id = $1 and user_id = $2",
+        id,
+        session.user_id
+    )
+    .execute(&db)
+    .await?;
+    Ok("")
+}
@@ -0,0 +1,16 @@
+//! Body weight logging. Day-to-day weight is noisy, so we mostly show an
+//! exponentially smoothed trend instead of the raw weigh-ins. Together with
+//! food history, the trend is used to estimate maintenance calories.
+
+mod entry;
+mod tdee;
+mod trend;
+mod unit;
+
+pub use entry::{delete_weight, log_weight, WeightEntryForm};
+pub use tdee::{
+    accept_suggested_goal, save_weight_goal, tdee_estimate,
+    TdeeEstimatePlaceholder,
+};
+pub use trend::{get_weight_days, weight_page, WeightChart, WeightDay};
+pub use unit::{get_weight_unit, WeightUnit};
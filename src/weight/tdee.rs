@@ -0,0 +1,518 @@
+//! Estimates the user's maintenance calories (total daily energy
+//! expenditure) from the energy balance over the last few weeks; whatever
+//! they ate, minus whatever was stored or lost as body weight. From there,
+//! we can suggest a calorie goal for their target rate of loss or gain.
+
+use super::{
+    trend::{get_weight_days, WeightDay},
+    unit::{unit_options, WeightUnit},
+};
+use crate::{
+    chrono_utils::start_of_date, config, htmx,
+    preferences::save_user_preference, prelude::*,
+};
+use chrono::Days;
+use futures::join;
+use std::collections::BTreeMap;
+
+/// The estimate is based on this many weeks of history, not including today.
+const ESTIMATE_WEEKS: u64 = 4;
+
+/// Fewer logged days than this, and the average intake is just a guess.
+const MIN_LOGGED_DAYS: usize = 14;
+
+/// We also need at least this many days of weigh-ins within the window, so
+/// that the trend has a chance to move.
+const MIN_WEIGH_IN_DAYS: usize = 4;
+
+/// We'll never suggest a goal lower than this.
+const MIN_SUGGESTED_GOAL: i32 = 1200;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct WeightGoal {
+    unit: WeightUnit,
+    /// Negative to lose weight, in [WeightGoal::unit] per week.
+    weekly_change: f64,
+}
+impl WeightGoal {
+    fn suggest_calorie_goal(&self, tdee: i32) -> i32 {
+        let daily_adjustment =
+            self.weekly_change * self.unit.calories_per_unit() / 7.0;
+        let goal = f64::from(tdee) + daily_adjustment;
+        // Nobody needs a goal more precise than the nearest 10 calories.
+        ((goal / 10.0).round() as i32 * 10).max(MIN_SUGGESTED_GOAL)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Estimate {
+    NotEnoughFood { days_logged: usize },
+    NotEnoughWeight { weigh_in_days: usize },
+    Ready(Tdee),
+}
+
+#[derive(Debug, PartialEq)]
+struct Tdee {
+    calories: i32,
+    average_intake: i32,
+    weight_change: f64,
+    days: i64,
+}
+
+/// `daily_calories` holds the total for each logged day in the window, and
+/// `weight` has one entry for every day in the window, in `unit`.
+///
+/// Days where nothing was logged are left out of the average intake, rather
+/// than being counted as days where the user ate nothing.
+fn estimate(
+    daily_calories: &[(NaiveDate, i32)],
+    weight: &[WeightDay],
+    unit: WeightUnit,
+) -> Estimate {
+    let days_logged = daily_calories.len();
+    if days_logged < MIN_LOGGED_DAYS {
+        return Estimate::NotEnoughFood { days_logged };
+    }
+    let weigh_in_days = weight.iter().filter(|d| d.weight.is_some()).count();
+    let start = weight.iter().find(|d| d.trend.is_some());
+    let end = weight.last();
+    let (Some(start), Some(end)) = (start, end) else {
+        return Estimate::NotEnoughWeight { weigh_in_days };
+    };
+    let (Some(start_trend), Some(end_trend)) = (start.trend, end.trend) else {
+        return Estimate::NotEnoughWeight { weigh_in_days };
+    };
+    let days = (end.date - start.date).num_days();
+    if weigh_in_days < MIN_WEIGH_IN_DAYS || days < MIN_LOGGED_DAYS as i64 {
+        return Estimate::NotEnoughWeight { weigh_in_days };
+    }
+    let average_intake =
+        daily_calories.iter().map(|(_, c)| c).sum::<i32>() / days_logged as i32;
+    let weight_change = end_trend - start_trend;
+    let stored_per_day = weight_change * unit.calories_per_unit() / days as f64;
+    Estimate::Ready(Tdee {
+        calories: (f64::from(average_intake) - stored_per_day).round() as i32,
+        average_intake,
+        weight_change,
+        days,
+    })
+}
+
+struct TdeeCard<'a> {
+    estimate: &'a Estimate,
+    goal: WeightGoal,
+    current_calorie_goal: Option<i32>,
+}
+impl Component for TdeeCard<'_> {
+    fn render(&self) -> String {
+        let tdee_route = Route::TdeeEstimate;
+        let accept = Route::AcceptSuggestedGoal;
+        let unit = self.goal.unit.as_str();
+        let weekly_change = self.goal.weekly_change;
+        let unit_options = unit_options(self.goal.unit);
+        let weeks = ESTIMATE_WEEKS;
+        let result = match self.estimate {
+            Estimate::NotEnoughFood { days_logged } => format!(
+                r#"
+                <p>
+                    We need at least {MIN_LOGGED_DAYS} days of food logged
+                    over the last {weeks} weeks to estimate your maintenance
+                    calories; you've logged {days_logged} so far.
+                </p>
+                "#
+            ),
+            Estimate::NotEnoughWeight { weigh_in_days } => format!(
+                r#"
+                <p>
+                    We need your weight on at least {MIN_WEIGH_IN_DAYS} days,
+                    spread over {MIN_LOGGED_DAYS} days or more, within the
+                    last {weeks} weeks; you've weighed in on {weigh_in_days}
+                    days so far.
+                </p>
+                "#
+            ),
+            Estimate::Ready(tdee) => {
+                let calories = tdee.calories;
+                let average_intake = tdee.average_intake;
+                let weight_change = tdee.weight_change;
+                let days = tdee.days;
+                let suggestion = self.goal.suggest_calorie_goal(calories);
+                let accept_button =
+                    if self.current_calorie_goal == Some(suggestion) {
+                        r#"<p class="text-sm">This is already your goal.</p>"#
+                            .to_string()
+                    } else {
+                        format!(
+                            r#"
+                        <form hx-post="{accept}">
+                            <input
+                                type="hidden"
+                                name="caloric_intake_goal"
+                                value="{suggestion}"
+                            />
+                            <button
+                                class="bg-emerald-100 hover:bg-emerald-200
+                                rounded p-1 text-black"
+                            >
+                                Set my goal to {suggestion} calories
+                            </button>
+                        </form>
+                        "#
+                        )
+                    };
+                format!(
+                    r#"
+                    <p>
+                        Your estimated maintenance is
+                        <span class="font-semibold">{calories}</span>
+                        calories per day.
+                    </p>
+                    <p class="text-sm">
+                        You ate an average of {average_intake} calories per
+                        logged day, and your trend weight changed by
+                        {weight_change:+.1} {unit} over {days} days.
+                    </p>
+                    <p>
+                        To change by {weekly_change:+.1} {unit} per week, we
+                        suggest a goal of {suggestion} calories.
+                    </p>
+                    {accept_button}
+                    "#
+                )
+            }
+        };
+        format!(
+            r##"
+            <div
+                id="tdee-estimate"
+                class="rounded my-3 p-3 border-2 border-black flex flex-col
+                gap-2 max-w-prose"
+            >
+                <h2 class="text-lg">Maintenance Calories</h2>
+                <details class="text-xs">
+                    <summary>Learn more</summary>
+                    <p>
+                        We estimate how many calories you burn in a day by
+                        comparing what you've eaten over the last {weeks}
+                        weeks with how your weight trend moved. Log your
+                        food and weight consistently for the best estimate.
+                        Your weigh-ins are shown in the unit you choose
+                        here.
+                    </p>
+                </details>
+                <form
+                    hx-post="{tdee_route}"
+                    hx-target="#tdee-estimate"
+                    class="flex flex-wrap items-end gap-2"
+                >
+                    <label class="flex flex-col text-sm">
+                        Target change per week
+                        <input
+                            type="number"
+                            step="0.1"
+                            name="weekly_change"
+                            value="{weekly_change}"
+                            class="w-24 dark:text-black"
+                        />
+                    </label>
+                    <label class="flex flex-col text-sm">
+                        Unit
+                        <select name="weight_unit" class="dark:text-black">
+                            {unit_options}
+                        </select>
+                    </label>
+                    <button
+                        class="bg-blue-100 hover:bg-blue-200 rounded p-1
+                        text-black"
+                    >
+                        Update
+                    </button>
+                </form>
+                {result}
+            </div>
+            "##
+        )
+    }
+}
+
+/// A placeholder for the preferences page, which loads the estimate lazily
+/// so that the preferences form itself stays quick.
+pub struct TdeeEstimatePlaceholder;
+impl Component for TdeeEstimatePlaceholder {
+    fn render(&self) -> String {
+        let tdee = Route::TdeeEstimate;
+        format!(r#"<div hx-get="{tdee}" hx-trigger="load"></div>"#)
+    }
+}
+
+async fn get_weight_goal(db: &PgPool, user_id: i32) -> Aresult<WeightGoal> {
+    let row = query!(
+        "select weight_unit, weekly_change from weight_goal
+        where user_id = $1",
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+    match row {
+        Some(row) => Ok(WeightGoal {
+            unit: WeightUnit::parse(&row.weight_unit)?,
+            weekly_change: row.weekly_change,
+        }),
+        None => Ok(WeightGoal::default()),
+    }
+}
+
+/// Total calories for each day in the window which has any food logged, in
+/// the user's timezone.
+async fn get_daily_calories(
+    db: &PgPool,
+    user_id: i32,
+    timezone: Tz,
+    first_day: NaiveDate,
+    end: NaiveDate,
+) -> Aresult<Vec<(NaiveDate, i32)>> {
+    struct Qres {
+        eaten_at: DateTime<Utc>,
+        calories: i32,
+    }
+    let food = query_as!(
+        Qres,
+        "select fee.eaten_at, f.calories
+        from food_eaten_event fee
+        join food f on fee.food_id = f.id
+        where
+            fee.user_id = $1
+            and f.user_id = $1
+            and fee.eaten_at >= $2
+            and fee.eaten_at < $3",
+        user_id,
+        start_of_date(first_day, timezone),
+        start_of_date(end, timezone)
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(food
+        .iter()
+        .fold(BTreeMap::new(), |mut acc, row| {
+            let date = row.eaten_at.with_timezone(&timezone).date_naive();
+            *acc.entry(date).or_default() += row.calories;
+            acc
+        })
+        .into_iter()
+        .collect())
+}
+
+async fn render_card(
+    db: &PgPool,
+    user_id: i32,
+    preferences: &UserPreference,
+    goal: WeightGoal,
+) -> Aresult<String> {
+    let timezone = preferences.timezone;
+    let today = utc_now().with_timezone(&timezone).date_naive();
+    let days = ESTIMATE_WEEKS * 7;
+    let first_day = today
+        .checked_sub_days(Days::new(days))
+        .ok_or(Error::msg("estimate starts before the dawn of time"))?;
+    let (daily_calories, weight) = join![
+        get_daily_calories(db, user_id, timezone, first_day, today),
+        get_weight_days(db, user_id, goal.unit, timezone, first_day, days)
+    ];
+    let estimate = estimate(&daily_calories?, &weight?, goal.unit);
+    Ok(TdeeCard {
+        estimate: &estimate,
+        goal,
+        current_calorie_goal: preferences.caloric_intake_goal,
+    }
+    .render())
+}
+
+pub async fn tdee_estimate(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "tdee estimate")?;
+    let preferences = session.get_preferences(&db).await?;
+    let goal = get_weight_goal(&db, session.user_id).await?;
+    Ok(render_card(&db, session.user_id, &preferences, goal).await?)
+}
+
+#[derive(Deserialize)]
+pub struct WeightGoalPayload {
+    weight_unit: String,
+    weekly_change: f64,
+}
+
+/// Gaining or losing more than this per week is not something we're going
+/// to help with.
+const MAX_WEEKLY_CHANGE_LBS: f64 = 2.0;
+
+pub async fn save_weight_goal(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(payload): Form<WeightGoalPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "save weight goal")?;
+    let unit = WeightUnit::parse(&payload.weight_unit)?;
+    let max_change = MAX_WEEKLY_CHANGE_LBS
+        * WeightUnit::Pounds.calories_per_unit()
+        / unit.calories_per_unit();
+    if payload.weekly_change.is_nan()
+        || payload.weekly_change.abs() > max_change
+    {
+        return Err(ServerError::bad_request(
+            "weekly weight change out of range",
+            Some(format!(
+                "Target change must be within {max_change:.1} {} per week.",
+                unit.as_str()
+            )),
+        ));
+    }
+    let goal = WeightGoal {
+        unit,
+        weekly_change: payload.weekly_change,
+    };
+    query!(
+        "insert into weight_goal (user_id, weight_unit, weekly_change)
+        values ($1, $2, $3)
+        on conflict (user_id)
+        do update set weight_unit = $2, weekly_change = $3",
+        session.user_id,
+        goal.unit.as_str(),
+        goal.weekly_change
+    )
+    .execute(&db)
+    .await?;
+    let preferences = session.get_preferences(&db).await?;
+    Ok(render_card(&db, session.user_id, &preferences, goal).await?)
+}
+
+#[derive(Deserialize)]
+pub struct AcceptGoalPayload {
+    caloric_intake_goal: i32,
+}
+
+/// Saves the suggested goal, and then reloads the preferences page so that
+/// the preferences form shows it.
+pub async fn accept_suggested_goal(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+    Form(AcceptGoalPayload {
+        caloric_intake_goal,
+    }): Form<AcceptGoalPayload>,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "accept goal")?;
+    if caloric_intake_goal < MIN_SUGGESTED_GOAL {
+        return Err(ServerError::bad_request(
+            "suggested goal is too low",
+            None,
+        ));
+    }
+    if caloric_intake_goal > config::MAX_CALORIC_INTAKE_GOAL {
+        return Err(ServerError::bad_request(
+            "suggested goal is too high",
+            None,
+        ));
+    }
+    let preferences = session.get_preferences(&db).await?;
+    save_user_preference(
+        &db,
+        session.user_id,
+        &UserPreference {
+            caloric_intake_goal: Some(caloric_intake_goal),
+            ..preferences
+        },
+    )
+    .await?;
+    Ok(htmx::redirect(
+        HeaderMap::new(),
+        &Route::UserPreference.as_string(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    /// 28 days of weight, falling linearly from `start` by `per_day`, with
+    /// a weigh-in every other day.
+    fn falling_weight(start: f64, per_day: f64) -> Vec<WeightDay> {
+        date("2024-06-01")
+            .iter_days()
+            .take(28)
+            .enumerate()
+            .map(|(i, date)| {
+                let trend = start - per_day * i as f64;
+                WeightDay {
+                    date,
+                    weight: (i % 2 == 0).then_some(trend),
+                    trend: Some(trend),
+                }
+            })
+            .collect()
+    }
+
+    fn intake(days: usize, calories: i32) -> Vec<(NaiveDate, i32)> {
+        date("2024-06-01")
+            .iter_days()
+            .take(days)
+            .map(|d| (d, calories))
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate_from_energy_balance() {
+        // Losing 0.1lbs per day is a 350 calorie daily deficit.
+        let weight = falling_weight(200.0, 0.1);
+        let result = estimate(&intake(20, 2000), &weight, WeightUnit::Pounds);
+        let Estimate::Ready(tdee) = result else {
+            panic!("expected an estimate, got {result:?}");
+        };
+        assert_eq!(tdee.calories, 2350);
+        assert_eq!(tdee.average_intake, 2000);
+        assert_eq!(tdee.days, 27);
+        assert!((tdee.weight_change + 2.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_estimate_needs_enough_food() {
+        let weight = falling_weight(200.0, 0.1);
+        assert_eq!(
+            estimate(&intake(13, 2000), &weight, WeightUnit::Pounds),
+            Estimate::NotEnoughFood { days_logged: 13 }
+        );
+    }
+
+    #[test]
+    fn test_estimate_needs_enough_weight() {
+        let mut weight = falling_weight(200.0, 0.1);
+        // The user only started weighing in 10 days ago.
+        for day in weight.iter_mut().take(18) {
+            day.weight = None;
+            day.trend = None;
+        }
+        assert_eq!(
+            estimate(&intake(20, 2000), &weight, WeightUnit::Pounds),
+            Estimate::NotEnoughWeight { weigh_in_days: 5 }
+        );
+    }
+
+    #[test]
+    fn test_suggested_goal() {
+        let lose = WeightGoal {
+            unit: WeightUnit::Pounds,
+            weekly_change: -1.0,
+        };
+        assert_eq!(lose.suggest_calorie_goal(2350), 1850);
+        let gain = WeightGoal {
+            unit: WeightUnit::Kilograms,
+            weekly_change: 0.25,
+        };
+        assert_eq!(gain.suggest_calorie_goal(2350), 2630);
+        assert_eq!(lose.suggest_calorie_goal(1400), MIN_SUGGESTED_GOAL);
+    }
+}
@@ -0,0 +1,447 @@
+use super::{
+    entry::WeightEntryForm,
+    unit::{get_weight_unit, WeightUnit},
+};
+use crate::{chrono_utils::start_of_date, prelude::*};
+use chrono::Days;
+use futures::join;
+use std::collections::BTreeMap;
+
+/// How far each day's weigh-in pulls the trend towards itself. This is the
+/// same 10% used by the moving average in The Hacker's Diet.
+const SMOOTHING: f64 = 0.1;
+
+/// How many days are shown on the weight page.
+const CHART_DAYS: u64 = 90;
+
+/// How many of the most recent weigh-ins are listed on the weight page.
+const RECENT_ENTRIES: usize = 30;
+
+/// A weigh-in, converted to the user's unit.
+struct WeightEntry {
+    id: i32,
+    weight: f64,
+    created_at: DateTime<Utc>,
+}
+
+/// Weigh-ins averaged by day, in the user's timezone.
+fn daily_weights(
+    entries: &[WeightEntry],
+    timezone: Tz,
+) -> Vec<(NaiveDate, f64)> {
+    entries
+        .iter()
+        .fold(BTreeMap::new(), |mut acc, entry| {
+            let date = entry.created_at.with_timezone(&timezone).date_naive();
+            let (sum, count): &mut (f64, i32) = acc.entry(date).or_default();
+            *sum += entry.weight;
+            *count += 1;
+            acc
+        })
+        .into_iter()
+        .map(|(date, (sum, count))| (date, sum / f64::from(count)))
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+struct TrendPoint {
+    date: NaiveDate,
+    weight: f64,
+    trend: f64,
+}
+
+/// Exponentially smoothed daily weights, starting from the first weigh-in.
+/// When the user skips days, the smoothing is applied once for each day
+/// that passed, so a weigh-in after a long break moves the trend further.
+fn smooth(daily: &[(NaiveDate, f64)]) -> Vec<TrendPoint> {
+    daily
+        .iter()
+        .fold(Vec::new(), |mut acc: Vec<TrendPoint>, (date, weight)| {
+            let trend = match acc.last() {
+                Some(prev) => {
+                    let gap = (*date - prev.date).num_days() as i32;
+                    let factor = 1.0 - (1.0 - SMOOTHING).powi(gap);
+                    prev.trend + factor * (weight - prev.trend)
+                }
+                None => *weight,
+            };
+            acc.push(TrendPoint {
+                date: *date,
+                weight: *weight,
+                trend,
+            });
+            acc
+        })
+}
+
+/// One day on a weight chart. Before the user's first weigh-in, there is
+/// no trend. After that, the trend carries forward through days without a
+/// weigh-in.
+#[derive(Debug, PartialEq)]
+pub struct WeightDay {
+    pub date: NaiveDate,
+    pub weight: Option<f64>,
+    pub trend: Option<f64>,
+}
+
+fn weight_days(
+    points: &[TrendPoint],
+    first_day: NaiveDate,
+    days: u64,
+) -> Vec<WeightDay> {
+    first_day
+        .iter_days()
+        .take(days as usize)
+        .map(|date| {
+            let latest = points.iter().take_while(|p| p.date <= date).last();
+            WeightDay {
+                date,
+                weight: latest.filter(|p| p.date == date).map(|p| p.weight),
+                trend: latest.map(|p| p.trend),
+            }
+        })
+        .collect()
+}
+
+async fn get_entries_before(
+    db: &PgPool,
+    user_id: i32,
+    end: DateTime<Utc>,
+    unit: WeightUnit,
+) -> Aresult<Vec<WeightEntry>> {
+    query!(
+        "select id, weight, unit, created_at
+        from weight_entry
+        where user_id = $1 and created_at < $2
+        order by created_at",
+        user_id,
+        end
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(WeightEntry {
+            id: row.id,
+            weight: WeightUnit::parse(&row.unit)?.convert(row.weight, unit),
+            created_at: row.created_at,
+        })
+    })
+    .collect()
+}
+
+/// Weight and trend for each day in `first_day..first_day + days`, in
+/// `unit`. All earlier weigh-ins are included in the trend, so that it's
+/// already settled at the start of the range.
+pub async fn get_weight_days(
+    db: &PgPool,
+    user_id: i32,
+    unit: WeightUnit,
+    timezone: Tz,
+    first_day: NaiveDate,
+    days: u64,
+) -> Aresult<Vec<WeightDay>> {
+    let end = first_day
+        .checked_add_days(Days::new(days))
+        .ok_or(Error::msg("weight range ends after the end of time"))?;
+    let entries =
+        get_entries_before(db, user_id, start_of_date(end, timezone), unit)
+            .await?;
+    let points = smooth(&daily_weights(&entries, timezone));
+    Ok(weight_days(&points, first_day, days))
+}
+
+const CHART_HEIGHT: f64 = 100.0;
+const DAY_WIDTH: f64 = 10.0;
+
+/// A line for the trend, with a short line from the trend to each day's
+/// weigh-in, so it's easy to see how far the scale strayed from the trend.
+pub struct WeightChart<'a> {
+    pub days: &'a [WeightDay],
+}
+impl Component for WeightChart<'_> {
+    fn render(&self) -> String {
+        let values = self
+            .days
+            .iter()
+            .flat_map(|day| [day.weight, day.trend])
+            .flatten();
+        let (Some(min), Some(max)) = (
+            values.clone().min_by(f64::total_cmp),
+            values.max_by(f64::total_cmp),
+        ) else {
+            return r#"<p class="text-sm">No weigh-ins yet.</p>"#.into();
+        };
+        // Pad the range so that the line doesn't hug the edges, and so that
+        // a perfectly flat trend still has some height to work with.
+        let padding = ((max - min) * 0.1).max(0.5);
+        let (low, high) = (min - padding, max + padding);
+        let y = |value: f64| CHART_HEIGHT * (high - value) / (high - low);
+        let x = |i: usize| i as f64 * DAY_WIDTH + DAY_WIDTH / 2.0;
+        let trend_points = self
+            .days
+            .iter()
+            .enumerate()
+            .filter_map(|(i, day)| {
+                Some(format!("{:.1},{:.1}", x(i), y(day.trend?)))
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        let weigh_ins = self.days.iter().enumerate().fold(
+            String::new(),
+            |mut acc, (i, day)| {
+                if let (Some(weight), Some(trend)) = (day.weight, day.trend) {
+                    let x = x(i);
+                    let y1 = y(trend);
+                    let y2 = y(weight);
+                    let date = day.date.format("%b %e");
+                    acc.push_str(&format!(
+                        r#"<line x1="{x:.1}" x2="{x:.1}" y1="{y1:.1}" y2="{y2:.1}" class="stroke-slate-400" stroke-width="2" vector-effect="non-scaling-stroke"><title>{date}: weighed {weight:.1}, trend {trend:.1}</title></line>"#
+                    ));
+                }
+                acc
+            },
+        );
+        let width = self.days.len() as f64 * DAY_WIDTH;
+        let first = self
+            .days
+            .first()
+            .map_or(String::new(), |d| d.date.format("%b %e").to_string());
+        let last = self
+            .days
+            .last()
+            .map_or(String::new(), |d| d.date.format("%b %e").to_string());
+        format!(
+            r#"
+            <div class="flex flex-col gap-1">
+                <p class="flex justify-between text-sm">
+                    <span class="font-semibold">Weight Trend</span>
+                    <span>{high:.1}</span>
+                </p>
+                <svg
+                    viewBox="0 0 {width} {CHART_HEIGHT}"
+                    preserveAspectRatio="none"
+                    class="w-full h-32 bg-slate-100 dark:bg-slate-800 rounded"
+                    role="img"
+                    aria-label="Weight trend per day"
+                >
+                    {weigh_ins}
+                    <polyline
+                        points="{trend_points}"
+                        fill="none"
+                        class="stroke-emerald-500"
+                        stroke-width="2"
+                        vector-effect="non-scaling-stroke"
+                    />
+                </svg>
+                <p class="flex justify-between text-xs">
+                    <span>{first}</span>
+                    <span>{low:.1}</span>
+                    <span>{last}</span>
+                </p>
+            </div>
+            "#
+        )
+    }
+}
+
+struct WeightPage<'a> {
+    days: &'a [WeightDay],
+    recent: &'a [&'a WeightEntry],
+    timezone: Tz,
+    unit: WeightUnit,
+}
+impl Component for WeightPage<'_> {
+    fn render(&self) -> String {
+        let home = Route::UserHome;
+        let unit = self.unit.as_str();
+        let form = WeightEntryForm {
+            saved: None,
+            unit: self.unit,
+        }
+        .render();
+        let chart = WeightChart { days: self.days }.render();
+        let current = self.days.last().and_then(|day| day.trend).map_or(
+            String::new(),
+            |trend| {
+                format!(
+                    r#"<p>Your current trend weight is {trend:.1} {unit}.</p>"#
+                )
+            },
+        );
+        let rows = self.recent.iter().fold(String::new(), |mut acc, entry| {
+            let date = entry
+                .created_at
+                .with_timezone(&self.timezone)
+                .format("%b %e, %l:%M %p");
+            let weight = entry.weight;
+            let delete = Route::DeleteWeight(Some(entry.id));
+            acc.push_str(&format!(
+                r#"
+                <tr>
+                    <td class="pr-4">{date}</td>
+                    <td class="pr-4">{weight:.1} {unit}</td>
+                    <td>
+                        <button
+                            hx-delete="{delete}"
+                            hx-target="closest tr"
+                            class="text-sm bg-red-100 hover:bg-red-200 rounded
+                            p-1 text-black"
+                        >
+                            Delete
+                        </button>
+                    </td>
+                </tr>
+                "#
+            ));
+            acc
+        });
+        format!(
+            r#"
+            <div class="flex flex-col gap-4 md:w-[40rem]">
+                <a href="{home}">
+                    <button
+                        class="dark:bg-emerald-700 dark:hover:bg-emerald-800
+                        bg-emerald-100 hover:bg-emerald-200 p-1 rounded"
+                    >
+                        Back
+                    </button>
+                </a>
+                <h1 class="text-2xl font-extrabold">Weight</h1>
+                <details class="text-sm">
+                    <summary>Learn more</summary>
+                    <p>
+                        Your weight goes up and down from day to day with
+                        water and food, so we smooth it out into a trend. The
+                        green line is your trend, and the gray lines show how
+                        far each weigh-in was from it. Weights are shown in
+                        the unit of your weight goal, which you can change on
+                        the preferences page.
+                    </p>
+                </details>
+                {form}
+                {current}
+                {chart}
+                <h2 class="text-lg font-semibold">Recent Weigh-Ins</h2>
+                <table class="self-start">
+                    {rows}
+                </table>
+            </div>
+            "#
+        )
+    }
+}
+
+pub async fn weight_page(
+    State(AppState { db, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServerError> {
+    let session = Session::from_headers_err(&headers, "weight page")?;
+    let (preferences, unit) = join![
+        session.get_preferences(&db),
+        get_weight_unit(&db, session.user_id)
+    ];
+    let preferences = preferences?;
+    let unit = unit?;
+    let timezone = preferences.timezone;
+    let today = utc_now().with_timezone(&timezone).date_naive();
+    let first_day = today
+        .checked_sub_days(Days::new(CHART_DAYS - 1))
+        .ok_or(Error::msg("weight chart starts before the dawn of time"))?;
+    let end = today
+        .checked_add_days(Days::new(1))
+        .ok_or(Error::msg("tomorrow is after the end of time"))?;
+    let entries = get_entries_before(
+        &db,
+        session.user_id,
+        start_of_date(end, timezone),
+        unit,
+    )
+    .await?;
+    let points = smooth(&daily_weights(&entries, timezone));
+    let days = weight_days(&points, first_day, CHART_DAYS);
+    let recent: Vec<&WeightEntry> =
+        entries.iter().rev().take(RECENT_ENTRIES).collect();
+
+    Ok(Page {
+        title: "Weight",
+        children: &PageContainer {
+            children: &WeightPage {
+                days: &days,
+                recent: &recent,
+                timezone,
+                unit,
+            },
+        },
+    }
+    .render())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn entry(created_at: &str, weight: f64) -> WeightEntry {
+        WeightEntry {
+            id: 1,
+            weight,
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .unwrap()
+                .to_utc(),
+        }
+    }
+
+    #[test]
+    fn test_daily_weights_average_by_local_day() {
+        let entries = [
+            entry("2024-06-27T12:00:00Z", 180.0),
+            // 8PM on the 27th in New York.
+            entry("2024-06-28T00:00:00Z", 181.0),
+            entry("2024-06-28T12:00:00Z", 179.0),
+        ];
+        assert_eq!(
+            daily_weights(&entries, Tz::America__New_York),
+            vec![(date("2024-06-27"), 180.5), (date("2024-06-28"), 179.0)]
+        );
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let points = smooth(&[
+            (date("2024-06-01"), 180.0),
+            (date("2024-06-02"), 190.0),
+            (date("2024-06-04"), 170.0),
+        ]);
+        assert_eq!(points[0].trend, 180.0);
+        assert_eq!(points[1].trend, 181.0);
+        // Two days passed, so the smoothing is applied twice; 19% of the way
+        // from 181 down to 170.
+        assert!((points[2].trend - 178.91).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trend_carries_forward() {
+        let points =
+            smooth(&[(date("2024-06-02"), 180.0), (date("2024-06-04"), 170.0)]);
+        let days = weight_days(&points, date("2024-06-01"), 5);
+        let trends: Vec<Option<f64>> = days.iter().map(|d| d.trend).collect();
+        let weights: Vec<Option<f64>> = days.iter().map(|d| d.weight).collect();
+        assert_eq!(trends[0], None);
+        assert_eq!(trends[1], Some(180.0));
+        assert_eq!(trends[2], Some(180.0));
+        assert_eq!(trends[3], trends[4]);
+        assert_eq!(weights, vec![None, Some(180.0), None, Some(170.0), None]);
+    }
+
+    #[test]
+    fn test_empty_chart() {
+        let days = weight_days(&[], date("2024-06-01"), 3);
+        assert!(WeightChart { days: &days }
+            .render()
+            .contains("No weigh-ins yet."));
+    }
+}
@@ -0,0 +1,92 @@
+//! Weights can be logged in pounds or kilograms. Each weigh-in is stored in
+//! the unit it was entered in, and converted to the user's unit when it's
+//! displayed or used in an estimate. The user's unit is the one they chose
+//! for their weight goal; see [super::tdee].
+
+use crate::prelude::*;
+
+const KILOGRAMS_PER_POUND: f64 = 0.453_592_37;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WeightUnit {
+    #[default]
+    Pounds,
+    Kilograms,
+}
+impl WeightUnit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pounds => "lbs",
+            Self::Kilograms => "kg",
+        }
+    }
+    pub fn parse(value: &str) -> Aresult<Self> {
+        match value {
+            "lbs" => Ok(Self::Pounds),
+            "kg" => Ok(Self::Kilograms),
+            other => Err(Error::msg(format!("unknown weight unit {other}"))),
+        }
+    }
+    /// Approximate energy stored in one unit of body weight.
+    pub fn calories_per_unit(self) -> f64 {
+        match self {
+            Self::Pounds => 3500.0,
+            Self::Kilograms => 7700.0,
+        }
+    }
+    /// Converts `weight`, which is in this unit, to `unit`.
+    pub fn convert(self, weight: f64, unit: Self) -> f64 {
+        match (self, unit) {
+            (Self::Pounds, Self::Kilograms) => weight * KILOGRAMS_PER_POUND,
+            (Self::Kilograms, Self::Pounds) => weight / KILOGRAMS_PER_POUND,
+            _ => weight,
+        }
+    }
+}
+
+/// `<option>` elements for choosing a unit, with `selected` picked.
+pub fn unit_options(selected: WeightUnit) -> String {
+    [WeightUnit::Pounds, WeightUnit::Kilograms]
+        .iter()
+        .map(|unit| {
+            let value = unit.as_str();
+            let selected = if *unit == selected { "selected" } else { "" };
+            format!(r#"<option {selected} value="{value}">{value}</option>"#)
+        })
+        .collect()
+}
+
+pub async fn get_weight_unit(db: &PgPool, user_id: i32) -> Aresult<WeightUnit> {
+    let row = query!(
+        "select weight_unit from weight_goal where user_id = $1",
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+    match row {
+        Some(row) => WeightUnit::parse(&row.weight_unit),
+        None => Ok(WeightUnit::default()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let lbs = WeightUnit::Pounds;
+        let kg = WeightUnit::Kilograms;
+        assert_eq!(lbs.convert(180.0, lbs), 180.0);
+        assert!((lbs.convert(220.462, kg) - 100.0).abs() < 1e-3);
+        assert!((kg.convert(100.0, lbs) - 220.462).abs() < 1e-3);
+        assert!((kg.convert(lbs.convert(150.0, kg), lbs) - 150.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unit_options() {
+        let options = unit_options(WeightUnit::Kilograms);
+        assert!(options.contains(r#"<option  value="lbs">lbs</option>"#));
+        assert!(options.contains(r#"<option selected value="kg">kg</option>"#));
+    }
+}